@@ -0,0 +1,478 @@
+// Reading of DDEX ERN (Electronic Release Notification) messages for ingest_ddex_ern.
+// Both the 3.x and 4.x layouts of NewReleaseMessage are understood; anything the canister
+// has no place for is listed in `unsupported` instead of being silently dropped.
+use crate::import::parse_media;
+use crate::validation;
+use crate::{Contributor, ContributorRole, LicenseType, Media};
+use roxmltree::Node;
+
+pub struct ErnMessage {
+    pub message_id: String,
+    pub recordings: Vec<ErnRecording>,
+    pub deals: Vec<ErnDeal>,
+    pub unsupported: Vec<String>,
+}
+
+pub struct ErnRecording {
+    pub reference: String,
+    pub isrc: Option<String>,
+    pub title: String,
+    pub artists: Vec<String>,
+    pub genre: String,
+    pub year: u32,
+    pub contributors: Vec<Contributor>,
+    pub duration_secs: Option<u32>,
+    pub explicit: Option<bool>,
+    pub language: Option<String>,
+}
+
+// A deal's terms, resolved to the sound recordings of the releases it covers
+pub struct ErnDeal {
+    pub resource_references: Vec<String>,
+    pub name: String,
+    pub license_type: LicenseType,
+    pub media: Vec<Media>,
+    pub territories: Vec<String>,
+    pub duration_days: u32,
+    pub price: u32,
+}
+
+struct ErnRelease {
+    reference: String,
+    resource_references: Vec<String>,
+}
+
+// Term used for offers created from deals that carry no end date
+const DEFAULT_DEAL_DAYS: u32 = 365;
+
+pub fn parse_ern(xml: &str) -> Result<ErnMessage, String> {
+    let doc = roxmltree::Document::parse(xml).map_err(|e| format!("invalid XML: {}", e))?;
+    let root = doc.root_element();
+    if root.tag_name().name() != "NewReleaseMessage" {
+        return Err(format!(
+            "root element {} is not an ERN NewReleaseMessage",
+            root.tag_name().name()
+        ));
+    }
+
+    let mut unsupported: Vec<String> = Vec::new();
+    let message_id = path_text(root, &["MessageHeader", "MessageId"]).unwrap_or_default();
+    let parties = party_names(root);
+
+    let mut recordings: Vec<ErnRecording> = Vec::new();
+    if let Some(resource_list) = child(root, "ResourceList") {
+        for resource in elements(resource_list) {
+            match resource.tag_name().name() {
+                "SoundRecording" => {
+                    recordings.push(parse_recording(resource, &parties, &mut unsupported))
+                }
+                other => unsupported.push(format!(
+                    "ResourceList/{} {}",
+                    other,
+                    child_text(resource, "ResourceReference").unwrap_or_default()
+                )),
+            }
+        }
+    }
+
+    let mut releases: Vec<ErnRelease> = Vec::new();
+    if let Some(release_list) = child(root, "ReleaseList") {
+        for release in elements(release_list) {
+            let reference = child_text(release, "ReleaseReference").unwrap_or_default();
+            unsupported.push(format!(
+                "ReleaseList/{} {} (release grouping)",
+                release.tag_name().name(),
+                reference
+            ));
+            releases.push(ErnRelease {
+                reference,
+                resource_references: descendant_texts(release, "ReleaseResourceReference"),
+            });
+        }
+    }
+
+    let mut deals: Vec<ErnDeal> = Vec::new();
+    if let Some(deal_list) = child(root, "DealList") {
+        for release_deal in elements(deal_list).filter(|n| n.has_tag_name("ReleaseDeal")) {
+            let release_refs = descendant_texts(release_deal, "DealReleaseReference");
+            let resource_references: Vec<String> = releases
+                .iter()
+                .filter(|release| release_refs.contains(&release.reference))
+                .flat_map(|release| release.resource_references.clone())
+                .collect();
+
+            for deal in elements(release_deal).filter(|n| n.has_tag_name("Deal")) {
+                match child(deal, "DealTerms") {
+                    Some(terms) => deals.push(parse_deal_terms(
+                        terms,
+                        resource_references.clone(),
+                        &mut unsupported,
+                    )),
+                    None => unsupported.push(format!(
+                        "DealList/ReleaseDeal/Deal for {} without DealTerms",
+                        release_refs.join(",")
+                    )),
+                }
+            }
+        }
+    }
+
+    for other in elements(root) {
+        let name = other.tag_name().name();
+        if !matches!(
+            name,
+            "MessageHeader"
+                | "UpdateIndicator"
+                | "PartyList"
+                | "ResourceList"
+                | "ReleaseList"
+                | "DealList"
+        ) {
+            unsupported.push(name.to_string());
+        }
+    }
+
+    Ok(ErnMessage {
+        message_id,
+        recordings,
+        deals,
+        unsupported,
+    })
+}
+
+fn parse_recording(
+    node: Node,
+    parties: &[(String, String)],
+    unsupported: &mut Vec<String>,
+) -> ErnRecording {
+    let reference = child_text(node, "ResourceReference").unwrap_or_default();
+
+    let isrc = path_text(node, &["SoundRecordingId", "ISRC"])
+        .or_else(|| path_text(node, &["ResourceId", "ISRC"]));
+
+    let title = path_text(node, &["ReferenceTitle", "TitleText"])
+        .or_else(|| child_text(node, "DisplayTitleText"))
+        .or_else(|| first_descendant_text(node, "TitleText"))
+        .unwrap_or_default();
+
+    // ERN 4 names artists directly or through the PartyList, ERN 3 per territory
+    let mut artists = descendant_texts(node, "DisplayArtistName");
+    let mut contributors: Vec<Contributor> = artists
+        .iter()
+        .map(|name| credit(name, ContributorRole::MainArtist))
+        .collect();
+    if artists.is_empty() {
+        for artist in node
+            .descendants()
+            .filter(|n| n.has_tag_name("DisplayArtist"))
+        {
+            if let Some(name) = party_name(artist, "ArtistPartyReference", parties) {
+                let featured = ["ArtistRole", "DisplayArtistRole"]
+                    .iter()
+                    .filter_map(|tag| child_text(artist, tag))
+                    .any(|role| role == "FeaturedArtist");
+                let role = match featured {
+                    true => ContributorRole::FeaturedArtist,
+                    false => ContributorRole::MainArtist,
+                };
+                add_credit(&mut contributors, credit(&name, role));
+                if !artists.contains(&name) {
+                    artists.push(name);
+                }
+            }
+        }
+    }
+
+    // ERN 3 separates direct and indirect (e.g. songwriter) contributors, ERN 4 has one list
+    for contributor in node.descendants().filter(|n| {
+        matches!(
+            n.tag_name().name(),
+            "ResourceContributor" | "IndirectResourceContributor" | "Contributor"
+        )
+    }) {
+        let name = match party_name(contributor, "ContributorPartyReference", parties) {
+            Some(name) => name,
+            None => {
+                unsupported.push(format!(
+                    "SoundRecording {}/{} without a name",
+                    reference,
+                    contributor.tag_name().name()
+                ));
+                continue;
+            }
+        };
+
+        for role in elements(contributor).filter(|n| {
+            matches!(
+                n.tag_name().name(),
+                "ResourceContributorRole" | "IndirectResourceContributorRole" | "Role"
+            )
+        }) {
+            let role = node_text(role).unwrap_or_default();
+            let roles: &[ContributorRole] = match role.as_str() {
+                "MainArtist" => &[ContributorRole::MainArtist],
+                "FeaturedArtist" => &[ContributorRole::FeaturedArtist],
+                "Composer" => &[ContributorRole::Composer],
+                "Lyricist" => &[ContributorRole::Lyricist],
+                "ComposerLyricist" => &[ContributorRole::Composer, ContributorRole::Lyricist],
+                "Producer" => &[ContributorRole::Producer],
+                "Arranger" => &[ContributorRole::Arranger],
+                "Remixer" => &[ContributorRole::Remixer],
+                "Performer" | "Musician" | "Vocalist" => &[ContributorRole::Performer],
+                "Engineer" | "MixingEngineer" | "RecordingEngineer" | "MasteringEngineer" => {
+                    &[ContributorRole::Engineer]
+                }
+                _ => {
+                    unsupported.push(format!(
+                        "SoundRecording {}/{} role {}",
+                        reference,
+                        contributor.tag_name().name(),
+                        role
+                    ));
+                    &[]
+                }
+            };
+            for role in roles {
+                add_credit(&mut contributors, credit(&name, *role));
+            }
+        }
+    }
+
+    let genre = first_descendant_text(node, "GenreText").unwrap_or_default();
+
+    let year = node
+        .descendants()
+        .filter(|n| n.has_tag_name("PLine"))
+        .find_map(|pline| child_text(pline, "Year"))
+        .or_else(|| first_descendant_text(node, "OriginalResourceReleaseDate"))
+        .or_else(|| first_descendant_text(node, "CreationDate"))
+        .and_then(|date| date.get(..4).and_then(|y| y.parse().ok()))
+        .unwrap_or(0);
+
+    let duration_secs = child_text(node, "Duration").and_then(|value| {
+        let secs = parse_duration(&value);
+        if secs.is_none() {
+            unsupported.push(format!("SoundRecording {} Duration {}", reference, value));
+        }
+        secs
+    });
+
+    let explicit =
+        first_descendant_text(node, "ParentalWarningType").and_then(|value| match value.as_str() {
+            "Explicit" | "ExplicitContentEdited" => Some(true),
+            "NotExplicit" => Some(false),
+            _ => None,
+        });
+
+    // Region subtags are dropped, e.g. en-US is stored as en
+    let language = first_descendant_text(node, "LanguageOfPerformance").and_then(|value| {
+        let code = value.split(['-', '_']).next().unwrap_or_default();
+        match validation::language(code) {
+            Ok(code) => Some(code),
+            Err(_) => {
+                unsupported.push(format!(
+                    "SoundRecording {} LanguageOfPerformance {}",
+                    reference, value
+                ));
+                None
+            }
+        }
+    });
+
+    ErnRecording {
+        reference,
+        isrc,
+        title,
+        artists,
+        genre,
+        year,
+        contributors,
+        duration_secs,
+        explicit,
+        language,
+    }
+}
+
+// ISO 8601 durations as used by DDEX, e.g. PT3M25S or PT0H3M25.500S; fractions of a second
+// are dropped
+fn parse_duration(value: &str) -> Option<u32> {
+    let mut rest = value.trim().strip_prefix("PT")?;
+    let mut secs: f64 = 0.0;
+    for (unit, scale) in [('H', 3600.0), ('M', 60.0), ('S', 1.0)] {
+        if let Some((number, tail)) = rest.split_once(unit) {
+            secs += number.parse::<f64>().ok()? * scale;
+            rest = tail;
+        }
+    }
+
+    match rest.is_empty() && secs >= 1.0 {
+        true => Some(secs as u32),
+        false => None,
+    }
+}
+
+fn credit(name: &str, role: ContributorRole) -> Contributor {
+    Contributor {
+        name: name.to_string(),
+        role,
+        owner_id: None,
+    }
+}
+
+// ERN 3 repeats credits in every territory block, so each name and role is kept once
+fn add_credit(contributors: &mut Vec<Contributor>, contributor: Contributor) {
+    if !contributors
+        .iter()
+        .any(|c| c.name == contributor.name && c.role == contributor.role)
+    {
+        contributors.push(contributor);
+    }
+}
+
+// A party's name, given inline (ERN 3) or through a reference into the PartyList (ERN 4)
+fn party_name(node: Node, reference_tag: &str, parties: &[(String, String)]) -> Option<String> {
+    path_text(node, &["PartyName", "FullName"]).or_else(|| {
+        child_text(node, reference_tag).and_then(|reference| {
+            parties
+                .iter()
+                .find(|(party, _)| *party == reference)
+                .map(|(_, name)| name.clone())
+        })
+    })
+}
+
+fn parse_deal_terms(
+    terms: Node,
+    resource_references: Vec<String>,
+    unsupported: &mut Vec<String>,
+) -> ErnDeal {
+    let models = descendant_texts(terms, "CommercialModelType");
+    let use_types = descendant_texts(terms, "UseType");
+
+    let mut media: Vec<Media> = Vec::new();
+    let mut license_type = LicenseType::Performance;
+    for use_type in &use_types {
+        match use_type.as_str() {
+            "Stream" | "OnDemandStream" | "NonInteractiveStream" | "Webcast" => {
+                if !media.contains(&Media::Streaming) {
+                    media.push(Media::Streaming)
+                }
+            }
+            "Download" | "PermanentDownload" | "ConditionalDownload" => {
+                license_type = LicenseType::Mechanical
+            }
+            other => match parse_media(other) {
+                Ok(m) if !media.contains(&m) => media.push(m),
+                Ok(_) => (),
+                Err(_) => unsupported.push(format!("DealTerms/UseType {}", other)),
+            },
+        }
+    }
+
+    let territories: Vec<String> = descendant_texts(terms, "TerritoryCode")
+        .into_iter()
+        .filter(|code| code != "Worldwide")
+        .collect();
+    if terms
+        .descendants()
+        .any(|n| n.has_tag_name("ExcludedTerritoryCode"))
+    {
+        unsupported.push("DealTerms/ExcludedTerritoryCode".to_string());
+    }
+
+    let start = path_text(terms, &["ValidityPeriod", "StartDate"])
+        .or_else(|| path_text(terms, &["ValidityPeriod", "StartDateTime"]));
+    let end = path_text(terms, &["ValidityPeriod", "EndDate"])
+        .or_else(|| path_text(terms, &["ValidityPeriod", "EndDateTime"]));
+    let duration_days = match (start, end) {
+        (Some(start), Some(end)) => {
+            match (
+                start.get(..10).and_then(crate::parse_date),
+                end.get(..10).and_then(crate::parse_date),
+            ) {
+                (Some(start), Some(end)) if end >= start => (end - start + 1) as u32,
+                _ => DEFAULT_DEAL_DAYS,
+            }
+        }
+        _ => DEFAULT_DEAL_DAYS,
+    };
+
+    let price = first_descendant_text(terms, "WholesalePricePerUnit")
+        .and_then(|price| price.parse::<f64>().ok())
+        .map(|price| price.round().max(0.0) as u32)
+        .unwrap_or(0);
+
+    let mut name_parts = models;
+    name_parts.extend(use_types);
+
+    ErnDeal {
+        resource_references,
+        name: format!("DDEX {}", name_parts.join(" ")),
+        license_type,
+        media,
+        territories,
+        duration_days,
+        price,
+    }
+}
+
+// Map PartyReference to the party's full name (ERN 4)
+fn party_names(root: Node) -> Vec<(String, String)> {
+    match child(root, "PartyList") {
+        Some(list) => elements(list)
+            .filter_map(|party| {
+                Some((
+                    child_text(party, "PartyReference")?,
+                    path_text(party, &["PartyName", "FullName"])?,
+                ))
+            })
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+fn elements<'a, 'input>(node: Node<'a, 'input>) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(|n| n.is_element())
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn child_text(node: Node, name: &str) -> Option<String> {
+    child(node, name).and_then(node_text)
+}
+
+fn path_text(node: Node, path: &[&str]) -> Option<String> {
+    let mut current = node;
+    for name in path {
+        current = child(current, name)?;
+    }
+    node_text(current)
+}
+
+fn first_descendant_text(node: Node, name: &str) -> Option<String> {
+    node.descendants()
+        .filter(|n| n.has_tag_name(name))
+        .find_map(node_text)
+}
+
+fn descendant_texts(node: Node, name: &str) -> Vec<String> {
+    let mut texts: Vec<String> = Vec::new();
+    for text in node
+        .descendants()
+        .filter(|n| n.has_tag_name(name))
+        .filter_map(node_text)
+    {
+        if !texts.contains(&text) {
+            texts.push(text);
+        }
+    }
+    texts
+}
+
+fn node_text(node: Node) -> Option<String> {
+    node.text()
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
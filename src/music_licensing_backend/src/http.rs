@@ -0,0 +1,150 @@
+// Request and response types of the HTTP gateway interface (http_request), and helpers for
+// building and certifying responses
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ic_certified_map::{labeled, labeled_hash, AsHashTree, Hash, RbTree};
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+
+#[derive(candid::CandidType, Clone, Deserialize)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: ByteBuf,
+}
+
+#[derive(candid::CandidType, Clone, Serialize)]
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: ByteBuf,
+}
+
+impl HttpRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Path segments of the URL, without the query string
+    pub fn segments(&self) -> Vec<&str> {
+        let path = self.url.split(['?', '#']).next().unwrap_or_default();
+        path.split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect()
+    }
+}
+
+impl HttpResponse {
+    pub fn new(status_code: u16, content_type: &str, body: Vec<u8>) -> Self {
+        HttpResponse {
+            status_code,
+            headers: vec![
+                ("Content-Type".to_string(), content_type.to_string()),
+                ("Content-Length".to_string(), body.len().to_string()),
+            ],
+            body: ByteBuf::from(body),
+        }
+    }
+
+    // JSON body, readable from any origin
+    pub fn json<T: Serialize>(status_code: u16, value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => HttpResponse::new(status_code, "application/json", body)
+                .with_header("Access-Control-Allow-Origin", "*"),
+            Err(e) => HttpResponse::text(500, &format!("response could not be encoded: {}", e)),
+        }
+    }
+
+    pub fn text(status_code: u16, message: &str) -> Self {
+        HttpResponse::new(
+            status_code,
+            "text/plain; charset=utf-8",
+            message.as_bytes().to_vec(),
+        )
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+// Resolve a single `bytes=` range against a body of `size` bytes into inclusive offsets.
+// Returns Ok(None) when the whole body should be sent and Err(()) when the range cannot be
+// satisfied; malformed headers and multiple ranges fall back to the whole body.
+pub fn parse_range(header: Option<&str>, size: u64) -> Result<Option<(u64, u64)>, ()> {
+    let spec = match header.and_then(|value| value.trim().strip_prefix("bytes=")) {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return Ok(None),
+    };
+    let (first, last) = match spec.split_once('-') {
+        Some((first, last)) => (first.trim(), last.trim()),
+        None => return Ok(None),
+    };
+    let number = |value: &str| value.parse::<u64>().ok();
+    let last_byte = size.saturating_sub(1);
+
+    let (start, end) = match (first, last) {
+        ("", suffix) => match number(suffix) {
+            Some(0) => return Err(()),
+            Some(length) => (size.saturating_sub(length), last_byte),
+            None => return Ok(None),
+        },
+        (start, "") => match number(start) {
+            Some(start) => (start, last_byte),
+            None => return Ok(None),
+        },
+        (start, end) => match (number(start), number(end)) {
+            (Some(start), Some(end)) if start <= end => (start, end.min(last_byte)),
+            _ => return Ok(None),
+        },
+    };
+
+    match size > 0 && start < size {
+        true => Ok(Some((start, end))),
+        false => Err(()),
+    }
+}
+
+// Publish the root hash of certified responses, following the v1 `http_assets` scheme
+pub fn set_certified_tree(tree: &RbTree<String, Hash>) {
+    crate::runtime::set_certified_data(&labeled_hash(b"http_assets", &tree.root_hash()));
+}
+
+// IC-Certificate header proving the body served at `path`; None outside of query calls
+pub fn certificate_header(tree: &RbTree<String, Hash>, path: &str) -> Option<(String, String)> {
+    let certificate = crate::runtime::data_certificate()?;
+    let witness = labeled(b"http_assets", tree.witness(path.as_bytes()));
+
+    let mut serializer = serde_cbor::ser::Serializer::new(Vec::new());
+    serializer.self_describe().ok()?;
+    witness.serialize(&mut serializer).ok()?;
+
+    Some((
+        "IC-Certificate".to_string(),
+        format!(
+            "certificate=:{}:, tree=:{}:",
+            BASE64.encode(certificate),
+            BASE64.encode(serializer.into_inner())
+        ),
+    ))
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,103 @@
+// Types of the ICRC-7 non-fungible token standard, used to hold licenses as tokens
+use candid::{Nat, Principal};
+use serde_bytes::ByteBuf;
+
+pub const SYMBOL: &str = "MLIC";
+pub const NAME: &str = "Music Licenses";
+pub const DESCRIPTION: &str = "Active music licenses, one token per license";
+pub const MAX_QUERY_BATCH_SIZE: usize = 100;
+pub const MAX_UPDATE_BATCH_SIZE: usize = 20;
+pub const DEFAULT_TAKE_VALUE: usize = 100;
+pub const MAX_TAKE_VALUE: usize = 500;
+pub const MAX_MEMO_SIZE: usize = 32;
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<ByteBuf>,
+}
+
+impl Account {
+    // The default subaccount may be given as null or as 32 zero bytes. Check is_valid first:
+    // a subaccount of any other length reads as the default one here.
+    pub fn subaccount(&self) -> [u8; 32] {
+        let mut subaccount = [0u8; 32];
+        if let Some(bytes) = &self.subaccount {
+            if bytes.len() == 32 {
+                subaccount.copy_from_slice(bytes);
+            }
+        }
+        subaccount
+    }
+
+    // A malformed account is never the same as any other
+    pub fn same_as(&self, other: &Account) -> bool {
+        self.is_valid()
+            && other.is_valid()
+            && self.owner == other.owner
+            && self.subaccount() == other.subaccount()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.subaccount
+            .as_ref()
+            .is_none_or(|bytes| bytes.len() == 32)
+    }
+}
+
+// ICRC-3 value, used for metadata
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub enum Value {
+    Nat(Nat),
+    Int(candid::Int),
+    Text(String),
+    Blob(ByteBuf),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct TransferArg {
+    pub from_subaccount: Option<ByteBuf>,
+    pub to: Account,
+    pub token_id: Nat,
+    pub memo: Option<ByteBuf>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub enum TransferResult {
+    Ok(Nat),
+    Err(TransferError),
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub enum TransferError {
+    NonExistingTokenId,
+    InvalidRecipient,
+    Unauthorized,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    GenericError { error_code: Nat, message: String },
+    GenericBatchError { error_code: Nat, message: String },
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct SupportedStandard {
+    pub name: String,
+    pub url: String,
+}
+
+// Token ids are license ids; ids that do not fit are treated as unknown tokens
+pub fn token_id(id: &Nat) -> Option<u64> {
+    u64::try_from(&id.0).ok()
+}
+
+pub fn text(value: &str) -> Value {
+    Value::Text(value.to_string())
+}
+
+pub fn nat(value: u64) -> Value {
+    Value::Nat(Nat::from(value))
+}
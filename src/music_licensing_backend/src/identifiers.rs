@@ -0,0 +1,89 @@
+// Normalization and validation of industry identifiers (ISRC, ISWC, UPC/EAN)
+
+// ISRC: CC-XXX-YY-NNNNN (country, registrant, year, designation), stored as 12 characters
+pub fn normalize_isrc(value: &str) -> Result<String, String> {
+    let isrc: String = value
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' '))
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    let bytes = isrc.as_bytes();
+
+    let valid = bytes.len() == 12
+        && bytes[..2].iter().all(|b| b.is_ascii_uppercase())
+        && bytes[2..5].iter().all(|b| b.is_ascii_alphanumeric())
+        && bytes[5..].iter().all(|b| b.is_ascii_digit());
+
+    match valid {
+        true => Ok(isrc),
+        false => Err(format!(
+            "isrc:{} is invalid, expected CC-XXX-YY-NNNNN",
+            value
+        )),
+    }
+}
+
+// ISWC: T-DDD.DDD.DDD-C with a mod 10 check digit, stored in that display form
+pub fn normalize_iswc(value: &str) -> Result<String, String> {
+    let compact: String = value
+        .chars()
+        .filter(|c| !matches!(c, '-' | '.' | ' '))
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    let invalid = || format!("iswc:{} is invalid, expected T-DDD.DDD.DDD-C", value);
+
+    let digits: Vec<u32> = match compact.strip_prefix('T') {
+        Some(rest) if rest.len() == 10 => rest
+            .chars()
+            .map(|c| c.to_digit(10))
+            .collect::<Option<Vec<u32>>>()
+            .ok_or_else(invalid)?,
+        _ => return Err(invalid()),
+    };
+
+    let sum: u32 = 1 + digits[..9]
+        .iter()
+        .enumerate()
+        .map(|(i, d)| d * (i as u32 + 1))
+        .sum::<u32>();
+    if (10 - sum % 10) % 10 != digits[9] {
+        return Err(format!("iswc:{} has an invalid check digit", value));
+    }
+
+    Ok(format!(
+        "T-{}.{}.{}-{}",
+        &compact[1..4],
+        &compact[4..7],
+        &compact[7..10],
+        &compact[10..]
+    ))
+}
+
+// UPC-A (12 digits) or EAN-13 (13 digits) with a GTIN check digit
+pub fn normalize_upc(value: &str) -> Result<String, String> {
+    let upc: String = value.chars().filter(|c| !matches!(c, '-' | ' ')).collect();
+    let digits: Vec<u32> = match upc.chars().map(|c| c.to_digit(10)).collect() {
+        Some(digits) => digits,
+        None => return Err(format!("upc:{} must contain only digits", value)),
+    };
+
+    if digits.len() != 12 && digits.len() != 13 {
+        return Err(format!(
+            "upc:{} must have 12 (UPC) or 13 (EAN) digits",
+            value
+        ));
+    }
+
+    let (body, check) = digits.split_at(digits.len() - 1);
+    let sum: u32 = body
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 0 { d * 3 } else { *d })
+        .sum();
+    if (10 - sum % 10) % 10 != check[0] {
+        return Err(format!("upc:{} has an invalid check digit", value));
+    }
+
+    Ok(upc)
+}
@@ -0,0 +1,449 @@
+// Parsing of catalog batches for bulk_import_songs
+use crate::{
+    Contributor, ContributorRole, Instrument, KeyMode, LicenseType, Media, Mood, MusicalKey,
+    PitchClass, SongPayload, Tempo, UsageRights,
+};
+
+// Largest number of rows accepted in one bulk import call
+pub const MAX_IMPORT_ROWS: usize = 500;
+
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize)]
+pub enum ImportFormat {
+    Csv,
+    Json,
+}
+
+// One catalog row; list columns hold values separated by ';' in CSV and arrays in JSON
+#[derive(Clone, Deserialize, Default)]
+#[serde(default)]
+pub struct ImportRow {
+    title: String,
+    artist: String,
+    year: u32,
+    genre: String,
+    price: u32,
+    license_types: Vec<String>,
+    media: Vec<String>,
+    territories: Vec<String>,
+    max_copies: Option<u64>,
+    max_streams: Option<u64>,
+    attribution: Option<String>,
+    exclusive_allowed: bool,
+    isrc: Option<String>,
+    iswc: Option<String>,
+    contributors: Vec<String>,
+    tags: Vec<String>,
+    moods: Vec<String>,
+    tempo: Option<String>,
+    instruments: Vec<String>,
+    duration_secs: Option<u32>,
+    bpm: Option<u32>,
+    key: Option<String>,
+    instrumental: Option<bool>,
+    explicit: Option<bool>,
+    language: Option<String>,
+}
+
+impl ImportRow {
+    pub fn into_payload(self, owner_id: u64, auth_key: &str) -> Result<SongPayload, String> {
+        if self.title.trim().is_empty() {
+            return Err("title is required".to_string());
+        }
+        if self.artist.trim().is_empty() {
+            return Err("artist is required".to_string());
+        }
+
+        let license_types = self
+            .license_types
+            .iter()
+            .map(|value| parse_license_type(value))
+            .collect::<Result<Vec<LicenseType>, String>>()?;
+        let media = self
+            .media
+            .iter()
+            .map(|value| parse_media(value))
+            .collect::<Result<Vec<Media>, String>>()?;
+        let contributors = self
+            .contributors
+            .iter()
+            .map(|value| parse_contributor(value))
+            .collect::<Result<Vec<Contributor>, String>>()?;
+        let moods = self
+            .moods
+            .iter()
+            .map(|value| parse_mood(value))
+            .collect::<Result<Vec<Mood>, String>>()?;
+        let tempo = match self.tempo.filter(|tempo| !tempo.is_empty()) {
+            Some(value) => Some(parse_tempo(&value)?),
+            None => None,
+        };
+        let instruments = self
+            .instruments
+            .iter()
+            .map(|value| parse_instrument(value))
+            .collect::<Result<Vec<Instrument>, String>>()?;
+        let key = match self.key.filter(|key| !key.is_empty()) {
+            Some(value) => Some(parse_key(&value)?),
+            None => None,
+        };
+
+        Ok(SongPayload {
+            auth_key: auth_key.to_string(),
+            title: self.title,
+            artist: self.artist,
+            owner_id,
+            year: self.year,
+            genre: self.genre,
+            price: self.price,
+            rights: UsageRights {
+                license_types,
+                media,
+                territories: self.territories,
+                max_copies: self.max_copies,
+                max_streams: self.max_streams,
+                attribution: self.attribution.filter(|a| !a.is_empty()),
+                exclusive_allowed: self.exclusive_allowed,
+            },
+            isrc: self.isrc.filter(|isrc| !isrc.is_empty()),
+            iswc: self.iswc.filter(|iswc| !iswc.is_empty()),
+            contributors,
+            tags: self.tags,
+            moods,
+            tempo,
+            instruments,
+            duration_secs: self.duration_secs,
+            bpm: self.bpm,
+            key,
+            instrumental: self.instrumental,
+            explicit: self.explicit,
+            language: self.language.filter(|language| !language.is_empty()),
+        })
+    }
+}
+
+pub fn parse_license_type(value: &str) -> Result<LicenseType, String> {
+    match value.trim().to_lowercase().as_str() {
+        "sync" => Ok(LicenseType::Sync),
+        "mechanical" => Ok(LicenseType::Mechanical),
+        "performance" => Ok(LicenseType::Performance),
+        "sample" => Ok(LicenseType::Sample),
+        other => Err(format!("license type:{} is not supported", other)),
+    }
+}
+
+pub fn parse_media(value: &str) -> Result<Media, String> {
+    match value.trim().to_lowercase().as_str() {
+        "film" => Ok(Media::Film),
+        "tv" => Ok(Media::Tv),
+        "advertising" | "ads" => Ok(Media::Advertising),
+        "games" => Ok(Media::Games),
+        "streaming" => Ok(Media::Streaming),
+        other => Err(format!("media:{} is not supported", other)),
+    }
+}
+
+pub fn parse_contributor_role(value: &str) -> Result<ContributorRole, String> {
+    match enum_key(value).as_str() {
+        "mainartist" | "artist" => Ok(ContributorRole::MainArtist),
+        "featuredartist" | "featured" => Ok(ContributorRole::FeaturedArtist),
+        "composer" => Ok(ContributorRole::Composer),
+        "lyricist" => Ok(ContributorRole::Lyricist),
+        "producer" => Ok(ContributorRole::Producer),
+        "arranger" => Ok(ContributorRole::Arranger),
+        "remixer" => Ok(ContributorRole::Remixer),
+        "performer" => Ok(ContributorRole::Performer),
+        "engineer" => Ok(ContributorRole::Engineer),
+        other => Err(format!("contributor role:{} is not supported", other)),
+    }
+}
+
+// Enum values are matched ignoring case, spaces, hyphens and underscores
+fn enum_key(value: &str) -> String {
+    value.trim().to_lowercase().replace([' ', '_', '-'], "")
+}
+
+pub fn parse_mood(value: &str) -> Result<Mood, String> {
+    match enum_key(value).as_str() {
+        "happy" => Ok(Mood::Happy),
+        "sad" => Ok(Mood::Sad),
+        "calm" => Ok(Mood::Calm),
+        "energetic" => Ok(Mood::Energetic),
+        "uplifting" => Ok(Mood::Uplifting),
+        "melancholic" => Ok(Mood::Melancholic),
+        "romantic" => Ok(Mood::Romantic),
+        "dark" => Ok(Mood::Dark),
+        "epic" => Ok(Mood::Epic),
+        "aggressive" => Ok(Mood::Aggressive),
+        "playful" => Ok(Mood::Playful),
+        "tense" => Ok(Mood::Tense),
+        other => Err(format!("mood:{} is not supported", other)),
+    }
+}
+
+pub fn parse_tempo(value: &str) -> Result<Tempo, String> {
+    match enum_key(value).as_str() {
+        "veryslow" => Ok(Tempo::VerySlow),
+        "slow" => Ok(Tempo::Slow),
+        "medium" => Ok(Tempo::Medium),
+        "fast" => Ok(Tempo::Fast),
+        "veryfast" => Ok(Tempo::VeryFast),
+        other => Err(format!("tempo:{} is not supported", other)),
+    }
+}
+
+pub fn parse_instrument(value: &str) -> Result<Instrument, String> {
+    match enum_key(value).as_str() {
+        "acousticguitar" => Ok(Instrument::AcousticGuitar),
+        "electricguitar" => Ok(Instrument::ElectricGuitar),
+        "bass" => Ok(Instrument::Bass),
+        "drums" => Ok(Instrument::Drums),
+        "percussion" => Ok(Instrument::Percussion),
+        "piano" => Ok(Instrument::Piano),
+        "keys" => Ok(Instrument::Keys),
+        "synth" => Ok(Instrument::Synth),
+        "strings" => Ok(Instrument::Strings),
+        "brass" => Ok(Instrument::Brass),
+        "woodwinds" => Ok(Instrument::Woodwinds),
+        "orchestra" => Ok(Instrument::Orchestra),
+        "choir" => Ok(Instrument::Choir),
+        other => Err(format!("instrument:{} is not supported", other)),
+    }
+}
+
+// Keys are written as a tonic with an optional sharp or flat followed by the mode, e.g.
+// "A minor", "F# major", "Bbm" or "C"; a bare tonic is major
+pub fn parse_key(value: &str) -> Result<MusicalKey, String> {
+    let invalid = || format!("key:{} is not supported", value.trim());
+    let mut chars = value.trim().chars().peekable();
+    let natural: u8 = match chars.next().map(|c| c.to_ascii_uppercase()) {
+        Some('C') => 0,
+        Some('D') => 2,
+        Some('E') => 4,
+        Some('F') => 5,
+        Some('G') => 7,
+        Some('A') => 9,
+        Some('B') => 11,
+        _ => return Err(invalid()),
+    };
+    let semitone = match chars.peek() {
+        Some('#') | Some('♯') => {
+            chars.next();
+            (natural + 1) % 12
+        }
+        Some('b') | Some('♭') => {
+            chars.next();
+            (natural + 11) % 12
+        }
+        _ => natural,
+    };
+    let mode = match enum_key(&chars.collect::<String>()).as_str() {
+        "" | "maj" | "major" => KeyMode::Major,
+        "m" | "min" | "minor" => KeyMode::Minor,
+        _ => return Err(invalid()),
+    };
+
+    let tonic = [
+        PitchClass::C,
+        PitchClass::CSharp,
+        PitchClass::D,
+        PitchClass::DSharp,
+        PitchClass::E,
+        PitchClass::F,
+        PitchClass::FSharp,
+        PitchClass::G,
+        PitchClass::GSharp,
+        PitchClass::A,
+        PitchClass::ASharp,
+        PitchClass::B,
+    ][semitone as usize];
+    Ok(MusicalKey { tonic, mode })
+}
+
+// Contributors are written as role:name, e.g. composer:Jane Doe
+fn parse_contributor(value: &str) -> Result<Contributor, String> {
+    match value.split_once(':') {
+        Some((role, name)) => Ok(Contributor {
+            name: name.trim().to_string(),
+            role: parse_contributor_role(role)?,
+            owner_id: None,
+        }),
+        None => Err(format!(
+            "contributor:{} is invalid, expected role:name",
+            value
+        )),
+    }
+}
+
+// Split a batch into rows; a row that cannot be read is reported as an error for that row
+pub fn parse_rows(
+    format: ImportFormat,
+    data: &str,
+) -> Result<Vec<Result<ImportRow, String>>, String> {
+    let rows = match format {
+        ImportFormat::Csv => parse_csv_rows(data)?,
+        ImportFormat::Json => parse_json_rows(data)?,
+    };
+
+    match rows.len() {
+        0 => Err("batch contains no rows".to_string()),
+        n if n > MAX_IMPORT_ROWS => Err(format!(
+            "batch contains {} rows, at most {} are allowed per call",
+            n, MAX_IMPORT_ROWS
+        )),
+        _ => Ok(rows),
+    }
+}
+
+fn parse_json_rows(data: &str) -> Result<Vec<Result<ImportRow, String>>, String> {
+    let values: Vec<serde_json::Value> =
+        serde_json::from_str(data).map_err(|e| format!("batch is not a JSON array: {}", e))?;
+
+    Ok(values
+        .into_iter()
+        .map(|value| serde_json::from_value::<ImportRow>(value).map_err(|e| e.to_string()))
+        .collect())
+}
+
+fn parse_csv_rows(data: &str) -> Result<Vec<Result<ImportRow, String>>, String> {
+    let mut records = parse_csv(data)?.into_iter();
+    let header: Vec<String> = match records.next() {
+        Some(header) => header.iter().map(|h| h.trim().to_lowercase()).collect(),
+        None => return Ok(Vec::new()),
+    };
+
+    for required in ["title", "artist", "year", "genre", "price"] {
+        if !header.iter().any(|h| h == required) {
+            return Err(format!("CSV header is missing the {} column", required));
+        }
+    }
+
+    Ok(records
+        .map(|record| csv_record_to_row(&header, &record))
+        .collect())
+}
+
+fn csv_record_to_row(header: &[String], record: &[String]) -> Result<ImportRow, String> {
+    if record.len() != header.len() {
+        return Err(format!(
+            "row has {} fields, expected {}",
+            record.len(),
+            header.len()
+        ));
+    }
+
+    let mut row = ImportRow::default();
+    for (column, value) in header.iter().zip(record) {
+        let value = value.trim();
+        match column.as_str() {
+            "title" => row.title = value.to_string(),
+            "artist" => row.artist = value.to_string(),
+            "year" => row.year = parse_number(column, value)?,
+            "genre" => row.genre = value.to_string(),
+            "price" => row.price = parse_number(column, value)?,
+            "license_types" => row.license_types = split_list(value),
+            "media" => row.media = split_list(value),
+            "territories" => row.territories = split_list(value),
+            "max_copies" if !value.is_empty() => {
+                row.max_copies = Some(parse_number(column, value)?)
+            }
+            "max_streams" if !value.is_empty() => {
+                row.max_streams = Some(parse_number(column, value)?)
+            }
+            "attribution" => row.attribution = Some(value.to_string()),
+            "isrc" => row.isrc = Some(value.to_string()),
+            "iswc" => row.iswc = Some(value.to_string()),
+            "contributors" => row.contributors = split_list(value),
+            "tags" => row.tags = split_list(value),
+            "moods" => row.moods = split_list(value),
+            "tempo" => row.tempo = Some(value.to_string()),
+            "instruments" => row.instruments = split_list(value),
+            "duration_secs" if !value.is_empty() => {
+                row.duration_secs = Some(parse_number(column, value)?)
+            }
+            "bpm" if !value.is_empty() => row.bpm = Some(parse_number(column, value)?),
+            "key" => row.key = Some(value.to_string()),
+            "instrumental" => row.instrumental = parse_flag(column, value)?,
+            "explicit" => row.explicit = parse_flag(column, value)?,
+            "language" => row.language = Some(value.to_string()),
+            "exclusive_allowed" => {
+                row.exclusive_allowed =
+                    matches!(value.to_lowercase().as_str(), "true" | "yes" | "1")
+            }
+            _ => (),
+        }
+    }
+
+    Ok(row)
+}
+
+// Optional yes/no columns; an empty field leaves the value unknown
+fn parse_flag(column: &str, value: &str) -> Result<Option<bool>, String> {
+    match value.to_lowercase().as_str() {
+        "" => Ok(None),
+        "true" | "yes" | "1" => Ok(Some(true)),
+        "false" | "no" | "0" => Ok(Some(false)),
+        _ => Err(format!("{}:{} is not a valid yes/no value", column, value)),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(column: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{}:{} is not a valid number", column, value))
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(';')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+// Minimal RFC 4180 reader: quoted fields may contain commas, doubled quotes and line breaks
+fn parse_csv(data: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records: Vec<Vec<String>> = Vec::new();
+    let mut record: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = data.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' if field.is_empty() => in_quotes = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' => (),
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                if !(record.len() == 1 && record[0].is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                }
+                record.clear();
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if in_quotes {
+        return Err("CSV ends inside a quoted field".to_string());
+    }
+
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    Ok(records)
+}
@@ -0,0 +1,105 @@
+// Calls to an ICRC-2 token ledger, which licensees pay licenses with
+use crate::icrc7::Account;
+use candid::{Nat, Principal};
+use serde_bytes::ByteBuf;
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct TransferArg {
+    from_subaccount: Option<ByteBuf>,
+    to: Account,
+    amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<ByteBuf>,
+    created_at_time: Option<u64>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct TransferFromArgs {
+    spender_subaccount: Option<ByteBuf>,
+    from: Account,
+    to: Account,
+    amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<ByteBuf>,
+    created_at_time: Option<u64>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Debug)]
+enum TransferError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Debug)]
+enum TransferFromError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+fn canister_account() -> Account {
+    Account {
+        owner: ic_cdk::id(),
+        subaccount: None,
+    }
+}
+
+pub async fn fee(ledger: Principal) -> Result<u64, String> {
+    let (fee,): (Nat,) = ic_cdk::call(ledger, "icrc1_fee", ())
+        .await
+        .map_err(|(code, msg)| format!("icrc1_fee failed ({:?}): {}", code, msg))?;
+    u64::try_from(fee.0).map_err(|_| "ledger fee does not fit in 64 bits".to_string())
+}
+
+// Move an amount the account approved the canister to spend into the canister's account
+pub async fn transfer_from(ledger: Principal, from: Account, amount: u64) -> Result<Nat, String> {
+    let args = TransferFromArgs {
+        spender_subaccount: None,
+        from,
+        to: canister_account(),
+        amount: Nat::from(amount),
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    };
+
+    let (result,): (Result<Nat, TransferFromError>,) =
+        ic_cdk::call(ledger, "icrc2_transfer_from", (args,))
+            .await
+            .map_err(|(code, msg)| format!("icrc2_transfer_from failed ({:?}): {}", code, msg))?;
+    result.map_err(|e| format!("icrc2_transfer_from was rejected: {:?}", e))
+}
+
+// Send an amount from the canister's account; the ledger fee is paid out of the amount
+pub async fn transfer(
+    ledger: Principal,
+    to: Account,
+    amount: u64,
+    fee: u64,
+) -> Result<Nat, String> {
+    let args = TransferArg {
+        from_subaccount: None,
+        to,
+        amount: Nat::from(amount - fee),
+        fee: Some(Nat::from(fee)),
+        memo: None,
+        created_at_time: None,
+    };
+
+    let (result,): (Result<Nat, TransferError>,) = ic_cdk::call(ledger, "icrc1_transfer", (args,))
+        .await
+        .map_err(|(code, msg)| format!("icrc1_transfer failed ({:?}): {}", code, msg))?;
+    result.map_err(|e| format!("icrc1_transfer was rejected: {:?}", e))
+}
@@ -0,0 +1,233 @@
+// Records written by earlier versions of the canister. Candid only fills in missing opt
+// fields, so every field added to a stored struct after its first release is opt here and
+// gets its default when an old record is read.
+
+use super::{
+    AutoApprovalRules, Contributor, CustodyTransfer, Instrument, License, LicenseType, Licensee,
+    Media, Memory, Mood, MusicalKey, Song, SongTransfer, Tempo, UsageRights,
+};
+use candid::{Decode, Principal};
+use ic_stable_structures::{BoundedStorable, Memory as _, StableBTreeMap, Storable};
+use std::borrow::Cow;
+
+#[derive(candid::CandidType, Deserialize)]
+struct StoredSong {
+    id: u64,
+    title: String,
+    artist: String,
+    owner_id: u64,
+    year: u32,
+    genre: String,
+    price: u32,
+    rights: Option<UsageRights>,
+    auto_approval: Option<AutoApprovalRules>,
+    pending_transfer: Option<SongTransfer>,
+    isrc: Option<String>,
+    iswc: Option<String>,
+    contributors: Option<Vec<Contributor>>,
+    genre_id: Option<u64>,
+    tags: Option<Vec<String>>,
+    moods: Option<Vec<Mood>>,
+    tempo: Option<Tempo>,
+    instruments: Option<Vec<Instrument>>,
+    duration_secs: Option<u32>,
+    bpm: Option<u32>,
+    key: Option<MusicalKey>,
+    instrumental: Option<bool>,
+    explicit: Option<bool>,
+    language: Option<String>,
+}
+
+#[derive(candid::CandidType, Deserialize)]
+struct StoredLicense {
+    id: u64,
+    song_id: u64,
+    owner_id: u64,
+    licensee_id: u64,
+    approved: bool,
+    price: u32,
+    start_date: String,
+    end_date: String,
+    exclusive: Option<bool>,
+    media: Option<Vec<Media>>,
+    territories: Option<Vec<String>>,
+    license_type: Option<LicenseType>,
+    max_copies: Option<u64>,
+    max_streams: Option<u64>,
+    attribution: Option<String>,
+    offer_id: Option<u64>,
+    previous_license_id: Option<u64>,
+    next_license_id: Option<u64>,
+    auto_renew: Option<bool>,
+    parent_license_id: Option<u64>,
+    pending_transfer_to: Option<u64>,
+    custody: Option<Vec<StoredCustodyTransfer>>,
+    revoked: Option<bool>,
+    release_id: Option<u64>,
+    bundle_id: Option<u64>,
+    transferable: Option<bool>,
+    counter_price: Option<u32>,
+}
+
+// Licenses kept their chain of custody inline before it moved to its own map
+#[derive(candid::CandidType, Deserialize)]
+struct StoredCustodyTransfer {
+    from_licensee_id: u64,
+    to_licensee_id: u64,
+    timestamp: u64,
+}
+
+// Licensees created before auth keys existed get an empty key, which never authenticates
+#[derive(candid::CandidType, Deserialize)]
+struct StoredLicensee {
+    id: u64,
+    name: String,
+    email: String,
+    auth_key: Option<String>,
+    licenses: Vec<u64>,
+    principal: Option<Principal>,
+}
+
+pub fn song(bytes: &[u8]) -> Song {
+    let song = Decode!(bytes, StoredSong).unwrap();
+    Song {
+        id: song.id,
+        title: song.title,
+        artist: song.artist,
+        owner_id: song.owner_id,
+        year: song.year,
+        genre: song.genre,
+        price: song.price,
+        rights: song.rights.unwrap_or_default(),
+        auto_approval: song.auto_approval,
+        pending_transfer: song.pending_transfer,
+        isrc: song.isrc,
+        iswc: song.iswc,
+        contributors: song.contributors.unwrap_or_default(),
+        genre_id: song.genre_id,
+        tags: song.tags.unwrap_or_default(),
+        moods: song.moods.unwrap_or_default(),
+        tempo: song.tempo,
+        instruments: song.instruments.unwrap_or_default(),
+        duration_secs: song.duration_secs,
+        bpm: song.bpm,
+        key: song.key,
+        instrumental: song.instrumental,
+        explicit: song.explicit,
+        language: song.language,
+    }
+}
+
+pub fn license(bytes: &[u8]) -> License {
+    let license = Decode!(bytes, StoredLicense).unwrap();
+    License {
+        id: license.id,
+        song_id: license.song_id,
+        owner_id: license.owner_id,
+        licensee_id: license.licensee_id,
+        approved: license.approved,
+        price: license.price,
+        start_date: license.start_date,
+        end_date: license.end_date,
+        exclusive: license.exclusive.unwrap_or_default(),
+        media: license.media.unwrap_or_default(),
+        territories: license.territories.unwrap_or_default(),
+        license_type: license.license_type.unwrap_or_default(),
+        max_copies: license.max_copies,
+        max_streams: license.max_streams,
+        attribution: license.attribution,
+        offer_id: license.offer_id,
+        previous_license_id: license.previous_license_id,
+        next_license_id: license.next_license_id,
+        auto_renew: license.auto_renew.unwrap_or_default(),
+        parent_license_id: license.parent_license_id,
+        pending_transfer_to: license.pending_transfer_to,
+        revoked: license.revoked.unwrap_or_default(),
+        release_id: license.release_id,
+        bundle_id: license.bundle_id,
+        transferable: license.transferable.unwrap_or_default(),
+        counter_price: license.counter_price,
+    }
+}
+
+pub fn license_custody(bytes: &[u8]) -> Vec<CustodyTransfer> {
+    let license = Decode!(bytes, StoredLicense).unwrap();
+    license
+        .custody
+        .unwrap_or_default()
+        .into_iter()
+        .enumerate()
+        .map(|(seq, transfer)| CustodyTransfer {
+            license_id: license.id,
+            seq: seq as u64,
+            from_licensee_id: transfer.from_licensee_id,
+            to_licensee_id: transfer.to_licensee_id,
+            timestamp: transfer.timestamp,
+        })
+        .collect()
+}
+
+pub fn licensee(bytes: &[u8]) -> Licensee {
+    let licensee = Decode!(bytes, StoredLicensee).unwrap();
+    Licensee {
+        id: licensee.id,
+        name: licensee.name,
+        email: licensee.email,
+        auth_key: licensee.auth_key.unwrap_or_default(),
+        licenses: licensee.licenses,
+        principal: licensee.principal,
+    }
+}
+
+// A stored value read as raw bytes. A B-tree can only be loaded with the value size it was
+// created with, so songs and licenses, whose records outgrew their first maps, are copied
+// from maps of each size they ever had.
+pub struct RawRecord<const N: u32>(pub Vec<u8>);
+
+impl<const N: u32> Storable for RawRecord<N> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(&self.0)
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        RawRecord(bytes.into_owned())
+    }
+}
+
+impl<const N: u32> BoundedStorable for RawRecord<N> {
+    const MAX_SIZE: u32 = N;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+const BTREE_MAGIC: &[u8; 3] = b"BTR";
+
+// Value size in the header of a B-tree, None when the memory holds no B-tree
+fn max_value_size(memory: &Memory) -> Option<u32> {
+    if memory.size() == 0 {
+        return None;
+    }
+
+    let mut header = [0; 12];
+    memory.read(0, &mut header);
+    match &header[..3] == BTREE_MAGIC {
+        true => Some(u32::from_le_bytes(header[8..12].try_into().unwrap())),
+        false => None,
+    }
+}
+
+fn drain<const N: u32>(memory: Memory) -> Vec<(u64, Vec<u8>)> {
+    let map: StableBTreeMap<u64, RawRecord<N>, Memory> = StableBTreeMap::load(memory.clone());
+    let records = map.iter().map(|(id, record)| (id, record.0)).collect();
+    StableBTreeMap::<u64, RawRecord<N>, Memory>::new(memory);
+    records
+}
+
+// Take every record out of a map in a memory the canister no longer stores it in
+pub fn drain_records(memory: Memory) -> Vec<(u64, Vec<u8>)> {
+    match max_value_size(&memory) {
+        Some(1024) => drain::<1024>(memory),
+        Some(2048) => drain::<2048>(memory),
+        Some(4096) => drain::<4096>(memory),
+        Some(size) => panic!("stored records of {} bytes cannot be migrated", size),
+        None => Vec::new(),
+    }
+}
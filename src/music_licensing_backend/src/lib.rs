@@ -3,11 +3,41 @@ extern crate serde;
 use candid::{Decode, Encode};
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::{BoundedStorable, Cell, DefaultMemoryImpl, StableBTreeMap, Storable};
-use std::{borrow::Cow, cell::RefCell};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+};
+
+mod ddex;
+mod http;
+mod icrc7;
+mod identifiers;
+mod import;
+mod ledger;
+mod legacy;
+mod runtime;
+mod validation;
+
+#[cfg(test)]
+mod tests;
+
+use candid::{Nat, Principal};
+use http::{HttpRequest, HttpResponse};
+use ic_cdk::api::management_canister::ecdsa::{
+    ecdsa_public_key, sign_with_ecdsa, EcdsaCurve, EcdsaKeyId, EcdsaPublicKeyArgument,
+    SignWithEcdsaArgument,
+};
+use ic_certified_map::{Hash, RbTree};
+use import::ImportFormat;
 
 // Define type aliases for convenience
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 type IdCell = Cell<u64, Memory>;
+// ((MessageParty as u8, owner or licensee id), id of a record kept for that party)
+type PartyKey = ((u8, u64), u64);
 
 // Define the data structures that will be stored in the stable memory
 #[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
@@ -19,6 +49,159 @@ struct Song {
     year: u32,
     genre: String,
     price: u32,
+    rights: UsageRights,
+    auto_approval: Option<AutoApprovalRules>,
+    pending_transfer: Option<SongTransfer>,
+    isrc: Option<String>,
+    iswc: Option<String>,
+    contributors: Vec<Contributor>,
+    genre_id: Option<u64>,
+    tags: Vec<String>,
+    moods: Vec<Mood>,
+    tempo: Option<Tempo>,
+    instruments: Vec<Instrument>,
+    duration_secs: Option<u32>,
+    bpm: Option<u32>,
+    key: Option<MusicalKey>,
+    instrumental: Option<bool>,
+    explicit: Option<bool>,
+    language: Option<String>,
+}
+
+// A person credited on a song, optionally linked to their owner account
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct Contributor {
+    name: String,
+    role: ContributorRole,
+    owner_id: Option<u64>,
+}
+
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+enum ContributorRole {
+    #[default]
+    MainArtist,
+    FeaturedArtist,
+    Composer,
+    Lyricist,
+    Producer,
+    Arranger,
+    Remixer,
+    Performer,
+    Engineer,
+}
+
+// Node of the genre taxonomy managed by controllers. A song's genre names one of them; the
+// aliases are other spellings that resolve to it.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct Genre {
+    id: u64,
+    name: String,
+    parent_id: Option<u64>,
+    aliases: Vec<String>,
+}
+
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+enum Mood {
+    Happy,
+    Sad,
+    Calm,
+    Energetic,
+    Uplifting,
+    Melancholic,
+    Romantic,
+    Dark,
+    Epic,
+    Aggressive,
+    Playful,
+    Tense,
+}
+
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+enum Tempo {
+    VerySlow,
+    Slow,
+    Medium,
+    Fast,
+    VeryFast,
+}
+
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+enum Instrument {
+    AcousticGuitar,
+    ElectricGuitar,
+    Bass,
+    Drums,
+    Percussion,
+    Piano,
+    Keys,
+    Synth,
+    Strings,
+    Brass,
+    Woodwinds,
+    Orchestra,
+    Choir,
+}
+
+// Musical key of a song, e.g. A minor. Flats are stored as the equal sharp (Bb as ASharp).
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+struct MusicalKey {
+    tonic: PitchClass,
+    mode: KeyMode,
+}
+
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+enum PitchClass {
+    C,
+    CSharp,
+    D,
+    DSharp,
+    E,
+    F,
+    FSharp,
+    G,
+    GSharp,
+    A,
+    ASharp,
+    B,
+}
+
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+enum KeyMode {
+    Major,
+    Minor,
+}
+
+// Songs matching all of the given criteria; a genre includes its subgenres and the duration
+// and BPM bounds are inclusive
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct SongFilter {
+    genre_id: Option<u64>,
+    tags: Vec<String>,
+    moods: Vec<Mood>,
+    tempo: Option<Tempo>,
+    instruments: Vec<Instrument>,
+    min_duration_secs: Option<u32>,
+    max_duration_secs: Option<u32>,
+    min_bpm: Option<u32>,
+    max_bpm: Option<u32>,
+    key: Option<MusicalKey>,
+    instrumental: Option<bool>,
+    explicit: Option<bool>,
+    language: Option<String>,
+}
+
+// An ownership transfer offered by the current owner and waiting for the new owner
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct SongTransfer {
+    new_owner_id: u64,
+    pending_requests: PendingRequestPolicy,
+}
+
+// What happens to license requests still waiting for approval when a song changes owner
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+enum PendingRequestPolicy {
+    Transfer,
+    Cancel,
 }
 
 #[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
@@ -27,7 +210,10 @@ struct Owner {
     name: String,
     email: String,
     auth_key: String,
+    // Songs of the owner from before OWNER_SONG_INDEX existed; the list only shrinks now, so
+    // owners with any number of songs fit Owner::MAX_SIZE. See owner_song_ids.
     song_ids: Vec<u64>,
+    // Licenses from before OWNER_LICENSE_INDEX existed; the list only shrinks now.
     license_ids: Vec<u64>,
 }
 
@@ -41,204 +227,5394 @@ struct License {
     price: u32,
     start_date: String,
     end_date: String,
+    exclusive: bool,
+    media: Vec<Media>,
+    territories: Vec<String>,
+    license_type: LicenseType,
+    max_copies: Option<u64>,
+    max_streams: Option<u64>,
+    attribution: Option<String>,
+    offer_id: Option<u64>,
+    previous_license_id: Option<u64>,
+    next_license_id: Option<u64>,
+    auto_renew: bool,
+    parent_license_id: Option<u64>,
+    pending_transfer_to: Option<u64>,
+    revoked: bool,
+    release_id: Option<u64>,
+    bundle_id: Option<u64>,
+    transferable: bool,
+    // Price the owner asked for instead of the requested one, until the licensee accepts it
+    counter_price: Option<u32>,
+}
+
+// A change of licensee recorded on a license, timestamped in nanoseconds. Each license numbers
+// its changes from 0.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct CustodyTransfer {
+    license_id: u64,
+    seq: u64,
+    from_licensee_id: u64,
+    to_licensee_id: u64,
+    timestamp: u64,
+}
+
+// Media a license may be used in; an empty list on a license means all media
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+enum Media {
+    Film,
+    Tv,
+    Advertising,
+    Games,
+    Streaming,
 }
 
+#[derive(
+    candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default, Debug,
+)]
+enum LicenseType {
+    #[default]
+    Sync,
+    Mechanical,
+    Performance,
+    Sample,
+}
+
+// Usage rights an owner offers for a song; empty lists and missing limits mean unrestricted
 #[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
-struct Licensee {
-    id: u64,
-    name: String,
-    email: String,
-    licenses: Vec<u64>,
+struct UsageRights {
+    license_types: Vec<LicenseType>,
+    media: Vec<Media>,
+    territories: Vec<String>,
+    max_copies: Option<u64>,
+    max_streams: Option<u64>,
+    attribution: Option<String>,
+    exclusive_allowed: bool,
 }
 
-// Define return types for calls
-#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
-struct ReturnOwner {
+// Rules under which a license request is approved without waiting for the owner.
+// An empty licensee list allows any licensee; a missing min_price means the song's list price.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct AutoApprovalRules {
+    licensee_ids: Vec<u64>,
+    min_price: Option<u32>,
+    allow_exclusive: bool,
+    max_duration_days: Option<u32>,
+}
+
+// A priced set of pre-set license terms published by an owner for a song
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct LicenseOffer {
     id: u64,
+    song_id: u64,
+    owner_id: u64,
     name: String,
-    email: String,
+    price: u32,
+    license_type: LicenseType,
+    media: Vec<Media>,
+    territories: Vec<String>,
+    exclusive: bool,
+    duration_days: u32,
+    max_copies: Option<u64>,
+    max_streams: Option<u64>,
+    instant: bool,
+    active: bool,
 }
 
-// Implement the 'Storable' trait for each of the data structures
-impl Storable for Song {
-    // Conversion to bytes
-    fn to_bytes(&self) -> Cow<[u8]> {
-        Cow::Owned(Encode!(self).unwrap())
-    }
-    // Conversion from bytes
-    fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
-    }
+// A release (single, EP, album) grouping an owner's songs in track order
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct Release {
+    id: u64,
+    owner_id: u64,
+    title: String,
+    release_type: ReleaseType,
+    release_date: String,
+    upc: Option<String>,
+    track_ids: Vec<u64>,
+    bundle_price: u32,
 }
 
-impl Storable for Owner {
-    fn to_bytes(&self) -> Cow<[u8]> {
-        Cow::Owned(Encode!(self).unwrap())
-    }
-    fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
-    }
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+enum ReleaseType {
+    #[default]
+    Single,
+    Ep,
+    Album,
+    Compilation,
 }
 
-impl Storable for License {
-    fn to_bytes(&self) -> Cow<[u8]> {
-        Cow::Owned(Encode!(self).unwrap())
-    }
-    fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
-    }
+// Kinds of files stored for a song (licensed master, public preview clip, cover image) or a
+// release (cover image)
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+enum AssetKind {
+    #[default]
+    Master,
+    Preview,
+    Cover,
 }
 
-impl Storable for Licensee {
-    fn to_bytes(&self) -> Cow<[u8]> {
-        Cow::Owned(Encode!(self).unwrap())
-    }
-    fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
-    }
+// A completely uploaded file of a song or release, stored in stable memory as chunks keyed by
+// the asset id
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct Asset {
+    id: u64,
+    // Song or release id, named song_id on the wire and in stored records since files
+    // were first only uploaded for songs
+    #[serde(rename = "song_id")]
+    entity_id: u64,
+    kind: AssetKind,
+    file_name: String,
+    content_type: String,
+    size: u64,
+    sha256: String,
+    chunk_size: u32,
+    chunk_count: u32,
+    uploaded_at: u64,
 }
 
-// Implement the 'BoundedStorable' trait for each of the data structures
-impl BoundedStorable for Song {
-    const MAX_SIZE: u32 = 1024;
-    const IS_FIXED_SIZE: bool = false;
+// An upload in progress; chunks may arrive in any order and be resent until it is finished
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct UploadSession {
+    id: u64,
+    #[serde(rename = "song_id")]
+    entity_id: u64,
+    owner_id: u64,
+    kind: AssetKind,
+    file_name: String,
+    content_type: String,
+    size: u64,
+    sha256: String,
+    chunk_size: u32,
+    received: Vec<bool>,
+    created_at: u64,
 }
 
-impl BoundedStorable for Owner {
-    const MAX_SIZE: u32 = 1024;
-    const IS_FIXED_SIZE: bool = false;
-}
+// Raw bytes of one chunk of an asset
+struct AssetChunk(Vec<u8>);
 
-impl BoundedStorable for License {
-    const MAX_SIZE: u32 = 1024;
-    const IS_FIXED_SIZE: bool = false;
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct Licensee {
+    id: u64,
+    name: String,
+    email: String,
+    auth_key: String,
+    // Licenses from before LICENSEE_LICENSE_INDEX existed; only shrinks. See
+    // licensee_license_ids.
+    licenses: Vec<u64>,
+    principal: Option<Principal>,
 }
 
-impl BoundedStorable for Licensee {
-    const MAX_SIZE: u32 = 1024;
-    const IS_FIXED_SIZE: bool = false;
+// ICRC-7 token representing a license; the token id is the license id and the token is
+// held by the principal of the license's licensee
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct LicenseToken {
+    license_id: u64,
+    minted_at: u64,
 }
 
-// Define thread-local static variables for memory management and storage
-thread_local! {
-    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
-        MemoryManager::init(DefaultMemoryImpl::default())
-    );
-
-    static ID_COUNTER: RefCell<IdCell> = RefCell::new(
-        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0))), 0)
-            .expect("Cannot create a counter")
-    );
+// A message in the thread of a license between its licensee and the owner of its song,
+// sent at a time in nanoseconds
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct LicenseMessage {
+    id: u64,
+    license_id: u64,
+    sender: MessageParty,
+    sender_id: u64,
+    body: String,
+    sent_at: u64,
+}
 
-    static SONG_STORAGE: RefCell<StableBTreeMap<u64, Song, Memory>> =
-        RefCell::new(StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
-    ));
+// An owner or licensee account, e.g. the recipient of a message or notification
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+enum MessageParty {
+    Owner,
+    Licensee,
+}
 
-    static OWNER_STORAGE: RefCell<StableBTreeMap<u64, Owner, Memory>> =
-        RefCell::new(StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2)))
-    ));
+// Event on a license an owner or licensee is notified of
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+enum NotificationKind {
+    // A licensee requested a license on one of the owner's songs
+    NewRequest,
+    // The owner answered the licensee's request with another price
+    CounterOffer,
+    Approved,
+    Revoked,
+    // An active license ends within EXPIRY_NOTICE_DAYS and has not been renewed
+    ExpiringSoon,
+    // A licensee's balance paid for one of the owner's licenses
+    PaymentReceived,
+}
 
-    static LICENSE_STORAGE: RefCell<StableBTreeMap<u64, License, Memory>> =
-        RefCell::new(StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)))
-    ));
+// Entry of a party's notification feed, created at a time in nanoseconds
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct Notification {
+    id: u64,
+    kind: NotificationKind,
+    license_id: u64,
+    song_id: u64,
+    message: String,
+    created_at: u64,
+    read: bool,
+}
 
-    static LICENSEE_STORAGE: RefCell<StableBTreeMap<u64, Licensee, Memory>> =
-        RefCell::new(StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4)))
-    ));
+// Kinds of notification a party has turned off; all kinds are on by default
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct NotificationPreferences {
+    disabled: Vec<NotificationKind>,
 }
 
-// Define structs for payload data (used in update calls)
+// Messages of a license thread the party has not read yet
 #[derive(candid::CandidType, Clone, Serialize, Deserialize)]
-struct SongPayload {
-    title: String,
-    artist: String,
-    owner_id: u64,
-    year: u32,
-    genre: String,
-    price: u32,
+struct UnreadCount {
+    license_id: u64,
+    unread: u32,
 }
 
+// Define return types for calls
 #[derive(candid::CandidType, Clone, Serialize, Deserialize)]
-struct OwnerPayload {
+struct ReturnOwner {
+    id: u64,
     name: String,
     email: String,
-    auth_key: String,
 }
 
+// Public statement of a license, served as a certified document. It only holds stored state,
+// so whether the license is in effect on a given day follows from its term dates.
 #[derive(candid::CandidType, Clone, Serialize, Deserialize)]
-struct LicensePayload {
+struct LicenseCertificate {
+    license_id: u64,
+    approved: bool,
+    revoked: bool,
     song_id: u64,
+    song_title: String,
+    artist: String,
+    isrc: Option<String>,
+    owner_id: u64,
+    owner_name: String,
     licensee_id: u64,
+    licensee_name: String,
+    license_type: LicenseType,
+    exclusive: bool,
+    media: Vec<Media>,
+    territories: Vec<String>,
+    max_copies: Option<u64>,
+    max_streams: Option<u64>,
+    attribution: Option<String>,
     start_date: String,
     end_date: String,
+    parent_license_id: Option<u64>,
+    previous_license_id: Option<u64>,
+    next_license_id: Option<u64>,
+}
+
+// A license certificate signed with the canister's threshold ECDSA key, verifiable offline:
+// `signature` (r || s, hex) is over the SHA-256 of `document`, the certificate JSON
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct LicenseSignature {
+    license_id: u64,
+    document: String,
+    sha256: String,
+    signature: String,
+    algorithm: String,
+    key_name: String,
+    public_key: String,
+    signed_at: u64,
 }
 
+// Threshold ECDSA key used for signing, with its public key once fetched
 #[derive(candid::CandidType, Clone, Serialize, Deserialize)]
-struct LicenseePayload {
-    name: String,
-    email: String,
+struct SigningConfig {
+    key_name: String,
+    public_key: Option<String>,
+}
+
+impl Default for SigningConfig {
+    fn default() -> Self {
+        SigningConfig {
+            key_name: "key_1".to_string(),
+            public_key: None,
+        }
+    }
+}
+
+// ICRC-2 ledger licensees pay with; no ledger means payments are off
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct PaymentConfig {
+    ledger: Option<Principal>,
 }
 
 #[derive(candid::CandidType, Clone, Serialize, Deserialize)]
-struct ProtectedPayload {
-    auth_key: String,
-    license_id: u64,
+struct SigningKey {
+    key_name: String,
+    algorithm: String,
+    public_key: String,
+}
+
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+enum LicenseStatus {
+    Active,
+    Pending,
+    NotYetValid,
+    Expired,
+    Revoked,
 }
 
 #[derive(candid::CandidType, Clone, Serialize, Deserialize)]
-struct Approvepayload {
-    auth_key: String,
-    license_id: u64,
-    cost: u32,
+struct LicenseVerification {
+    valid: bool,
+    status: LicenseStatus,
+    reason: Option<String>,
+    certificate: LicenseCertificate,
 }
 
+// What anyone may see of an owner: no email or auth key
 #[derive(candid::CandidType, Clone, Serialize, Deserialize)]
-struct UpdateSongPayload {
-    auth_key: String,
+struct OwnerProfile {
     id: u64,
-    title: String,
-    artist: String,
-    year: u32,
-    genre: String,
-    price: u32,
+    name: String,
+    song_ids: Vec<u64>,
+    release_ids: Vec<u64>,
 }
 
-// Define query functions to get all licensable songs
-#[ic_cdk::query]
-fn get_all_songs() -> Result<Vec<Song>, Error> {
-    let songs_vec: Vec<(u64, Song)> = SONG_STORAGE.with(|s| s.borrow().iter().collect());
-    let songs: Vec<Song> = songs_vec.into_iter().map(|(_, song)| song).collect();
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct ImportRowResult {
+    row: u32,
+    song_id: Option<u64>,
+    error: Option<String>,
+}
 
-    match songs.len() {
-        0 => Err(Error::NotFound {
-            msg: format!("no songs licensable could be found"),
-        }),
-        _ => Ok(songs),
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct ImportReport {
+    committed: bool,
+    imported: u32,
+    failed: u32,
+    rows: Vec<ImportRowResult>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct DdexSongResult {
+    resource_reference: String,
+    song_id: Option<u64>,
+    error: Option<String>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct DdexReport {
+    committed: bool,
+    message_id: String,
+    songs: Vec<DdexSongResult>,
+    offer_ids: Vec<u64>,
+    unsupported: Vec<String>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct ReturnLicensee {
+    id: u64,
+    name: String,
+    email: String,
+    licenses: Vec<u64>,
+    principal: Option<Principal>,
+}
+
+// Implement the 'Storable' trait for each of the data structures
+impl Storable for Song {
+    // Conversion to bytes
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    // Conversion from bytes, also reading songs stored by earlier versions
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap_or_else(|_| legacy::song(&bytes))
     }
 }
 
-// Define query functions to get songs by id
-#[ic_cdk::query]
-fn get_song(id: u64) -> Result<Song, Error> {
-    match _get_song(&id) {
-        Some(song) => Ok(song),
-        None => Err(Error::NotFound {
-            msg: format!("song id:{} could not be found", id),
-        }),
+impl Storable for Owner {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
     }
 }
 
-fn _get_song(id: &u64) -> Option<Song> {
-    SONG_STORAGE.with(|s| s.borrow().get(id))
+impl Storable for License {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap_or_else(|_| legacy::license(&bytes))
+    }
+}
+
+impl Storable for LicenseOffer {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Storable for Genre {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Storable for Release {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Storable for Asset {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Storable for UploadSession {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Storable for AssetChunk {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(&self.0)
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        AssetChunk(bytes.into_owned())
+    }
+}
+
+impl Storable for LicenseSignature {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Storable for CustodyTransfer {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Storable for LicenseToken {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Storable for LicenseMessage {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Storable for Notification {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Storable for NotificationPreferences {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Storable for SigningConfig {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Storable for PaymentConfig {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Storable for Licensee {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap_or_else(|_| legacy::licensee(&bytes))
+    }
+}
+
+// Implement the 'BoundedStorable' trait for each of the data structures
+impl BoundedStorable for Song {
+    const MAX_SIZE: u32 = 4096;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl BoundedStorable for Owner {
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl BoundedStorable for License {
+    const MAX_SIZE: u32 = 4096;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl BoundedStorable for Licensee {
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl BoundedStorable for LicenseOffer {
+    const MAX_SIZE: u32 = 2048;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl BoundedStorable for Release {
+    const MAX_SIZE: u32 = 4096;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl BoundedStorable for Genre {
+    const MAX_SIZE: u32 = 2048;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl BoundedStorable for Asset {
+    const MAX_SIZE: u32 = 2048;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl BoundedStorable for UploadSession {
+    const MAX_SIZE: u32 = 2048;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl BoundedStorable for LicenseSignature {
+    const MAX_SIZE: u32 = 8192;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl BoundedStorable for CustodyTransfer {
+    const MAX_SIZE: u32 = 128;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl BoundedStorable for LicenseToken {
+    const MAX_SIZE: u32 = 128;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Message bodies are limited to validation::MAX_MESSAGE_LENGTH characters of up to 4 bytes
+impl BoundedStorable for LicenseMessage {
+    const MAX_SIZE: u32 = 16384;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl BoundedStorable for Notification {
+    const MAX_SIZE: u32 = 2048;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl BoundedStorable for NotificationPreferences {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl BoundedStorable for AssetChunk {
+    const MAX_SIZE: u32 = MAX_CHUNK_SIZE;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Size of every chunk but the last one of an upload
+const MAX_CHUNK_SIZE: u32 = 512 * 1024;
+
+// Largest file accepted for an asset
+const MAX_ASSET_SIZE: u64 = 100 * 1024 * 1024;
+
+// Previews and covers are kept small enough to be served whole in a single HTTP response
+const MAX_PREVIEW_SIZE: u64 = 1024 * 1024;
+const MAX_COVER_SIZE: u64 = 1024 * 1024;
+
+// Cover image formats, recognised by their leading bytes
+const COVER_TYPES: [(&str, &[u8]); 3] = [
+    ("image/png", b"\x89PNG\r\n\x1a\n"),
+    ("image/jpeg", b"\xff\xd8\xff"),
+    ("image/webp", b"RIFF"),
+];
+
+// Browsers may reuse a cover for a day; a new upload changes its ETag
+const COVER_CACHE_CONTROL: &str = "public, max-age=86400";
+
+// How often tokens of expired licenses are burned and licenses ending soon are announced
+const TOKEN_SWEEP_INTERVAL_SECS: u64 = 60 * 60;
+
+// How often queued license certificates are signed, and at most how many per run. Every
+// signature is a paid threshold ECDSA call, so a license that changes several times between
+// runs is signed once.
+const SIGNING_INTERVAL_SECS: u64 = 5 * 60;
+const SIGNING_BATCH_SIZE: usize = 20;
+
+// Licenses re-indexed and recertified per message after an upgrade. Each one renders and hashes
+// its two certificate bodies, so a batch stays far below the instruction limit of a message.
+const RECERTIFY_BATCH_SIZE: usize = 500;
+
+// Parties are notified this many days before a license ends
+const EXPIRY_NOTICE_DAYS: i64 = 14;
+
+// Longest notification feed kept per party; older notifications are dropped
+const MAX_NOTIFICATIONS: usize = 500;
+
+const DEFAULT_NOTIFICATION_TAKE: usize = 50;
+const MAX_NOTIFICATION_TAKE: usize = 200;
+
+// Each owner and licensee may send this many license messages per window, across all of
+// their threads
+const MESSAGE_RATE_LIMIT: u32 = 30;
+const MESSAGE_RATE_WINDOW_SECS: u64 = 10 * 60;
+
+// Most licensees an auto-approval rule may be limited to
+const MAX_AUTO_APPROVAL_LICENSEES: usize = 50;
+
+// Page sizes of list queries that take prev/take arguments
+const DEFAULT_PAGE_TAKE: usize = 50;
+const MAX_PAGE_TAKE: usize = 200;
+
+// Uploads left unfinished for longer than this are discarded
+const UPLOAD_SESSION_TTL_NS: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+// Songs and licenses were first stored in these memories with smaller records, see
+// migrate_stored_records
+const LEGACY_SONG_MEMORY: MemoryId = MemoryId::new(1);
+const LEGACY_LICENSE_MEMORY: MemoryId = MemoryId::new(3);
+
+// Define thread-local static variables for memory management and storage
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
+        MemoryManager::init(DefaultMemoryImpl::default())
+    );
+
+    static ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0))), 0)
+            .expect("Cannot create a counter")
+    );
+
+    static SONG_STORAGE: RefCell<StableBTreeMap<u64, Song, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(21)))
+    ));
+
+    static OWNER_STORAGE: RefCell<StableBTreeMap<u64, Owner, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2)))
+    ));
+
+    static LICENSE_STORAGE: RefCell<StableBTreeMap<u64, License, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(22)))
+    ));
+
+    static LICENSEE_STORAGE: RefCell<StableBTreeMap<u64, Licensee, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4)))
+    ));
+
+    static OFFER_STORAGE: RefCell<StableBTreeMap<u64, LicenseOffer, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5)))
+    ));
+
+    // Unique index from a song's ISRC to its id
+    static ISRC_INDEX: RefCell<StableBTreeMap<[u8; 12], u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6)))
+    ));
+
+    static RELEASE_STORAGE: RefCell<StableBTreeMap<u64, Release, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7)))
+    ));
+
+    static ASSET_STORAGE: RefCell<StableBTreeMap<(u64, u8), Asset, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(8)))
+    ));
+
+    static UPLOAD_STORAGE: RefCell<StableBTreeMap<u64, UploadSession, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9)))
+    ));
+
+    static CHUNK_STORAGE: RefCell<StableBTreeMap<(u64, u32), AssetChunk, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10)))
+    ));
+
+    static SIGNATURE_STORAGE: RefCell<StableBTreeMap<u64, LicenseSignature, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11)))
+    ));
+
+    static SIGNING_CONFIG: RefCell<Cell<SigningConfig, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12))),
+            SigningConfig::default(),
+        )
+        .expect("Cannot create the signing config")
+    );
+
+    static TOKEN_STORAGE: RefCell<StableBTreeMap<u64, LicenseToken, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13)))
+    ));
+
+    static GENRE_STORAGE: RefCell<StableBTreeMap<u64, Genre, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14)))
+    ));
+
+    // Catalog filter index: (SHA-256 of a term such as `tag:lofi`, song id). Looking up a term
+    // is a range scan over its hash.
+    static CATALOG_INDEX: RefCell<StableBTreeMap<([u8; 32], u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(15)))
+    ));
+
+    // License threads, keyed by (license id, message id) so a thread is a range scan
+    static MESSAGE_STORAGE: RefCell<StableBTreeMap<(u64, u64), LicenseMessage, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(16)))
+    ));
+
+    // Unread messages by party and license id; threads without unread messages have no entry
+    static UNREAD_COUNTS: RefCell<StableBTreeMap<PartyKey, u32, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(17)))
+    ));
+
+    // Notification feeds by party and notification id
+    static NOTIFICATION_STORAGE: RefCell<StableBTreeMap<PartyKey, Notification, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(18)))
+    ));
+
+    static NOTIFICATION_PREFERENCES: RefCell<StableBTreeMap<(u8, u64), NotificationPreferences, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(19)))
+    ));
+
+    // Index of licenses by (end date in days since 1970-01-01, license id), so licenses ending
+    // soon are a range scan
+    static LICENSE_END_INDEX: RefCell<StableBTreeMap<(u64, u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(27)))
+    ));
+
+    // Songs of each owner, keyed by (owner id, song id)
+    static OWNER_SONG_INDEX: RefCell<StableBTreeMap<(u64, u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(28)))
+    ));
+
+    // Licenses of each owner, keyed by (owner id, license id)
+    static OWNER_LICENSE_INDEX: RefCell<StableBTreeMap<(u64, u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(29)))
+    ));
+
+    // Licenses of each licensee, keyed by (licensee id, license id)
+    static LICENSEE_LICENSE_INDEX: RefCell<StableBTreeMap<(u64, u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(30)))
+    ));
+
+    // Licenses whose parties have been told that they end soon
+    static EXPIRY_NOTICES: RefCell<StableBTreeMap<u64, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(20)))
+    ));
+
+    static PAYMENT_CONFIG: RefCell<Cell<PaymentConfig, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(23))),
+            PaymentConfig::default(),
+        )
+        .expect("Cannot create the payment config")
+    );
+
+    // Funds held for owners and licensees, in the ledger's base units
+    static BALANCES: RefCell<StableBTreeMap<(u8, u64), u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(24)))
+    ));
+
+    // Chain of custody of each license, keyed by license and sequence number
+    static CUSTODY_STORAGE: RefCell<StableBTreeMap<(u64, u64), CustodyTransfer, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(25)))
+    ));
+
+    // Hashes of the certified HTTP responses (cover images and license certificates) as
+    // `http_assets`. The tree lives on the heap and is rebuilt from storage after an upgrade.
+    static CERTIFIED_TREE: RefCell<RbTree<String, Hash>> = const { RefCell::new(RbTree::new()) };
+
+    // Approved licenses whose current certificate has not been signed yet
+    static SIGNING_QUEUE: RefCell<StableBTreeMap<u64, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(26)))
+    ));
+
+    // Set while a signing run waits on its calls, so runs never overlap
+    static SIGNING_RUN_ACTIVE: RefCell<bool> = const { RefCell::new(false) };
+
+    // Start (ns) and number of messages of the current rate window of each sender, by
+    // (MessageParty as u8, owner or licensee id). Windows live on the heap and restart after an
+    // upgrade.
+    static MESSAGE_RATE: RefCell<BTreeMap<(u8, u64), (u64, u32)>> =
+        const { RefCell::new(BTreeMap::new()) };
+}
+
+// Define structs for payload data (used in update calls)
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct SongPayload {
+    auth_key: String,
+    title: String,
+    artist: String,
+    owner_id: u64,
+    year: u32,
+    genre: String,
+    price: u32,
+    rights: UsageRights,
+    isrc: Option<String>,
+    iswc: Option<String>,
+    contributors: Vec<Contributor>,
+    tags: Vec<String>,
+    moods: Vec<Mood>,
+    tempo: Option<Tempo>,
+    instruments: Vec<Instrument>,
+    duration_secs: Option<u32>,
+    bpm: Option<u32>,
+    key: Option<MusicalKey>,
+    instrumental: Option<bool>,
+    explicit: Option<bool>,
+    language: Option<String>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct BulkImportPayload {
+    auth_key: String,
+    owner_id: u64,
+    format: ImportFormat,
+    data: String,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct DdexPayload {
+    auth_key: String,
+    owner_id: u64,
+    xml: String,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct GenrePayload {
+    name: String,
+    parent_id: Option<u64>,
+    aliases: Vec<String>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct OwnerPayload {
+    name: String,
+    email: String,
+    auth_key: String,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct LicensePayload {
+    auth_key: String,
+    song_id: u64,
+    licensee_id: u64,
+    start_date: String,
+    end_date: String,
+    exclusive: bool,
+    media: Vec<Media>,
+    territories: Vec<String>,
+    license_type: LicenseType,
+    max_copies: Option<u64>,
+    max_streams: Option<u64>,
+    price: u32,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct LicenseePayload {
+    name: String,
+    email: String,
+    auth_key: String,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct ProtectedPayload {
+    auth_key: String,
+    license_id: u64,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct Approvepayload {
+    auth_key: String,
+    license_id: u64,
+    cost: u32,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct CounterOfferPayload {
+    auth_key: String,
+    license_id: u64,
+    price: u32,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct RenewalPayload {
+    auth_key: String,
+    license_id: u64,
+    end_date: Option<String>,
+    price: Option<u32>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct ExtendPayload {
+    auth_key: String,
+    license_id: u64,
+    end_date: String,
+    price: u32,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct AutoRenewPayload {
+    auth_key: String,
+    license_id: u64,
+    auto_renew: bool,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct TransferablePayload {
+    auth_key: String,
+    license_id: u64,
+    transferable: bool,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct MessagePayload {
+    auth_key: String,
+    license_id: u64,
+    body: String,
+}
+
+// A page of a party's notifications, newest first, starting after the notification `prev`
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct NotificationQuery {
+    party: MessageParty,
+    id: u64,
+    auth_key: String,
+    prev: Option<u64>,
+    take: Option<u32>,
+    unread_only: bool,
+}
+
+// Mark the given notifications as read, or all of them when no ids are given
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct MarkNotificationsPayload {
+    party: MessageParty,
+    id: u64,
+    auth_key: String,
+    notification_ids: Option<Vec<u64>>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct NotificationPreferencesPayload {
+    party: MessageParty,
+    id: u64,
+    auth_key: String,
+    preferences: NotificationPreferences,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct SongTransferPayload {
+    auth_key: String,
+    song_id: u64,
+    new_owner_id: u64,
+    pending_requests: PendingRequestPolicy,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct TransferPayload {
+    auth_key: String,
+    license_id: u64,
+    new_licensee_id: u64,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct SublicensePayload {
+    auth_key: String,
+    parent_license_id: u64,
+    sublicensee_id: u64,
+    start_date: String,
+    end_date: String,
+    media: Vec<Media>,
+    territories: Vec<String>,
+    max_copies: Option<u64>,
+    max_streams: Option<u64>,
+    price: u32,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct AutoApprovalPayload {
+    auth_key: String,
+    song_id: u64,
+    rules: Option<AutoApprovalRules>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct DepositPayload {
+    auth_key: String,
+    licensee_id: u64,
+    amount: u64,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct WithdrawPayload {
+    party: MessageParty,
+    id: u64,
+    auth_key: String,
+    amount: u64,
+    to: icrc7::Account,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct OfferPayload {
+    auth_key: String,
+    song_id: u64,
+    name: String,
+    price: u32,
+    license_type: LicenseType,
+    media: Vec<Media>,
+    territories: Vec<String>,
+    exclusive: bool,
+    duration_days: u32,
+    max_copies: Option<u64>,
+    max_streams: Option<u64>,
+    instant: bool,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct ReleasePayload {
+    auth_key: String,
+    owner_id: u64,
+    title: String,
+    release_type: ReleaseType,
+    release_date: String,
+    upc: Option<String>,
+    track_ids: Vec<u64>,
+    bundle_price: u32,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct UpdateReleasePayload {
+    auth_key: String,
+    id: u64,
+    title: String,
+    release_type: ReleaseType,
+    release_date: String,
+    upc: Option<String>,
+    track_ids: Vec<u64>,
+    bundle_price: u32,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct ReleaseLicensePayload {
+    release_id: u64,
+    licensee_id: u64,
+    start_date: String,
+    end_date: String,
+    exclusive: bool,
+    media: Vec<Media>,
+    territories: Vec<String>,
+    license_type: LicenseType,
+    max_copies: Option<u64>,
+    max_streams: Option<u64>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct ApproveBundlePayload {
+    auth_key: String,
+    bundle_id: u64,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct StartUploadPayload {
+    auth_key: String,
+    #[serde(rename = "song_id")]
+    entity_id: u64,
+    kind: AssetKind,
+    file_name: String,
+    content_type: String,
+    size: u64,
+    sha256: String,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct UploadChunkPayload {
+    auth_key: String,
+    session_id: u64,
+    index: u32,
+    data: ByteBuf,
+    sha256: String,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct DownloadChunkPayload {
+    licensee_id: u64,
+    auth_key: String,
+    song_id: u64,
+    kind: AssetKind,
+    index: u32,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct OfferLicensePayload {
+    auth_key: String,
+    offer_id: u64,
+    licensee_id: u64,
+    start_date: String,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct UpdateSongPayload {
+    auth_key: String,
+    id: u64,
+    title: String,
+    artist: String,
+    year: u32,
+    genre: String,
+    price: u32,
+    rights: UsageRights,
+    isrc: Option<String>,
+    iswc: Option<String>,
+    contributors: Vec<Contributor>,
+    tags: Vec<String>,
+    moods: Vec<Mood>,
+    tempo: Option<Tempo>,
+    instruments: Vec<Instrument>,
+    duration_secs: Option<u32>,
+    bpm: Option<u32>,
+    key: Option<MusicalKey>,
+    instrumental: Option<bool>,
+    explicit: Option<bool>,
+    language: Option<String>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct ContributorLinkPayload {
+    auth_key: String,
+    owner_id: u64,
+    song_id: u64,
+    name: String,
+    role: ContributorRole,
+}
+
+// Define query functions to get all licensable songs
+#[ic_cdk::query]
+fn get_all_songs() -> Result<Vec<Song>, Error> {
+    let songs_vec: Vec<(u64, Song)> = SONG_STORAGE.with(|s| s.borrow().iter().collect());
+    let songs: Vec<Song> = songs_vec.into_iter().map(|(_, song)| song).collect();
+
+    match songs.len() {
+        0 => Err(Error::NotFound {
+            msg: "no songs licensable could be found".to_string(),
+        }),
+        _ => Ok(songs),
+    }
+}
+
+// Define query functions to get songs by id
+#[ic_cdk::query]
+fn get_song(id: u64) -> Result<Song, Error> {
+    match _get_song(&id) {
+        Some(song) => Ok(song),
+        None => Err(Error::NotFound {
+            msg: format!("song id:{} could not be found", id),
+        }),
+    }
+}
+
+fn _get_song(id: &u64) -> Option<Song> {
+    SONG_STORAGE.with(|s| s.borrow().get(id))
 }
 
 // Define update functions to create new songs
 #[ic_cdk::update]
-fn create_song(payload: SongPayload) -> Result<Song, Error> {
+fn create_song(payload: SongPayload) -> Result<Song, Error> {
+    authorize_party(MessageParty::Owner, payload.owner_id, &payload.auth_key)?;
+
+    // The id the song gets once it passes validation
+    let id = ID_COUNTER.with(|counter| *counter.borrow().get());
+
+    let mut song = song_from_payload(id, payload.clone());
+
+    check_new_song(&mut song, None, &mut Vec::new())?;
+
+    // Increment the global ID counter to get a new unique ID
+    ID_COUNTER
+        .with(|counter| counter.borrow_mut().set(id + 1))
+        .expect("Cannot increment Ids");
+
+    match add_song_to_owner(song.owner_id, song.id) {
+        Ok(_) => (),
+        Err(e) => return Err(e),
+    }
+
+    index_song_isrc(None, &song);
+    index_song_catalog(None, Some(&song));
+
+    match SONG_STORAGE.with(|s| s.borrow_mut().insert(id, song.clone())) {
+        None => Ok(song),
+        Some(_) => Err(Error::InternalError {
+            msg: format!("song title:{} could not be created", payload.title),
+        }),
+    }
+}
+
+// Define query functions to look up a song by its ISRC
+#[ic_cdk::query]
+fn get_song_by_isrc(isrc: String) -> Result<Song, Error> {
+    let normalized =
+        identifiers::normalize_isrc(&isrc).map_err(|reason| validation_error("isrc", reason))?;
+
+    match ISRC_INDEX
+        .with(|i| i.borrow().get(&isrc_key(&normalized)))
+        .and_then(|id| _get_song(&id))
+    {
+        Some(song) => Ok(song),
+        None => Err(Error::NotFound {
+            msg: format!("song isrc:{} could not be found", normalized),
+        }),
+    }
+}
+
+fn isrc_key(isrc: &str) -> [u8; 12] {
+    let mut key = [0u8; 12];
+    key.copy_from_slice(&isrc.as_bytes()[..12]);
+    key
+}
+
+// Normalize a song's ISRC and ISWC and make sure no other song, including earlier songs of
+// the same batch, already uses the ISRC
+// `existing_id` is the id of the stored song being updated, None for songs not stored yet
+fn check_song_identifiers(
+    song: &mut Song,
+    existing_id: Option<u64>,
+    batch_isrcs: &mut Vec<String>,
+) -> Result<(), Error> {
+    if let Some(isrc) = &song.isrc {
+        let isrc =
+            identifiers::normalize_isrc(isrc).map_err(|reason| validation_error("isrc", reason))?;
+
+        let existing = ISRC_INDEX.with(|i| i.borrow().get(&isrc_key(&isrc)));
+        if existing.is_some_and(|id| Some(id) != existing_id) || batch_isrcs.contains(&isrc) {
+            return Err(Error::Conflict {
+                msg: format!("isrc:{} is already used by another song", isrc),
+                license_ids: Vec::new(),
+            });
+        }
+
+        batch_isrcs.push(isrc.clone());
+        song.isrc = Some(isrc);
+    }
+
+    if let Some(iswc) = &song.iswc {
+        song.iswc = Some(
+            identifiers::normalize_iswc(iswc).map_err(|reason| validation_error("iswc", reason))?,
+        );
+    }
+
+    Ok(())
+}
+
+// Validate a song before it is stored
+fn check_new_song(
+    song: &mut Song,
+    existing_id: Option<u64>,
+    batch_isrcs: &mut Vec<String>,
+) -> Result<(), Error> {
+    check_song_fields(song)?;
+    validate_territories(&song.rights.territories)?;
+    check_song_identifiers(song, existing_id, batch_isrcs)?;
+    check_contributors(song, existing_id)?;
+    check_song_size(song)
+}
+
+fn check_song_size(song: &Song) -> Result<(), Error> {
+    match Encode!(song).map_or(true, |bytes| bytes.len() > Song::MAX_SIZE as usize) {
+        true => Err(validation_error(
+            "song",
+            format!(
+                "song title:{} exceeds the maximum size of {} bytes",
+                song.title,
+                Song::MAX_SIZE
+            ),
+        )),
+        false => Ok(()),
+    }
+}
+
+// Normalize the free-text fields of a song and check their lengths, year, genre, duration,
+// BPM and language
+fn check_song_fields(song: &mut Song) -> Result<(), Error> {
+    song.title = check_text("title", &song.title, validation::MAX_TITLE_LENGTH)?;
+    song.artist = check_text("artist", &song.artist, validation::MAX_NAME_LENGTH)?;
+    song.year = validation::year(song.year, current_year())
+        .map_err(|reason| validation_error("year", reason))?;
+    let genre = resolve_genre(&song.genre)?;
+    song.genre = genre.name;
+    song.genre_id = Some(genre.id);
+    song.tags = validation::tags(&song.tags).map_err(|reason| validation_error("tags", reason))?;
+    song.moods = unique(&song.moods);
+    song.instruments = unique(&song.instruments);
+    if let Some(duration_secs) = song.duration_secs {
+        validation::duration_secs(duration_secs)
+            .map_err(|reason| validation_error("duration_secs", reason))?;
+    }
+    if let Some(bpm) = song.bpm {
+        validation::bpm(bpm).map_err(|reason| validation_error("bpm", reason))?;
+    }
+    song.language = match song
+        .language
+        .as_deref()
+        .filter(|code| !code.trim().is_empty())
+    {
+        Some(code) => Some(
+            validation::language(code).map_err(|reason| validation_error("language", reason))?,
+        ),
+        None => None,
+    };
+    if song.instrumental == Some(true) && song.language.is_some() {
+        return Err(validation_error(
+            "language",
+            "an instrumental song has no lyrics language".to_string(),
+        ));
+    }
+    song.rights.attribution = validation::optional_text(
+        "attribution",
+        song.rights.attribution.as_deref(),
+        validation::MAX_ATTRIBUTION_LENGTH,
+    )
+    .map_err(|reason| validation_error("attribution", reason))?;
+
+    Ok(())
+}
+
+fn unique<T: PartialEq + Copy>(values: &[T]) -> Vec<T> {
+    let mut unique: Vec<T> = Vec::new();
+    for value in values {
+        if !unique.contains(value) {
+            unique.push(*value);
+        }
+    }
+    unique
+}
+
+fn check_text(field: &str, value: &str, max_length: usize) -> Result<String, Error> {
+    validation::text(field, value, max_length).map_err(|reason| validation_error(field, reason))
+}
+
+// Name, email and auth key of a new owner or licensee
+fn check_party(name: &str, email: &str, auth_key: &str) -> Result<(String, String), Error> {
+    let name = check_text("name", name, validation::MAX_NAME_LENGTH)?;
+    let email = validation::email(email).map_err(|reason| validation_error("email", reason))?;
+    validation::auth_key(auth_key).map_err(|reason| validation_error("auth_key", reason))?;
+    Ok((name, email))
+}
+
+fn current_year() -> u32 {
+    let today = (runtime::time() / 86_400_000_000_000) as i64;
+    format_date(today)[..4].parse().unwrap_or(u32::MAX - 1)
+}
+
+// Normalize contributor names and make sure linked owner accounts exist
+// A song owner may credit their own account; any other owner links themselves with
+// link_contributor, and links they made are kept when the song is updated
+fn check_contributors(song: &mut Song, existing_id: Option<u64>) -> Result<(), Error> {
+    let linked: Vec<Contributor> = existing_id
+        .and_then(|id| _get_song(&id))
+        .map(|song| song.contributors)
+        .unwrap_or_default();
+
+    for contributor in song.contributors.iter_mut() {
+        contributor.name = check_text(
+            "contributors",
+            &contributor.name,
+            validation::MAX_NAME_LENGTH,
+        )?;
+
+        if let Some(owner_id) = contributor.owner_id {
+            if _get_owner(&owner_id).is_none() {
+                return Err(Error::NotFound {
+                    msg: format!("owner id:{} could not be found", owner_id),
+                });
+            }
+
+            let confirmed = owner_id == song.owner_id
+                || linked.iter().any(|c| {
+                    c.owner_id == Some(owner_id)
+                        && c.role == contributor.role
+                        && c.name == contributor.name
+                });
+            if !confirmed {
+                return Err(validation_error(
+                    "contributors",
+                    format!(
+                        "owner id:{} has not confirmed the credit, they link themselves with link_contributor",
+                        owner_id
+                    ),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// An owner confirms a credit on someone else's song by linking it to their account
+#[ic_cdk::update]
+fn link_contributor(payload: ContributorLinkPayload) -> Result<Song, Error> {
+    set_contributor_link(payload, true)
+}
+
+// The linked owner removes the link from a credit; the credit itself stays
+#[ic_cdk::update]
+fn unlink_contributor(payload: ContributorLinkPayload) -> Result<Song, Error> {
+    set_contributor_link(payload, false)
+}
+
+fn set_contributor_link(payload: ContributorLinkPayload, link: bool) -> Result<Song, Error> {
+    let owner = match _get_owner(&payload.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", payload.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != payload.auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only the credited owner can link a contributor",
+                payload.auth_key
+            ),
+        });
+    }
+
+    let mut song = match _get_song(&payload.song_id) {
+        Some(song) => song,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("song id:{} could not be found", payload.song_id),
+            })
+        }
+    };
+
+    let name = payload.name.trim().to_lowercase();
+    let contributor = match song.contributors.iter_mut().find(|c| {
+        c.name.to_lowercase() == name
+            && c.role == payload.role
+            && match link {
+                true => c.owner_id.is_none(),
+                false => c.owner_id == Some(owner.id),
+            }
+    }) {
+        Some(contributor) => contributor,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!(
+                    "contributor:{} could not be found on song id:{}",
+                    name, payload.song_id
+                ),
+            })
+        }
+    };
+    contributor.owner_id = link.then_some(owner.id);
+    check_song_size(&song)?;
+
+    match SONG_STORAGE.with(|s| s.borrow_mut().insert(song.id, song.clone())) {
+        Some(_) => Ok(song),
+        None => Err(Error::InternalError {
+            msg: format!("song id:{} could not be updated", payload.song_id),
+        }),
+    }
+}
+
+// Define query functions to get the songs crediting a contributor, matched by name
+// regardless of case, optionally only in one role
+#[ic_cdk::query]
+fn get_songs_by_contributor(
+    name: String,
+    role: Option<ContributorRole>,
+) -> Result<Vec<Song>, Error> {
+    let name = name.trim().to_lowercase();
+    let songs = find_songs(|song| {
+        song.contributors.iter().any(|contributor| {
+            contributor.name.to_lowercase() == name && role.is_none_or(|r| contributor.role == r)
+        })
+    });
+
+    match songs.len() {
+        0 => Err(Error::NotFound {
+            msg: format!("no songs could be found for contributor:{}", name),
+        }),
+        _ => Ok(songs),
+    }
+}
+
+// Define query functions to get the songs an owner is credited on, whoever owns them
+#[ic_cdk::query]
+fn get_owner_contributions(owner_id: u64) -> Result<Vec<Song>, Error> {
+    let songs = find_songs(|song| {
+        song.contributors
+            .iter()
+            .any(|contributor| contributor.owner_id == Some(owner_id))
+    });
+
+    match songs.len() {
+        0 => Err(Error::NotFound {
+            msg: format!("no contributions could be found for owner id:{}", owner_id),
+        }),
+        _ => Ok(songs),
+    }
+}
+
+fn find_songs(filter: impl Fn(&Song) -> bool) -> Vec<Song> {
+    let songs_vec: Vec<(u64, Song)> = SONG_STORAGE.with(|s| s.borrow().iter().collect());
+    songs_vec
+        .into_iter()
+        .map(|(_, song)| song)
+        .filter(|song| filter(song))
+        .collect()
+}
+
+// Point the ISRC index at a song, dropping the entry for its previous ISRC
+fn index_song_isrc(previous_isrc: Option<&String>, song: &Song) {
+    ISRC_INDEX.with(|i| {
+        let mut index = i.borrow_mut();
+        if let Some(previous) = previous_isrc {
+            index.remove(&isrc_key(previous));
+        }
+        if let Some(isrc) = &song.isrc {
+            index.insert(isrc_key(isrc), song.id);
+        }
+    });
+}
+
+// Define query functions to get the genre taxonomy
+#[ic_cdk::query]
+fn get_genres() -> Vec<Genre> {
+    GENRE_STORAGE.with(|s| s.borrow().iter().map(|(_, genre)| genre).collect())
+}
+
+#[ic_cdk::query]
+fn get_genre(id: u64) -> Result<Genre, Error> {
+    match _get_genre(&id) {
+        Some(genre) => Ok(genre),
+        None => Err(Error::NotFound {
+            msg: format!("genre id:{} could not be found", id),
+        }),
+    }
+}
+
+fn _get_genre(id: &u64) -> Option<Genre> {
+    GENRE_STORAGE.with(|s| s.borrow().get(id))
+}
+
+// Define query functions to get a genre and its ancestors, top-level genre first
+#[ic_cdk::query]
+fn get_genre_path(id: u64) -> Result<Vec<Genre>, Error> {
+    let mut path = vec![get_genre(id)?];
+    while let Some(parent) = path.last().and_then(|genre| genre.parent_id) {
+        match _get_genre(&parent) {
+            Some(genre) if !path.iter().any(|g| g.id == genre.id) => path.push(genre),
+            _ => break,
+        }
+    }
+    path.reverse();
+    Ok(path)
+}
+
+#[ic_cdk::update]
+fn create_genre(payload: GenrePayload) -> Result<Genre, Error> {
+    authorize_controller("manage genres")?;
+
+    // Increment the global ID counter to get a new unique ID
+    let id = ID_COUNTER
+        .with(|counter| {
+            let current_id = *counter.borrow().get();
+            counter.borrow_mut().set(current_id + 1)
+        })
+        .expect("Cannot increment Ids");
+
+    let genre = check_genre(id, payload)?;
+
+    match GENRE_STORAGE.with(|s| s.borrow_mut().insert(id, genre.clone())) {
+        None => Ok(genre),
+        Some(_) => Err(Error::InternalError {
+            msg: format!("genre name:{} could not be created", genre.name),
+        }),
+    }
+}
+
+// Rename, re-parent or re-alias a genre; songs filed under it take the new name
+#[ic_cdk::update]
+fn update_genre(id: u64, payload: GenrePayload) -> Result<Genre, Error> {
+    authorize_controller("manage genres")?;
+    get_genre(id)?;
+
+    let genre = check_genre(id, payload)?;
+
+    // Songs store the genre's name, so a longer name must still fit every song filed under it
+    let mut songs = Vec::new();
+    for song_id in indexed_song_ids(&genre_term(id)) {
+        if let Some(mut song) = _get_song(&song_id) {
+            song.genre = genre.name.clone();
+            check_song_size(&song)?;
+            songs.push((song_id, song));
+        }
+    }
+
+    GENRE_STORAGE.with(|s| s.borrow_mut().insert(id, genre.clone()));
+    SONG_STORAGE.with(|s| {
+        let mut storage = s.borrow_mut();
+        for (song_id, song) in songs {
+            storage.insert(song_id, song);
+        }
+    });
+
+    Ok(genre)
+}
+
+// Only genres without subgenres and songs can be deleted
+#[ic_cdk::update]
+fn delete_genre(id: u64) -> Result<Genre, Error> {
+    authorize_controller("manage genres")?;
+    let genre = get_genre(id)?;
+
+    if get_genres().iter().any(|g| g.parent_id == Some(id)) {
+        return Err(Error::Conflict {
+            msg: format!("genre id:{} has subgenres", id),
+            license_ids: Vec::new(),
+        });
+    }
+
+    if !indexed_song_ids(&genre_term(id)).is_empty() {
+        return Err(Error::Conflict {
+            msg: format!("genre id:{} still has songs filed under it", id),
+            license_ids: Vec::new(),
+        });
+    }
+
+    GENRE_STORAGE.with(|s| s.borrow_mut().remove(&id));
+    Ok(genre)
+}
+
+// Normalize a genre's name and aliases, which must not name another genre, and make sure its
+// parent exists and is not the genre itself or one of its subgenres
+fn check_genre(id: u64, payload: GenrePayload) -> Result<Genre, Error> {
+    let name = check_text("name", &payload.name, validation::MAX_NAME_LENGTH)?;
+    let mut aliases: Vec<String> = Vec::new();
+    for alias in &payload.aliases {
+        let alias = check_text("aliases", alias, validation::MAX_NAME_LENGTH)?;
+        if !aliases.contains(&alias) {
+            aliases.push(alias);
+        }
+    }
+
+    let others: Vec<Genre> = get_genres().into_iter().filter(|g| g.id != id).collect();
+    for spelling in std::iter::once(&name).chain(aliases.iter()) {
+        let key = validation::genre_key(spelling);
+        if key.is_empty() {
+            return Err(validation_error(
+                "name",
+                format!("genre name:{} has no letters or digits", spelling),
+            ));
+        }
+        if let Some(other) = others.iter().find(|g| genre_keys(g).contains(&key)) {
+            return Err(Error::Conflict {
+                msg: format!(
+                    "genre name:{} is already used by genre id:{}",
+                    spelling, other.id
+                ),
+                license_ids: Vec::new(),
+            });
+        }
+    }
+
+    if let Some(parent_id) = payload.parent_id {
+        if _get_genre(&parent_id).is_none() {
+            return Err(Error::NotFound {
+                msg: format!("genre id:{} could not be found", parent_id),
+            });
+        }
+        if genre_descendants(id).contains(&parent_id) {
+            return Err(validation_error(
+                "parent_id",
+                format!(
+                    "genre id:{} cannot be filed under itself or its subgenres",
+                    id
+                ),
+            ));
+        }
+    }
+
+    let genre = Genre {
+        id,
+        name,
+        parent_id: payload.parent_id,
+        aliases,
+    };
+
+    if Encode!(&genre).map_or(true, |bytes| bytes.len() > Genre::MAX_SIZE as usize) {
+        return Err(validation_error(
+            "aliases",
+            format!(
+                "genre name:{} exceeds the maximum size of {} bytes",
+                genre.name,
+                Genre::MAX_SIZE
+            ),
+        ));
+    }
+
+    Ok(genre)
+}
+
+fn genre_keys(genre: &Genre) -> Vec<String> {
+    std::iter::once(&genre.name)
+        .chain(genre.aliases.iter())
+        .map(|spelling| validation::genre_key(spelling))
+        .collect()
+}
+
+// The genre a song names, by its name or one of its aliases
+fn resolve_genre(name: &str) -> Result<Genre, Error> {
+    let key = validation::genre_key(name);
+    if key.is_empty() {
+        return Err(validation_error("genre", "genre is required".to_string()));
+    }
+
+    match get_genres()
+        .into_iter()
+        .find(|genre| genre_keys(genre).contains(&key))
+    {
+        Some(genre) => Ok(genre),
+        None => Err(validation_error(
+            "genre",
+            format!("genre:{} is not in the genre taxonomy", name),
+        )),
+    }
+}
+
+// A genre and all genres below it
+fn genre_descendants(id: u64) -> Vec<u64> {
+    let genres = get_genres();
+    let mut ids = vec![id];
+    let mut i = 0;
+    while i < ids.len() {
+        for genre in &genres {
+            if genre.parent_id == Some(ids[i]) && !ids.contains(&genre.id) {
+                ids.push(genre.id);
+            }
+        }
+        i += 1;
+    }
+    ids
+}
+
+// The taxonomy starts with the default top-level genres. Songs stored before it existed are
+// filed under their genre and indexed when it is created.
+fn seed_genres() {
+    if !GENRE_STORAGE.with(|s| s.borrow().is_empty()) {
+        return;
+    }
+
+    for name in validation::DEFAULT_GENRES {
+        // Increment the global ID counter to get a new unique ID
+        let id = ID_COUNTER
+            .with(|counter| {
+                let current_id = *counter.borrow().get();
+                counter.borrow_mut().set(current_id + 1)
+            })
+            .expect("Cannot increment Ids");
+
+        let genre = Genre {
+            id,
+            name: name.to_string(),
+            parent_id: None,
+            aliases: Vec::new(),
+        };
+        GENRE_STORAGE.with(|s| s.borrow_mut().insert(id, genre));
+    }
+
+    let songs: Vec<Song> = SONG_STORAGE.with(|s| s.borrow().iter().map(|(_, song)| song).collect());
+    for mut song in songs {
+        if let Ok(genre) = resolve_genre(&song.genre) {
+            song.genre = genre.name;
+            song.genre_id = Some(genre.id);
+            SONG_STORAGE.with(|s| s.borrow_mut().insert(song.id, song.clone()));
+        }
+        index_song_catalog(None, Some(&song));
+    }
+}
+
+fn authorize_controller(action: &str) -> Result<(), Error> {
+    match runtime::is_controller(&runtime::caller()) {
+        true => Ok(()),
+        false => Err(Error::Forbidden {
+            msg: format!("only canister controllers can {}", action),
+        }),
+    }
+}
+
+// Define query functions to filter the catalog by genre, tags and musical attributes
+#[ic_cdk::query]
+fn search_songs(filter: SongFilter) -> Result<Vec<Song>, Error> {
+    // Each group lists alternative terms; a song must match a term of every group
+    let mut groups: Vec<Vec<String>> = Vec::new();
+    if let Some(genre_id) = filter.genre_id {
+        get_genre(genre_id)?;
+        groups.push(
+            genre_descendants(genre_id)
+                .into_iter()
+                .map(genre_term)
+                .collect(),
+        );
+    }
+    for tag in &filter.tags {
+        let tag = validation::tag(tag).map_err(|reason| validation_error("tags", reason))?;
+        groups.push(vec![format!("tag:{}", tag)]);
+    }
+    for mood in &filter.moods {
+        groups.push(vec![format!("mood:{:?}", mood)]);
+    }
+    if let Some(tempo) = filter.tempo {
+        groups.push(vec![format!("tempo:{:?}", tempo)]);
+    }
+    for instrument in &filter.instruments {
+        groups.push(vec![format!("instrument:{:?}", instrument)]);
+    }
+    if let Some(key) = filter.key {
+        groups.push(vec![key_term(key)]);
+    }
+    if let Some(instrumental) = filter.instrumental {
+        groups.push(vec![format!("instrumental:{}", instrumental)]);
+    }
+    if let Some(explicit) = filter.explicit {
+        groups.push(vec![format!("explicit:{}", explicit)]);
+    }
+    if let Some(code) = &filter.language {
+        let code =
+            validation::language(code).map_err(|reason| validation_error("language", reason))?;
+        groups.push(vec![format!("language:{}", code)]);
+    }
+    check_bounds(
+        "duration_secs",
+        filter.min_duration_secs,
+        filter.max_duration_secs,
+    )?;
+    check_bounds("bpm", filter.min_bpm, filter.max_bpm)?;
+
+    // Durations and tempos are compared on the songs left after the indexed criteria
+    let in_bounds = |song: &Song| {
+        within(
+            song.duration_secs,
+            filter.min_duration_secs,
+            filter.max_duration_secs,
+        ) && within(song.bpm, filter.min_bpm, filter.max_bpm)
+    };
+
+    if groups.is_empty() {
+        return Ok(find_songs(in_bounds));
+    }
+
+    let mut ids: Option<BTreeSet<u64>> = None;
+    for group in groups {
+        let matched: BTreeSet<u64> = group
+            .iter()
+            .flat_map(|term| indexed_song_ids(term))
+            .collect();
+        ids = Some(match ids {
+            Some(ids) => ids.intersection(&matched).copied().collect(),
+            None => matched,
+        });
+    }
+
+    Ok(ids
+        .unwrap_or_default()
+        .iter()
+        .filter_map(_get_song)
+        .filter(|song| in_bounds(song))
+        .collect())
+}
+
+fn check_bounds(field: &str, min: Option<u32>, max: Option<u32>) -> Result<(), Error> {
+    match (min, max) {
+        (Some(min), Some(max)) if min > max => Err(validation_error(
+            field,
+            format!("minimum {} is greater than maximum {}", min, max),
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Songs without a value only match when no bound is given
+fn within(value: Option<u32>, min: Option<u32>, max: Option<u32>) -> bool {
+    if min.is_none() && max.is_none() {
+        return true;
+    }
+    value.is_some_and(|value| {
+        min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+    })
+}
+
+fn genre_term(id: u64) -> String {
+    format!("genre:{}", id)
+}
+
+fn key_term(key: MusicalKey) -> String {
+    format!("key:{:?}:{:?}", key.tonic, key.mode)
+}
+
+// Index terms of a song's genre, tags, moods, tempo, instruments, key, vocals, explicit
+// content flag and language
+fn catalog_terms(song: &Song) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    if let Some(genre_id) = song.genre_id {
+        terms.push(genre_term(genre_id));
+    }
+    terms.extend(song.tags.iter().map(|tag| format!("tag:{}", tag)));
+    terms.extend(song.moods.iter().map(|mood| format!("mood:{:?}", mood)));
+    if let Some(tempo) = song.tempo {
+        terms.push(format!("tempo:{:?}", tempo));
+    }
+    terms.extend(
+        song.instruments
+            .iter()
+            .map(|instrument| format!("instrument:{:?}", instrument)),
+    );
+    if let Some(key) = song.key {
+        terms.push(key_term(key));
+    }
+    if let Some(instrumental) = song.instrumental {
+        terms.push(format!("instrumental:{}", instrumental));
+    }
+    if let Some(explicit) = song.explicit {
+        terms.push(format!("explicit:{}", explicit));
+    }
+    if let Some(code) = &song.language {
+        terms.push(format!("language:{}", code));
+    }
+    terms
+}
+
+fn term_key(term: &str) -> [u8; 32] {
+    Sha256::digest(term.as_bytes()).into()
+}
+
+fn index_song_catalog(previous: Option<&Song>, song: Option<&Song>) {
+    CATALOG_INDEX.with(|i| {
+        let mut index = i.borrow_mut();
+        if let Some(previous) = previous {
+            for term in catalog_terms(previous) {
+                index.remove(&(term_key(&term), previous.id));
+            }
+        }
+        if let Some(song) = song {
+            for term in catalog_terms(song) {
+                index.insert((term_key(&term), song.id), ());
+            }
+        }
+    });
+}
+
+fn indexed_song_ids(term: &str) -> BTreeSet<u64> {
+    let key = term_key(term);
+    CATALOG_INDEX.with(|i| {
+        i.borrow()
+            .range((key, 0)..=(key, u64::MAX))
+            .map(|((_, song_id), _)| song_id)
+            .collect()
+    })
+}
+
+fn song_from_payload(id: u64, payload: SongPayload) -> Song {
+    Song {
+        id,
+        title: payload.title,
+        artist: payload.artist,
+        owner_id: payload.owner_id,
+        year: payload.year,
+        genre: payload.genre,
+        price: payload.price,
+        rights: normalize_rights(payload.rights),
+        auto_approval: None,
+        pending_transfer: None,
+        isrc: payload.isrc,
+        iswc: payload.iswc,
+        contributors: payload.contributors,
+        genre_id: None,
+        tags: payload.tags,
+        moods: payload.moods,
+        tempo: payload.tempo,
+        instruments: payload.instruments,
+        duration_secs: payload.duration_secs,
+        bpm: payload.bpm,
+        key: payload.key,
+        instrumental: payload.instrumental,
+        explicit: payload.explicit,
+        language: payload.language,
+    }
+}
+
+// Import a batch of songs for an owner. Every row is validated first and the batch is only
+// written when all rows pass, so a failed batch can be fixed and resent as a whole.
+#[ic_cdk::update]
+fn bulk_import_songs(payload: BulkImportPayload) -> Result<ImportReport, Error> {
+    let owner = match _get_owner(&payload.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", payload.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != payload.auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only the owner can import songs",
+                payload.auth_key
+            ),
+        });
+    }
+
+    let rows = import::parse_rows(payload.format, &payload.data)
+        .map_err(|reason| validation_error("data", reason))?;
+
+    let mut batch_isrcs: Vec<String> = Vec::new();
+    let checked: Vec<Result<Song, String>> = rows
+        .into_iter()
+        .map(|row| {
+            let mut song = song_from_payload(0, row?.into_payload(owner.id, &payload.auth_key)?);
+            check_new_song(&mut song, None, &mut batch_isrcs).map_err(error_message)?;
+            Ok(song)
+        })
+        .collect();
+
+    let failed = checked.iter().filter(|row| row.is_err()).count() as u32;
+    if failed > 0 {
+        return Ok(ImportReport {
+            committed: false,
+            imported: 0,
+            failed,
+            rows: checked
+                .into_iter()
+                .enumerate()
+                .map(|(i, row)| ImportRowResult {
+                    row: i as u32 + 1,
+                    song_id: None,
+                    error: row.err(),
+                })
+                .collect(),
+        });
+    }
+
+    let mut results: Vec<ImportRowResult> = Vec::new();
+
+    for (i, mut song) in checked.into_iter().flatten().enumerate() {
+        // Increment the global ID counter to get a new unique ID
+        let id = ID_COUNTER
+            .with(|counter| {
+                let current_id = *counter.borrow().get();
+                counter.borrow_mut().set(current_id + 1)
+            })
+            .expect("Cannot increment Ids");
+
+        song.id = id;
+        index_song_isrc(None, &song);
+        index_song_catalog(None, Some(&song));
+        SONG_STORAGE.with(|s| s.borrow_mut().insert(id, song));
+        OWNER_SONG_INDEX.with(|i| i.borrow_mut().insert((owner.id, id), ()));
+
+        results.push(ImportRowResult {
+            row: i as u32 + 1,
+            song_id: Some(id),
+            error: None,
+        });
+    }
+
+    Ok(ImportReport {
+        committed: true,
+        imported: results.len() as u32,
+        failed: 0,
+        rows: results,
+    })
+}
+
+// Ingest a DDEX ERN release message for an owner: sound recordings become songs and deal
+// terms become license offers on them. Like bulk imports, nothing is written unless every
+// sound recording can be imported.
+#[ic_cdk::update]
+fn ingest_ddex_ern(payload: DdexPayload) -> Result<DdexReport, Error> {
+    let owner = match _get_owner(&payload.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", payload.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != payload.auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only the owner can ingest releases",
+                payload.auth_key
+            ),
+        });
+    }
+
+    let message =
+        ddex::parse_ern(&payload.xml).map_err(|reason| validation_error("xml", reason))?;
+
+    let mut batch_isrcs: Vec<String> = Vec::new();
+    let checked: Vec<(String, Result<Song, String>)> = message
+        .recordings
+        .iter()
+        .map(|recording| {
+            let mut song = song_from_payload(
+                0,
+                SongPayload {
+                    auth_key: payload.auth_key.clone(),
+                    title: recording.title.clone(),
+                    artist: recording.artists.join(", "),
+                    owner_id: owner.id,
+                    year: recording.year,
+                    genre: recording.genre.clone(),
+                    price: message
+                        .deals
+                        .iter()
+                        .filter(|deal| deal.resource_references.contains(&recording.reference))
+                        .map(|deal| deal.price)
+                        .min()
+                        .unwrap_or(0),
+                    rights: UsageRights::default(),
+                    isrc: recording.isrc.clone(),
+                    iswc: None,
+                    contributors: recording.contributors.clone(),
+                    tags: Vec::new(),
+                    moods: Vec::new(),
+                    tempo: None,
+                    instruments: Vec::new(),
+                    duration_secs: recording.duration_secs,
+                    bpm: None,
+                    key: None,
+                    instrumental: None,
+                    explicit: recording.explicit,
+                    language: recording.language.clone(),
+                },
+            );
+
+            let result = if song.title.is_empty() {
+                Err("sound recording has no title".to_string())
+            } else {
+                check_new_song(&mut song, None, &mut batch_isrcs)
+                    .and_then(|_| {
+                        // Deals become offers, which take the same territory codes as any other
+                        message
+                            .deals
+                            .iter()
+                            .filter(|deal| deal.resource_references.contains(&recording.reference))
+                            .try_for_each(|deal| {
+                                validate_territories(&normalize_territories(
+                                    deal.territories.clone(),
+                                ))
+                            })
+                    })
+                    .map(|_| song)
+                    .map_err(error_message)
+            };
+            (recording.reference.clone(), result)
+        })
+        .collect();
+
+    if checked.iter().any(|(_, song)| song.is_err()) {
+        return Ok(DdexReport {
+            committed: false,
+            message_id: message.message_id,
+            songs: checked
+                .into_iter()
+                .map(|(resource_reference, song)| DdexSongResult {
+                    resource_reference,
+                    song_id: None,
+                    error: song.err(),
+                })
+                .collect(),
+            offer_ids: Vec::new(),
+            unsupported: message.unsupported,
+        });
+    }
+
+    let mut songs: Vec<DdexSongResult> = Vec::new();
+    let mut offer_ids: Vec<u64> = Vec::new();
+
+    for (resource_reference, song) in checked {
+        let mut song = song.expect("rows were checked above");
+
+        // Increment the global ID counter to get a new unique ID
+        let id = ID_COUNTER
+            .with(|counter| {
+                let current_id = *counter.borrow().get();
+                counter.borrow_mut().set(current_id + 1)
+            })
+            .expect("Cannot increment Ids");
+        song.id = id;
+        index_song_isrc(None, &song);
+        index_song_catalog(None, Some(&song));
+        SONG_STORAGE.with(|s| s.borrow_mut().insert(id, song));
+        OWNER_SONG_INDEX.with(|i| i.borrow_mut().insert((owner.id, id), ()));
+
+        for deal in message
+            .deals
+            .iter()
+            .filter(|deal| deal.resource_references.contains(&resource_reference))
+        {
+            let offer_id = ID_COUNTER
+                .with(|counter| {
+                    let current_id = *counter.borrow().get();
+                    counter.borrow_mut().set(current_id + 1)
+                })
+                .expect("Cannot increment Ids");
+
+            let offer = LicenseOffer {
+                id: offer_id,
+                song_id: id,
+                owner_id: owner.id,
+                name: deal.name.clone(),
+                price: deal.price,
+                license_type: deal.license_type,
+                media: deal.media.clone(),
+                territories: normalize_territories(deal.territories.clone()),
+                exclusive: false,
+                duration_days: deal.duration_days,
+                max_copies: None,
+                max_streams: None,
+                instant: false,
+                active: true,
+            };
+            OFFER_STORAGE.with(|s| s.borrow_mut().insert(offer_id, offer));
+            offer_ids.push(offer_id);
+        }
+
+        songs.push(DdexSongResult {
+            resource_reference,
+            song_id: Some(id),
+            error: None,
+        });
+    }
+
+    Ok(DdexReport {
+        committed: true,
+        message_id: message.message_id,
+        songs,
+        offer_ids,
+        unsupported: message.unsupported,
+    })
+}
+
+// Define query functions to get owners by id
+#[ic_cdk::query]
+fn get_song_owner(id: u64) -> Result<ReturnOwner, Error> {
+    let song = match _get_song(&id) {
+        Some(song) => song,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("song id:{} could not be found", id),
+            })
+        }
+    };
+
+    match _get_owner(&song.owner_id) {
+        Some(owner) => Ok(ReturnOwner {
+            id: owner.id,
+            name: owner.name,
+            email: owner.email,
+        }),
+        None => Err(Error::NotFound {
+            msg: format!("owner id:{} could not be found", song.owner_id),
+        }),
+    }
+}
+
+#[ic_cdk::update]
+fn update_song(payload: UpdateSongPayload) -> Result<Song, Error> {
+    let song = match _get_song(&payload.id) {
+        Some(song) => song,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("song id:{} could not be found", payload.id),
+            })
+        }
+    };
+
+    let owner = match _get_owner(&song.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", song.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != payload.auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only song owner can update",
+                payload.auth_key
+            ),
+        });
+    }
+
+    let mut new_song = song.clone();
+    new_song.title = payload.title.clone();
+    new_song.artist = payload.artist;
+    new_song.year = payload.year;
+    new_song.genre = payload.genre;
+    new_song.price = payload.price;
+    new_song.rights = normalize_rights(payload.rights);
+    new_song.isrc = payload.isrc;
+    new_song.iswc = payload.iswc;
+    new_song.contributors = payload.contributors;
+    new_song.tags = payload.tags;
+    new_song.moods = payload.moods;
+    new_song.tempo = payload.tempo;
+    new_song.instruments = payload.instruments;
+    new_song.duration_secs = payload.duration_secs;
+    new_song.bpm = payload.bpm;
+    new_song.key = payload.key;
+    new_song.instrumental = payload.instrumental;
+    new_song.explicit = payload.explicit;
+    new_song.language = payload.language;
+
+    check_new_song(&mut new_song, Some(payload.id), &mut Vec::new())?;
+    index_song_isrc(song.isrc.as_ref(), &new_song);
+    index_song_catalog(Some(&song), Some(&new_song));
+
+    match SONG_STORAGE.with(|s| s.borrow_mut().insert(payload.id, new_song.clone())) {
+        Some(_) => {
+            certify_song_licenses(payload.id);
+            Ok(new_song)
+        }
+        None => Err(Error::InternalError {
+            msg: format!(
+                "song title:{} id: {} could not be updated",
+                payload.title, payload.id
+            ),
+        }),
+    }
+}
+
+#[ic_cdk::update]
+fn delete_song(auth_key: String, id: u64) -> Result<Song, Error> {
+    let song = match _get_song(&id) {
+        Some(song) => song,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("song id:{} could not be found", id),
+            })
+        }
+    };
+
+    let owner = match _get_owner(&song.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", song.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only song owner can delete",
+                auth_key
+            ),
+        });
+    }
+
+    match remove_song_from_owner(id) {
+        Ok(_) => (),
+        Err(e) => return Err(e),
+    }
+
+    match remove_song_from_licensee(id) {
+        Ok(_) => (),
+        Err(e) => return Err(e),
+    }
+
+    remove_song_offers(id);
+    remove_song_from_releases(id);
+    remove_entity_assets(id);
+
+    ISRC_INDEX.with(|i| {
+        if let Some(isrc) = &song.isrc {
+            i.borrow_mut().remove(&isrc_key(isrc));
+        }
+    });
+    index_song_catalog(Some(&song), None);
+
+    match SONG_STORAGE.with(|s| s.borrow_mut().remove(&id)) {
+        Some(song) => {
+            // The song's licenses are kept, but their certificates no longer show its details
+            certify_song_licenses(id);
+            Ok(song)
+        }
+        None => Err(Error::InternalError {
+            msg: format!("song id:{} could not be deleted", id),
+        }),
+    }
+}
+
+// Define query functions to get an owner's public profile
+#[ic_cdk::query]
+fn get_owner_profile(id: u64) -> Result<OwnerProfile, Error> {
+    match _get_owner(&id) {
+        Some(owner) => Ok(OwnerProfile {
+            song_ids: owner_song_ids(&owner),
+            id: owner.id,
+            name: owner.name,
+            release_ids: find_releases(|release| release.owner_id == id)
+                .into_iter()
+                .map(|release| release.id)
+                .collect(),
+        }),
+        None => Err(Error::NotFound {
+            msg: format!("owner id:{} could not be found", id),
+        }),
+    }
+}
+
+#[ic_cdk::query]
+fn get_owner_songs(id: u64) -> Result<Vec<Song>, Error> {
+    let songs = find_songs(|song| song.owner_id == id);
+
+    match songs.len() {
+        0 => Err(Error::NotFound {
+            msg: format!("no songs could be found for owner id:{}", id),
+        }),
+        _ => Ok(songs),
+    }
+}
+
+fn _get_owner(id: &u64) -> Option<Owner> {
+    OWNER_STORAGE.with(|s| s.borrow().get(id))
+}
+
+fn check_owner_size(owner: &Owner) -> Result<(), Error> {
+    match Encode!(owner).map_or(true, |bytes| bytes.len() > Owner::MAX_SIZE as usize) {
+        true => Err(validation_error(
+            "owner",
+            format!(
+                "owner id:{} exceeds the maximum size of {} bytes",
+                owner.id,
+                Owner::MAX_SIZE
+            ),
+        )),
+        false => Ok(()),
+    }
+}
+
+// Song ids of an owner: those still listed on the owner record, then the indexed ones
+fn owner_song_ids(owner: &Owner) -> Vec<u64> {
+    let mut song_ids = owner.song_ids.clone();
+    OWNER_SONG_INDEX.with(|i| {
+        for ((_, song_id), _) in i.borrow().range((owner.id, 0)..=(owner.id, u64::MAX)) {
+            if !song_ids.contains(&song_id) {
+                song_ids.push(song_id);
+            }
+        }
+    });
+    song_ids
+}
+
+fn add_song_to_owner(owner_id: u64, song_id: u64) -> Result<(), Error> {
+    if _get_owner(&owner_id).is_none() {
+        return Err(Error::NotFound {
+            msg: format!("owner id:{} could not be found", owner_id),
+        });
+    }
+
+    OWNER_SONG_INDEX.with(|i| i.borrow_mut().insert((owner_id, song_id), ()));
+    Ok(())
+}
+
+#[ic_cdk::update]
+fn create_owner(payload: OwnerPayload) -> Result<Owner, Error> {
+    let (name, email) = check_party(&payload.name, &payload.email, &payload.auth_key)?;
+
+    // Increment the global ID counter to get a new unique ID
+    let id = ID_COUNTER
+        .with(|counter| {
+            let current_id = *counter.borrow().get();
+            counter.borrow_mut().set(current_id + 1)
+        })
+        .expect("Cannot increment Ids");
+
+    let owner = Owner {
+        id,
+        name,
+        email,
+        auth_key: payload.auth_key.clone(),
+        song_ids: Vec::new(),
+        license_ids: Vec::new(),
+    };
+    check_owner_size(&owner)?;
+
+    match OWNER_STORAGE.with(|s| s.borrow_mut().insert(id, owner.clone())) {
+        None => Ok(owner),
+        Some(_) => Err(Error::InternalError {
+            msg: format!("owner name:{} could not be created", payload.name),
+        }),
+    }
+}
+
+// Current owner offers a song to another owner; nothing changes until the new owner accepts
+#[ic_cdk::update]
+fn offer_song_transfer(payload: SongTransferPayload) -> Result<Song, Error> {
+    let song = match _get_song(&payload.song_id) {
+        Some(song) => song,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("song id:{} could not be found", payload.song_id),
+            })
+        }
+    };
+
+    let owner = match _get_owner(&song.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", song.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != payload.auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only song owner can transfer",
+                payload.auth_key
+            ),
+        });
+    }
+
+    if payload.new_owner_id == song.owner_id || _get_owner(&payload.new_owner_id).is_none() {
+        return Err(validation_error(
+            "new_owner_id",
+            format!(
+                "owner id:{} is not a valid transfer recipient",
+                payload.new_owner_id
+            ),
+        ));
+    }
+
+    let mut new_song = song.clone();
+    new_song.pending_transfer = Some(SongTransfer {
+        new_owner_id: payload.new_owner_id,
+        pending_requests: payload.pending_requests,
+    });
+
+    match SONG_STORAGE.with(|s| s.borrow_mut().insert(payload.song_id, new_song.clone())) {
+        Some(_) => Ok(new_song),
+        None => Err(Error::InternalError {
+            msg: format!(
+                "transfer of song id:{} could not be offered",
+                payload.song_id
+            ),
+        }),
+    }
+}
+
+// Either owner withdraws or declines a pending song transfer
+#[ic_cdk::update]
+fn cancel_song_transfer(auth_key: String, song_id: u64) -> Result<Song, Error> {
+    let song = match _get_song(&song_id) {
+        Some(song) => song,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("song id:{} could not be found", song_id),
+            })
+        }
+    };
+
+    let transfer = match &song.pending_transfer {
+        Some(transfer) => transfer.clone(),
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("no transfer is pending for song id:{}", song_id),
+            })
+        }
+    };
+
+    let authorized = [song.owner_id, transfer.new_owner_id]
+        .iter()
+        .any(|id| _get_owner(id).is_some_and(|owner| owner.auth_key == auth_key));
+    if !authorized {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only the current or new owner can cancel",
+                auth_key
+            ),
+        });
+    }
+
+    let mut new_song = song.clone();
+    new_song.pending_transfer = None;
+
+    match SONG_STORAGE.with(|s| s.borrow_mut().insert(song_id, new_song.clone())) {
+        Some(_) => Ok(new_song),
+        None => Err(Error::InternalError {
+            msg: format!("transfer of song id:{} could not be cancelled", song_id),
+        }),
+    }
+}
+
+// New owner accepts a song. Approved licenses stay valid and move to the new owner along
+// with the song's offers, so renewals and future sales go to them; pending requests follow
+// the policy chosen when the transfer was offered.
+#[ic_cdk::update]
+fn accept_song_transfer(auth_key: String, song_id: u64) -> Result<Song, Error> {
+    let song = match _get_song(&song_id) {
+        Some(song) => song,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("song id:{} could not be found", song_id),
+            })
+        }
+    };
+
+    let transfer = match &song.pending_transfer {
+        Some(transfer) => transfer.clone(),
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("no transfer is pending for song id:{}", song_id),
+            })
+        }
+    };
+
+    let new_owner = match _get_owner(&transfer.new_owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", transfer.new_owner_id),
+            })
+        }
+    };
+
+    if new_owner.auth_key != auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only the new owner can accept",
+                auth_key
+            ),
+        });
+    }
+
+    let mut old_owner = match _get_owner(&song.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", song.owner_id),
+            })
+        }
+    };
+
+    let licenses: Vec<License> = LICENSE_STORAGE.with(|s| {
+        s.borrow()
+            .iter()
+            .map(|(_, license)| license)
+            .filter(|license| license.song_id == song_id)
+            .collect()
+    });
+    let moved_license_ids: Vec<u64> = licenses
+        .iter()
+        .filter(|license| license.approved)
+        .map(|license| license.id)
+        .collect();
+
+    // The old owner record only shrinks and the new owner's songs and licenses are indexed,
+    // so nothing here can fail once the transfer has been checked
+    old_owner.song_ids.retain(|id| *id != song_id);
+    old_owner
+        .license_ids
+        .retain(|id| !moved_license_ids.contains(id));
+
+    OWNER_SONG_INDEX.with(|i| {
+        let mut index = i.borrow_mut();
+        index.remove(&(old_owner.id, song_id));
+        index.insert((new_owner.id, song_id), ());
+    });
+    OWNER_LICENSE_INDEX.with(|i| {
+        let mut index = i.borrow_mut();
+        for license_id in &moved_license_ids {
+            index.remove(&(old_owner.id, *license_id));
+            index.insert((new_owner.id, *license_id), ());
+        }
+    });
+    OWNER_STORAGE.with(|s| s.borrow_mut().insert(old_owner.id, old_owner));
+    remove_song_from_releases(song_id);
+
+    for license in licenses {
+        let pending = !license.approved && !license.revoked;
+        if pending && transfer.pending_requests == PendingRequestPolicy::Cancel {
+            cancel_license_request(&license);
+            continue;
+        }
+
+        let mut new_license = license.clone();
+        new_license.owner_id = new_owner.id;
+        store_license(new_license);
+    }
+
+    let offers_vec: Vec<(u64, LicenseOffer)> = OFFER_STORAGE.with(|s| s.borrow().iter().collect());
+    for (_, offer) in offers_vec {
+        if offer.song_id == song_id {
+            let mut new_offer = offer.clone();
+            new_offer.owner_id = new_owner.id;
+            OFFER_STORAGE.with(|s| s.borrow_mut().insert(offer.id, new_offer));
+        }
+    }
+
+    // Auto-approval rules are terms the previous owner set; the new owner opts in again
+    let mut new_song = song.clone();
+    new_song.owner_id = new_owner.id;
+    new_song.pending_transfer = None;
+    new_song.auto_approval = None;
+
+    match SONG_STORAGE.with(|s| s.borrow_mut().insert(song_id, new_song.clone())) {
+        Some(_) => Ok(new_song),
+        None => Err(Error::InternalError {
+            msg: format!("song id:{} could not be transferred", song_id),
+        }),
+    }
+}
+
+// Remove a license request that was never approved, unlinking it from the license it renews
+fn cancel_license_request(license: &License) {
+    if let Some(mut previous) = license.previous_license_id.and_then(|id| _get_license(&id)) {
+        previous.next_license_id = None;
+        store_license(previous);
+    }
+
+    LICENSE_STORAGE.with(|s| s.borrow_mut().remove(&license.id));
+    if let Some(key) = license_end_key(license) {
+        LICENSE_END_INDEX.with(|i| i.borrow_mut().remove(&key));
+    }
+    uncertify_license(license.id);
+    remove_license_messages(license);
+}
+
+#[ic_cdk::query]
+fn get_license(id: u64) -> Result<License, Error> {
+    match _get_license(&id) {
+        Some(license) => Ok(license),
+        None => Err(Error::NotFound {
+            msg: format!("license id:{} could not be found", id),
+        }),
+    }
+}
+
+#[ic_cdk::query]
+fn get_owner_license_requests(id: u64) -> Result<Vec<License>, Error> {
+    let licenses_vec: Vec<(u64, License)> = LICENSE_STORAGE.with(|s| s.borrow().iter().collect());
+    let licenses: Vec<License> = licenses_vec
+        .into_iter()
+        .map(|(_, license)| license)
+        .collect();
+    let mut owner_licenses: Vec<License> = Vec::new();
+
+    for license in licenses {
+        if license.owner_id == id {
+            owner_licenses.push(license);
+        }
+    }
+
+    match owner_licenses.len() {
+        0 => Err(Error::NotFound {
+            msg: format!("no licenses could be found for owner id:{}", id),
+        }),
+        _ => Ok(owner_licenses),
+    }
+}
+
+#[ic_cdk::query]
+fn get_licensee_licenses(id: u64) -> Result<Vec<License>, Error> {
+    let licenses_vec: Vec<(u64, License)> = LICENSE_STORAGE.with(|s| s.borrow().iter().collect());
+    let licenses: Vec<License> = licenses_vec
+        .into_iter()
+        .map(|(_, license)| license)
+        .collect();
+    let mut licensee_licenses: Vec<License> = Vec::new();
+
+    for license in licenses {
+        if license.licensee_id == id {
+            licensee_licenses.push(license);
+        }
+    }
+
+    match licensee_licenses.len() {
+        0 => Err(Error::NotFound {
+            msg: format!("no licenses could be found for licensee id:{}", id),
+        }),
+        _ => Ok(licensee_licenses),
+    }
+}
+
+#[ic_cdk::update]
+fn create_license_request(payload: LicensePayload) -> Result<License, Error> {
+    // Auto-approved requests are paid from the licensee's balance
+    authorize_party(
+        MessageParty::Licensee,
+        payload.licensee_id,
+        &payload.auth_key,
+    )?;
+
+    // The id the license gets once it passes validation
+    let id = ID_COUNTER.with(|counter| *counter.borrow().get());
+
+    let song = match _get_song(&payload.song_id) {
+        Some(song) => song,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("song id:{} could not be found", payload.song_id),
+            })
+        }
+    };
+
+    let license = License {
+        id,
+        song_id: payload.song_id,
+        owner_id: song.owner_id,
+        licensee_id: payload.licensee_id,
+        approved: false,
+        price: payload.price,
+        start_date: payload.start_date,
+        end_date: payload.end_date,
+        exclusive: payload.exclusive,
+        media: payload.media,
+        territories: normalize_territories(payload.territories),
+        license_type: payload.license_type,
+        max_copies: payload.max_copies,
+        max_streams: payload.max_streams,
+        attribution: song.rights.attribution.clone(),
+        offer_id: None,
+        previous_license_id: None,
+        next_license_id: None,
+        auto_renew: false,
+        parent_license_id: None,
+        pending_transfer_to: None,
+        revoked: false,
+        release_id: None,
+        bundle_id: None,
+        transferable: false,
+        counter_price: None,
+    };
+
+    validate_license_scope(&license)?;
+    check_usage_rights(&license, &song.rights)?;
+    check_exclusive_conflicts(&license)?;
+
+    // Increment the global ID counter to get a new unique ID
+    ID_COUNTER
+        .with(|counter| counter.borrow_mut().set(id + 1))
+        .expect("Cannot increment Ids");
+
+    // Without payment the request waits for the owner like any other
+    let license = match &song.auto_approval {
+        Some(rules)
+            if auto_approval_applies(&license, &song, rules)?
+                && check_payment(&license).is_ok() =>
+        {
+            let license = activate_license(&license, license.price)?;
+            collect_payment(&license);
+            license
+        }
+        _ => license,
+    };
+
+    match store_license(license.clone()) {
+        None => Ok(license),
+        Some(_) => Err(Error::InternalError {
+            msg: format!("license id:{} could not be created", id),
+        }),
+    }
+}
+
+#[ic_cdk::update]
+fn approve_license(payload: Approvepayload) -> Result<License, Error> {
+    let license = match _get_license(&payload.license_id) {
+        Some(license) => license,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("license id:{} could not be found", payload.license_id),
+            })
+        }
+    };
+
+    let owner = match _get_owner(&license.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", license.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != payload.auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only song owner can approve",
+                payload.auth_key
+            ),
+        });
+    }
+
+    if license.approved {
+        return Err(Error::Conflict {
+            msg: format!(
+                "license id:{} has already been approved",
+                payload.license_id
+            ),
+            license_ids: vec![payload.license_id],
+        });
+    }
+
+    if let Some(bundle_id) = license.bundle_id {
+        return Err(Error::Conflict {
+            msg: format!(
+                "license id:{} is part of release bundle id:{}, approve the bundle instead",
+                payload.license_id, bundle_id
+            ),
+            license_ids: Vec::new(),
+        });
+    }
+
+    if let Some(parent_id) = license.parent_license_id {
+        if !_get_license(&parent_id).is_some_and(|parent| parent.approved) {
+            return Err(Error::Conflict {
+                msg: format!(
+                    "parent license id:{} of sublicense id:{} is no longer approved",
+                    parent_id, payload.license_id
+                ),
+                license_ids: vec![parent_id],
+            });
+        }
+    }
+
+    let new_license = activate_license(&license, payload.cost)?;
+
+    match store_license(new_license.clone()) {
+        Some(_) => Ok(new_license),
+        None => Err(Error::InternalError {
+            msg: format!("license id:{} could not be approved", payload.license_id),
+        }),
+    }
+}
+
+// Owner answers a license request with another price. Bundled licenses and sublicenses are
+// approved as they were requested.
+#[ic_cdk::update]
+fn counter_license_request(payload: CounterOfferPayload) -> Result<License, Error> {
+    let license = match _get_license(&payload.license_id) {
+        Some(license) => license,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("license id:{} could not be found", payload.license_id),
+            })
+        }
+    };
+
+    authorize_party(MessageParty::Owner, license.owner_id, &payload.auth_key)?;
+
+    if license.approved || license.revoked {
+        return Err(Error::Conflict {
+            msg: format!(
+                "license id:{} is no longer a pending request",
+                payload.license_id
+            ),
+            license_ids: vec![payload.license_id],
+        });
+    }
+
+    if license.bundle_id.is_some() || license.parent_license_id.is_some() {
+        return Err(Error::Conflict {
+            msg: format!(
+                "license id:{} is part of a bundle or a sublicense and cannot be countered",
+                payload.license_id
+            ),
+            license_ids: Vec::new(),
+        });
+    }
+
+    let mut new_license = license.clone();
+    new_license.counter_price = Some(payload.price);
+
+    match store_license(new_license.clone()) {
+        Some(_) => Ok(new_license),
+        None => Err(Error::InternalError {
+            msg: format!("license id:{} could not be countered", payload.license_id),
+        }),
+    }
+}
+
+// Licensee accepts the owner's counter-offer, which approves the license at that price
+#[ic_cdk::update]
+fn accept_counter_offer(payload: ProtectedPayload) -> Result<License, Error> {
+    let license = match _get_license(&payload.license_id) {
+        Some(license) => license,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("license id:{} could not be found", payload.license_id),
+            })
+        }
+    };
+
+    authorize_party(
+        MessageParty::Licensee,
+        license.licensee_id,
+        &payload.auth_key,
+    )?;
+
+    let price = match license.counter_price {
+        Some(price) if !license.approved && !license.revoked => price,
+        _ => {
+            return Err(Error::Conflict {
+                msg: format!(
+                    "license id:{} has no counter-offer to accept",
+                    payload.license_id
+                ),
+                license_ids: vec![payload.license_id],
+            })
+        }
+    };
+
+    let new_license = activate_license(&license, price)?;
+
+    match store_license(new_license.clone()) {
+        Some(_) => Ok(new_license),
+        None => Err(Error::InternalError {
+            msg: format!("license id:{} could not be approved", payload.license_id),
+        }),
+    }
+}
+
+// Check whether a license request satisfies the song's auto-approval rules
+fn auto_approval_applies(
+    license: &License,
+    song: &Song,
+    rules: &AutoApprovalRules,
+) -> Result<bool, Error> {
+    let (start, end) = license_window(license)?;
+    let min_price = rules.min_price.unwrap_or(song.price);
+
+    Ok(
+        (rules.licensee_ids.is_empty() || rules.licensee_ids.contains(&license.licensee_id))
+            && license.price >= min_price
+            && (rules.allow_exclusive || !license.exclusive)
+            && rules
+                .max_duration_days
+                .is_none_or(|max| end - start < max as i64),
+    )
+}
+
+#[ic_cdk::update]
+fn set_auto_approval(payload: AutoApprovalPayload) -> Result<Song, Error> {
+    let song = match _get_song(&payload.song_id) {
+        Some(song) => song,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("song id:{} could not be found", payload.song_id),
+            })
+        }
+    };
+
+    let owner = match _get_owner(&song.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", song.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != payload.auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only song owner can set auto-approval",
+                payload.auth_key
+            ),
+        });
+    }
+
+    if let Some(rules) = &payload.rules {
+        if rules.licensee_ids.len() > MAX_AUTO_APPROVAL_LICENSEES {
+            return Err(validation_error(
+                "licensee_ids",
+                format!(
+                    "auto-approval is limited to at most {} licensees",
+                    MAX_AUTO_APPROVAL_LICENSEES
+                ),
+            ));
+        }
+    }
+
+    let mut new_song = song.clone();
+    new_song.auto_approval = payload.rules;
+    check_song_size(&new_song)?;
+
+    match SONG_STORAGE.with(|s| s.borrow_mut().insert(payload.song_id, new_song.clone())) {
+        Some(_) => Ok(new_song),
+        None => Err(Error::InternalError {
+            msg: format!(
+                "auto-approval for song id:{} could not be updated",
+                payload.song_id
+            ),
+        }),
+    }
+}
+
+// Controllers choose the ICRC-2 ledger licenses are paid with, or turn payments off
+#[ic_cdk::update]
+fn set_payment_ledger(ledger: Option<Principal>) -> Result<(), Error> {
+    authorize_controller("set the payment ledger")?;
+
+    PAYMENT_CONFIG
+        .with(|c| c.borrow_mut().set(PaymentConfig { ledger }))
+        .map(|_| ())
+        .map_err(|_| Error::InternalError {
+            msg: "payment ledger could not be stored".to_string(),
+        })
+}
+
+#[ic_cdk::query]
+fn get_payment_ledger() -> Option<Principal> {
+    PAYMENT_CONFIG.with(|c| c.borrow().get().ledger)
+}
+
+fn payment_ledger() -> Result<Principal, Error> {
+    get_payment_ledger().ok_or(Error::PaymentFailed {
+        msg: "payments are not enabled".to_string(),
+    })
+}
+
+#[ic_cdk::query]
+fn get_balance(party: MessageParty, id: u64, auth_key: String) -> Result<u64, Error> {
+    authorize_party(party, id, &auth_key)?;
+    Ok(balance(party, id))
+}
+
+fn balance(party: MessageParty, id: u64) -> u64 {
+    BALANCES
+        .with(|b| b.borrow().get(&(party as u8, id)))
+        .unwrap_or(0)
+}
+
+fn set_balance(party: MessageParty, id: u64, amount: u64) {
+    BALANCES.with(|b| match amount {
+        0 => b.borrow_mut().remove(&(party as u8, id)),
+        _ => b.borrow_mut().insert((party as u8, id), amount),
+    });
+}
+
+fn credit_balance(party: MessageParty, id: u64, amount: u64) {
+    set_balance(party, id, balance(party, id).saturating_add(amount));
+}
+
+// Licensees fund their balance from the caller's ledger account, which must have approved
+// the canister to spend the amount (ICRC-2)
+#[ic_cdk::update]
+async fn deposit(payload: DepositPayload) -> Result<u64, Error> {
+    authorize_party(
+        MessageParty::Licensee,
+        payload.licensee_id,
+        &payload.auth_key,
+    )?;
+    let ledger = payment_ledger()?;
+    if payload.amount == 0 {
+        return Err(validation_error(
+            "amount",
+            "amount must be greater than 0".to_string(),
+        ));
+    }
+
+    let from = icrc7::Account {
+        owner: runtime::caller(),
+        subaccount: None,
+    };
+    ledger::transfer_from(ledger, from, payload.amount)
+        .await
+        .map_err(|msg| Error::PaymentFailed { msg })?;
+
+    credit_balance(MessageParty::Licensee, payload.licensee_id, payload.amount);
+    Ok(balance(MessageParty::Licensee, payload.licensee_id))
+}
+
+// Owners withdraw their earnings and licensees their unspent funds; the ledger fee is paid
+// out of the amount
+#[ic_cdk::update]
+async fn withdraw(payload: WithdrawPayload) -> Result<u64, Error> {
+    authorize_party(payload.party, payload.id, &payload.auth_key)?;
+    let ledger = payment_ledger()?;
+    if !payload.to.is_valid() {
+        return Err(validation_error(
+            "to",
+            "subaccount must be 32 bytes".to_string(),
+        ));
+    }
+
+    let fee = ledger::fee(ledger)
+        .await
+        .map_err(|msg| Error::PaymentFailed { msg })?;
+    if payload.amount <= fee {
+        return Err(validation_error(
+            "amount",
+            format!("amount must be greater than the ledger fee of {}", fee),
+        ));
+    }
+
+    // The balance is taken before the transfer so concurrent withdrawals cannot spend it twice
+    let available = balance(payload.party, payload.id);
+    if available < payload.amount {
+        return Err(Error::PaymentFailed {
+            msg: format!(
+                "balance of {} is less than the amount of {}",
+                available, payload.amount
+            ),
+        });
+    }
+    set_balance(payload.party, payload.id, available - payload.amount);
+
+    match ledger::transfer(ledger, payload.to, payload.amount, fee).await {
+        Ok(_) => Ok(balance(payload.party, payload.id)),
+        Err(msg) => {
+            credit_balance(payload.party, payload.id, payload.amount);
+            Err(Error::PaymentFailed { msg })
+        }
+    }
+}
+
+// Check that a license's price can be paid from its licensee's balance
+fn check_payment(license: &License) -> Result<(), Error> {
+    payment_ledger()?;
+
+    let available = balance(MessageParty::Licensee, license.licensee_id);
+    match available >= license.price as u64 {
+        true => Ok(()),
+        false => Err(Error::PaymentFailed {
+            msg: format!(
+                "balance of {} is less than the price of {} for license id:{}",
+                available, license.price, license.id
+            ),
+        }),
+    }
+}
+
+// Move a license's price from its licensee to its owner, once check_payment passed
+fn collect_payment(license: &License) {
+    let price = license.price as u64;
+    let available = balance(MessageParty::Licensee, license.licensee_id);
+    set_balance(
+        MessageParty::Licensee,
+        license.licensee_id,
+        available - price,
+    );
+    credit_balance(MessageParty::Owner, license.owner_id, price);
+
+    notify(
+        MessageParty::Owner,
+        license.owner_id,
+        NotificationKind::PaymentReceived,
+        license,
+        format!(
+            "licensee id:{} paid {} for license id:{}",
+            license.licensee_id, price, license.id
+        ),
+    );
+}
+
+// Mark a license as approved at the given price and link it to its owner and licensee.
+// The caller is responsible for storing the returned license.
+fn activate_license(license: &License, price: u32) -> Result<License, Error> {
+    check_exclusive_conflicts(license)?;
+
+    let mut new_license = license.clone();
+    new_license.approved = true;
+    new_license.revoked = false;
+    new_license.price = price;
+    new_license.counter_price = None;
+
+    add_license_to_owner(license.owner_id, license.id)?;
+    add_license_to_licensee(license.licensee_id, license.id)?;
+
+    Ok(new_license)
+}
+
+#[ic_cdk::update]
+fn revoke_license(payload: ProtectedPayload) -> Result<License, Error> {
+    let license = match _get_license(&payload.license_id) {
+        Some(license) => license,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("license id:{} could not be found", payload.license_id),
+            })
+        }
+    };
+
+    let owner = match _get_owner(&license.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", license.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != payload.auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only song owner can revoke",
+                payload.auth_key
+            ),
+        });
+    }
+
+    let new_license = deactivate_license(&license)?;
+
+    // Sublicenses cannot outlive the license they were granted under
+    for sublicense in get_descendant_licenses(license.id) {
+        if sublicense.approved {
+            let revoked = deactivate_license(&sublicense)?;
+            store_license(revoked);
+        }
+    }
+
+    match store_license(new_license.clone()) {
+        Some(_) => Ok(new_license),
+        None => Err(Error::InternalError {
+            msg: format!("license id:{} could not be revoked", payload.license_id),
+        }),
+    }
+}
+
+fn _get_license(id: &u64) -> Option<License> {
+    LICENSE_STORAGE.with(|s| s.borrow().get(id))
+}
+
+// Mark a license as no longer approved and unlink it from its owner and licensee.
+// The caller is responsible for storing the returned license.
+fn deactivate_license(license: &License) -> Result<License, Error> {
+    let mut new_license = license.clone();
+    new_license.approved = false;
+    new_license.revoked = true;
+    new_license.pending_transfer_to = None;
+
+    remove_license_from_owner(license.owner_id, license.id)?;
+    remove_license_from_licensee(license.licensee_id, license.id)?;
+
+    Ok(new_license)
+}
+
+// Collect the sublicenses granted under a license, and theirs in turn
+fn get_descendant_licenses(id: u64) -> Vec<License> {
+    let licenses_vec: Vec<(u64, License)> = LICENSE_STORAGE.with(|s| s.borrow().iter().collect());
+    let mut descendants: Vec<License> = Vec::new();
+    let mut parents: Vec<u64> = vec![id];
+
+    while let Some(parent_id) = parents.pop() {
+        for (_, license) in &licenses_vec {
+            if license.parent_license_id == Some(parent_id) {
+                parents.push(license.id);
+                descendants.push(license.clone());
+            }
+        }
+    }
+
+    descendants
+}
+
+fn is_ancestor_license(ancestor_id: u64, license: &License) -> bool {
+    let mut parent_id = license.parent_license_id;
+    while let Some(id) = parent_id {
+        if id == ancestor_id {
+            return true;
+        }
+        parent_id = _get_license(&id).and_then(|parent| parent.parent_license_id);
+    }
+    false
+}
+
+#[ic_cdk::query]
+fn get_sublicenses(id: u64) -> Result<Vec<License>, Error> {
+    let sublicenses: Vec<License> = get_descendant_licenses(id)
+        .into_iter()
+        .filter(|license| license.parent_license_id == Some(id))
+        .collect();
+
+    match sublicenses.len() {
+        0 => Err(Error::NotFound {
+            msg: format!("no sublicenses could be found for license id:{}", id),
+        }),
+        _ => Ok(sublicenses),
+    }
+}
+
+// Licensee asks to hand an approved license over to another licensee; the owner must consent
+#[ic_cdk::update]
+fn request_license_transfer(payload: TransferPayload) -> Result<License, Error> {
+    let license = match _get_license(&payload.license_id) {
+        Some(license) => license,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("license id:{} could not be found", payload.license_id),
+            })
+        }
+    };
+
+    let licensee = match _get_licensee(&license.licensee_id) {
+        Some(licensee) => licensee,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("licensee id:{} could not be found", license.licensee_id),
+            })
+        }
+    };
+
+    if !licensee_key_matches(&licensee, &payload.auth_key) {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only the licensee can transfer",
+                payload.auth_key
+            ),
+        });
+    }
+
+    if !license.approved {
+        return Err(Error::Conflict {
+            msg: format!(
+                "license id:{} is not approved and cannot be transferred",
+                payload.license_id
+            ),
+            license_ids: Vec::new(),
+        });
+    }
+
+    if payload.new_licensee_id == license.licensee_id
+        || _get_licensee(&payload.new_licensee_id).is_none()
+    {
+        return Err(validation_error(
+            "new_licensee_id",
+            format!(
+                "licensee id:{} is not a valid transfer recipient",
+                payload.new_licensee_id
+            ),
+        ));
+    }
+
+    let mut new_license = license.clone();
+    new_license.pending_transfer_to = Some(payload.new_licensee_id);
+
+    match store_license(new_license.clone()) {
+        Some(_) => Ok(new_license),
+        None => Err(Error::InternalError {
+            msg: format!(
+                "transfer of license id:{} could not be requested",
+                payload.license_id
+            ),
+        }),
+    }
+}
+
+// Owner consents to a pending transfer, moving the license to the new licensee
+#[ic_cdk::update]
+fn approve_license_transfer(payload: ProtectedPayload) -> Result<License, Error> {
+    let license = match _get_license(&payload.license_id) {
+        Some(license) => license,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("license id:{} could not be found", payload.license_id),
+            })
+        }
+    };
+
+    let owner = match _get_owner(&license.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", license.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != payload.auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only song owner can approve transfers",
+                payload.auth_key
+            ),
+        });
+    }
+
+    let new_licensee_id = match license.pending_transfer_to {
+        Some(id) => id,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!(
+                    "no transfer is pending for license id:{}",
+                    payload.license_id
+                ),
+            })
+        }
+    };
+
+    transfer_license(&license, new_licensee_id)
+}
+
+// Move a license to a new licensee and record the change in its chain of custody
+fn transfer_license(license: &License, new_licensee_id: u64) -> Result<License, Error> {
+    remove_license_from_licensee(license.licensee_id, license.id)?;
+    add_license_to_licensee(new_licensee_id, license.id)?;
+
+    record_custody(license.id, license.licensee_id, new_licensee_id);
+
+    let mut new_license = license.clone();
+    new_license.licensee_id = new_licensee_id;
+    new_license.pending_transfer_to = None;
+
+    match store_license(new_license.clone()) {
+        Some(_) => Ok(new_license),
+        None => Err(Error::InternalError {
+            msg: format!("license id:{} could not be transferred", license.id),
+        }),
+    }
+}
+
+fn record_custody(license_id: u64, from_licensee_id: u64, to_licensee_id: u64) {
+    CUSTODY_STORAGE.with(|s| {
+        let mut custody = s.borrow_mut();
+        let seq = custody
+            .range((license_id, 0)..=(license_id, u64::MAX))
+            .last()
+            .map_or(0, |((_, seq), _)| seq + 1);
+        custody.insert(
+            (license_id, seq),
+            CustodyTransfer {
+                license_id,
+                seq,
+                from_licensee_id,
+                to_licensee_id,
+                timestamp: runtime::time(),
+            },
+        );
+    });
+}
+
+// Define query functions to get the chain of custody of a license, oldest change first,
+// continuing after the change numbered prev
+#[ic_cdk::query]
+fn get_license_custody(
+    license_id: u64,
+    prev: Option<u64>,
+    take: Option<u32>,
+) -> Result<Vec<CustodyTransfer>, Error> {
+    if _get_license(&license_id).is_none() {
+        return Err(Error::NotFound {
+            msg: format!("license id:{} could not be found", license_id),
+        });
+    }
+
+    let take = take
+        .map_or(DEFAULT_PAGE_TAKE, |take| take as usize)
+        .min(MAX_PAGE_TAKE);
+    let start = prev.map_or(0, |prev| prev.saturating_add(1));
+
+    Ok(CUSTODY_STORAGE.with(|s| {
+        s.borrow()
+            .range((license_id, start)..=(license_id, u64::MAX))
+            .take(take)
+            .map(|(_, transfer)| transfer)
+            .collect()
+    }))
+}
+
+#[ic_cdk::update]
+fn reject_license_transfer(payload: ProtectedPayload) -> Result<License, Error> {
+    let license = match _get_license(&payload.license_id) {
+        Some(license) => license,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("license id:{} could not be found", payload.license_id),
+            })
+        }
+    };
+
+    let owner = match _get_owner(&license.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", license.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != payload.auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only song owner can reject transfers",
+                payload.auth_key
+            ),
+        });
+    }
+
+    let mut new_license = license.clone();
+    new_license.pending_transfer_to = None;
+
+    match store_license(new_license.clone()) {
+        Some(_) => Ok(new_license),
+        None => Err(Error::InternalError {
+            msg: format!(
+                "transfer of license id:{} could not be rejected",
+                payload.license_id
+            ),
+        }),
+    }
+}
+
+// Licensee requests a child license for another licensee, bounded by the parent's scope
+// and term. Sublicenses are non-exclusive and need the owner's approval like any request.
+#[ic_cdk::update]
+fn create_sublicense(payload: SublicensePayload) -> Result<License, Error> {
+    let parent = match _get_license(&payload.parent_license_id) {
+        Some(license) => license,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!(
+                    "license id:{} could not be found",
+                    payload.parent_license_id
+                ),
+            })
+        }
+    };
+
+    let licensee = match _get_licensee(&parent.licensee_id) {
+        Some(licensee) => licensee,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("licensee id:{} could not be found", parent.licensee_id),
+            })
+        }
+    };
+
+    if !licensee_key_matches(&licensee, &payload.auth_key) {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only the licensee can sublicense",
+                payload.auth_key
+            ),
+        });
+    }
+
+    if !parent.approved {
+        return Err(Error::Conflict {
+            msg: format!(
+                "license id:{} is not approved and cannot be sublicensed",
+                payload.parent_license_id
+            ),
+            license_ids: Vec::new(),
+        });
+    }
+
+    if _get_licensee(&payload.sublicensee_id).is_none() {
+        return Err(Error::NotFound {
+            msg: format!("licensee id:{} could not be found", payload.sublicensee_id),
+        });
+    }
+
+    // Increment the global ID counter to get a new unique ID
+    let id = ID_COUNTER
+        .with(|counter| {
+            let current_id = *counter.borrow().get();
+            counter.borrow_mut().set(current_id + 1)
+        })
+        .expect("Cannot increment Ids");
+
+    let mut sublicense = parent.clone();
+    sublicense.id = id;
+    sublicense.licensee_id = payload.sublicensee_id;
+    sublicense.approved = false;
+    sublicense.price = payload.price;
+    sublicense.start_date = payload.start_date;
+    sublicense.end_date = payload.end_date;
+    sublicense.exclusive = false;
+    sublicense.media = payload.media;
+    sublicense.territories = normalize_territories(payload.territories);
+    sublicense.max_copies = payload.max_copies;
+    sublicense.max_streams = payload.max_streams;
+    sublicense.offer_id = None;
+    sublicense.previous_license_id = None;
+    sublicense.next_license_id = None;
+    sublicense.auto_renew = false;
+    sublicense.parent_license_id = Some(parent.id);
+    sublicense.pending_transfer_to = None;
+    sublicense.revoked = false;
+    sublicense.release_id = None;
+    sublicense.bundle_id = None;
+    sublicense.transferable = false;
+
+    validate_license_scope(&sublicense)?;
+    check_within_parent(&sublicense, &parent)?;
+    check_exclusive_conflicts(&sublicense)?;
+
+    match store_license(sublicense.clone()) {
+        None => Ok(sublicense),
+        Some(_) => Err(Error::InternalError {
+            msg: format!("sublicense id:{} could not be created", id),
+        }),
+    }
+}
+
+fn check_within_parent(license: &License, parent: &License) -> Result<(), Error> {
+    let (start, end) = license_window(license)?;
+    let (parent_start, parent_end) = license_window(parent)?;
+
+    let rights = UsageRights {
+        license_types: vec![parent.license_type],
+        media: parent.media.clone(),
+        territories: parent.territories.clone(),
+        max_copies: parent.max_copies,
+        max_streams: parent.max_streams,
+        attribution: parent.attribution.clone(),
+        exclusive_allowed: false,
+    };
+    check_usage_rights(license, &rights)?;
+
+    if start < parent_start || end > parent_end {
+        return Err(validation_error(
+            "end_date",
+            format!(
+                "sublicense term must fall within parent license id:{} ({} to {})",
+                parent.id, parent.start_date, parent.end_date
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+// Build a pending successor that keeps the terms of a license and starts the day after it ends.
+// Only an approved successor blocks a new one; a pending one is replaced by link_successor.
+fn successor_license(
+    license: &License,
+    id: u64,
+    end_date: Option<String>,
+    price: Option<u32>,
+) -> Result<License, Error> {
+    let (start, end) = license_window(license)?;
+
+    if let Some(next) = license.next_license_id.and_then(|id| _get_license(&id)) {
+        if next.approved {
+            return Err(Error::Conflict {
+                msg: format!(
+                    "license id:{} already has a successor license id:{}",
+                    license.id, next.id
+                ),
+                license_ids: vec![next.id],
+            });
+        }
+    }
+
+    let mut successor = license.clone();
+    successor.id = id;
+    successor.approved = false;
+    successor.price = price.unwrap_or(license.price);
+    successor.start_date = format_date(end + 1);
+    successor.end_date = end_date.unwrap_or(format_date(end + 1 + (end - start)));
+    successor.previous_license_id = Some(license.id);
+    successor.next_license_id = None;
+    successor.revoked = false;
+    successor.bundle_id = None;
+
+    validate_license_scope(&successor)?;
+    Ok(successor)
+}
+
+// Store a successor license and link it from the license it renews, dropping a renewal
+// request that was still waiting for approval
+fn link_successor(license: &License, successor: &License) {
+    if let Some(pending) = license.next_license_id.and_then(|id| _get_license(&id)) {
+        if !pending.approved && !pending.revoked {
+            cancel_license_request(&pending);
+        }
+    }
+
+    let mut previous = license.clone();
+    previous.next_license_id = Some(successor.id);
+
+    store_license(successor.clone());
+    store_license(previous);
+}
+
+// Licensee requests a renewal; it is approved immediately when the owner enabled auto-renew
+// on the license or the song's auto-approval rules match and the licensee pays. A new request
+// replaces one still waiting for approval.
+#[ic_cdk::update]
+fn request_renewal(payload: RenewalPayload) -> Result<License, Error> {
+    let license = match _get_license(&payload.license_id) {
+        Some(license) => license,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("license id:{} could not be found", payload.license_id),
+            })
+        }
+    };
+
+    let licensee = match _get_licensee(&license.licensee_id) {
+        Some(licensee) => licensee,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("licensee id:{} could not be found", license.licensee_id),
+            })
+        }
+    };
+
+    if !licensee_key_matches(&licensee, &payload.auth_key) {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only the licensee can request a renewal",
+                payload.auth_key
+            ),
+        });
+    }
+
+    if !license.approved {
+        return Err(Error::Conflict {
+            msg: format!(
+                "license id:{} is not approved and cannot be renewed",
+                payload.license_id
+            ),
+            license_ids: Vec::new(),
+        });
+    }
+
+    let song = match _get_song(&license.song_id) {
+        Some(song) => song,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("song id:{} could not be found", license.song_id),
+            })
+        }
+    };
+
+    // Increment the global ID counter to get a new unique ID
+    let id = ID_COUNTER
+        .with(|counter| {
+            let current_id = *counter.borrow().get();
+            counter.borrow_mut().set(current_id + 1)
+        })
+        .expect("Cannot increment Ids");
+
+    let mut successor = successor_license(&license, id, payload.end_date, payload.price)?;
+    check_usage_rights(&successor, &song.rights)?;
+    check_exclusive_conflicts(&successor)?;
+
+    // The owner's auto-renew covers the license's own price and term only; other terms are
+    // left to the owner or the song's auto-approval rules
+    let renews_as_is = successor_license(&license, id, None, None)
+        .is_ok_and(|same| same.price == successor.price && same.end_date == successor.end_date);
+    let approved = match &song.auto_approval {
+        _ if license.auto_renew && renews_as_is => true,
+        Some(rules) => auto_approval_applies(&successor, &song, rules)?,
+        None => false,
+    };
+
+    // Either way the licensee pays; without payment the renewal waits for the owner
+    if approved && check_payment(&successor).is_ok() {
+        successor = activate_license(&successor, successor.price)?;
+        collect_payment(&successor);
+    }
+
+    link_successor(&license, &successor);
+    Ok(successor)
+}
+
+// Owner extends a license with an approved successor running to the new end date
+#[ic_cdk::update]
+fn extend_license(payload: ExtendPayload) -> Result<License, Error> {
+    let license = match _get_license(&payload.license_id) {
+        Some(license) => license,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("license id:{} could not be found", payload.license_id),
+            })
+        }
+    };
+
+    let owner = match _get_owner(&license.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", license.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != payload.auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only song owner can extend",
+                payload.auth_key
+            ),
+        });
+    }
+
+    if !license.approved {
+        return Err(Error::Conflict {
+            msg: format!(
+                "license id:{} is not approved and cannot be extended",
+                payload.license_id
+            ),
+            license_ids: Vec::new(),
+        });
+    }
+
+    // Increment the global ID counter to get a new unique ID
+    let id = ID_COUNTER
+        .with(|counter| {
+            let current_id = *counter.borrow().get();
+            counter.borrow_mut().set(current_id + 1)
+        })
+        .expect("Cannot increment Ids");
+
+    let successor = successor_license(&license, id, Some(payload.end_date), Some(payload.price))?;
+    let successor = activate_license(&successor, payload.price)?;
+
+    link_successor(&license, &successor);
+    Ok(successor)
+}
+
+#[ic_cdk::update]
+fn set_auto_renew(payload: AutoRenewPayload) -> Result<License, Error> {
+    let license = match _get_license(&payload.license_id) {
+        Some(license) => license,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("license id:{} could not be found", payload.license_id),
+            })
+        }
+    };
+
+    let owner = match _get_owner(&license.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", license.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != payload.auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only song owner can set auto-renew",
+                payload.auth_key
+            ),
+        });
+    }
+
+    let mut new_license = license.clone();
+    new_license.auto_renew = payload.auto_renew;
+
+    match store_license(new_license.clone()) {
+        Some(_) => Ok(new_license),
+        None => Err(Error::InternalError {
+            msg: format!(
+                "auto-renew for license id:{} could not be updated",
+                payload.license_id
+            ),
+        }),
+    }
+}
+
+// Owners decide whether a license may change hands as an ICRC-7 token
+#[ic_cdk::update]
+fn set_license_transferable(payload: TransferablePayload) -> Result<License, Error> {
+    let license = match _get_license(&payload.license_id) {
+        Some(license) => license,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("license id:{} could not be found", payload.license_id),
+            })
+        }
+    };
+
+    let owner = match _get_owner(&license.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", license.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != payload.auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only song owner can set transferability",
+                payload.auth_key
+            ),
+        });
+    }
+
+    if license.parent_license_id.is_some() && payload.transferable {
+        return Err(Error::Conflict {
+            msg: format!(
+                "license id:{} is a sublicense and cannot be transferable",
+                payload.license_id
+            ),
+            license_ids: Vec::new(),
+        });
+    }
+
+    let mut new_license = license.clone();
+    new_license.transferable = payload.transferable;
+
+    match store_license(new_license.clone()) {
+        Some(_) => Ok(new_license),
+        None => Err(Error::InternalError {
+            msg: format!(
+                "transferability of license id:{} could not be updated",
+                payload.license_id
+            ),
+        }),
+    }
+}
+
+// Define query functions to get the renewal chain a license belongs to, oldest first
+#[ic_cdk::query]
+fn get_license_history(id: u64) -> Result<Vec<License>, Error> {
+    let license = match _get_license(&id) {
+        Some(license) => license,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("license id:{} could not be found", id),
+            })
+        }
+    };
+
+    let mut first = license;
+    while let Some(previous) = first.previous_license_id.and_then(|id| _get_license(&id)) {
+        first = previous;
+    }
+
+    let mut history = vec![first];
+    while let Some(next) = history
+        .last()
+        .and_then(|license| license.next_license_id)
+        .and_then(|id| _get_license(&id))
+    {
+        history.push(next);
+    }
+
+    Ok(history)
+}
+
+// Parse a "YYYY-MM-DD" date into a day number (days since 1970-01-01)
+fn parse_date(date: &str) -> Option<i64> {
+    let parts: Vec<&str> = date.split('-').collect();
+    if parts.len() != 3 || parts[0].len() != 4 || parts[1].len() != 2 || parts[2].len() != 2 {
+        return None;
+    }
+
+    let year: i64 = parts[0].parse().ok()?;
+    let month: i64 = parts[1].parse().ok()?;
+    let day: i64 = parts[2].parse().ok()?;
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    // Days from civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146097 + doe - 719468)
+}
+
+// Format a day number (days since 1970-01-01) as "YYYY-MM-DD"
+fn format_date(days: i64) -> String {
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Parse the start and end date of a license, rejecting malformed or inverted windows
+fn license_window(license: &License) -> Result<(i64, i64), Error> {
+    let start = parse_date(&license.start_date).ok_or(validation_error(
+        "start_date",
+        format!(
+            "start date:{} is invalid, expected YYYY-MM-DD",
+            license.start_date
+        ),
+    ))?;
+    let end = parse_date(&license.end_date).ok_or(validation_error(
+        "end_date",
+        format!(
+            "end date:{} is invalid, expected YYYY-MM-DD",
+            license.end_date
+        ),
+    ))?;
+
+    if end < start {
+        return Err(validation_error(
+            "end_date",
+            format!(
+                "end date:{} is before start date:{}",
+                license.end_date, license.start_date
+            ),
+        ));
+    }
+
+    Ok((start, end))
+}
+
+// Territories are ISO 3166-1 alpha-2 codes, stored upper case and deduplicated
+fn normalize_territories(territories: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = territories
+        .into_iter()
+        .map(|t| t.trim().to_uppercase())
+        .collect();
+    normalized.sort();
+    normalized.dedup();
+    normalized
+}
+
+fn normalize_rights(rights: UsageRights) -> UsageRights {
+    UsageRights {
+        territories: normalize_territories(rights.territories),
+        ..rights
+    }
+}
+
+fn validate_territories(territories: &[String]) -> Result<(), Error> {
+    for territory in territories {
+        if territory.len() != 2 || !territory.chars().all(|c| c.is_ascii_uppercase()) {
+            return Err(validation_error(
+                "territories",
+                format!(
+                    "territory:{} is invalid, expected an ISO 3166-1 alpha-2 code",
+                    territory
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_license_scope(license: &License) -> Result<(), Error> {
+    license_window(license)?;
+    validate_territories(&license.territories)
+}
+
+// Requested entries must all be offered; an empty offer allows anything, but once an
+// offer is restricted an empty request (meaning "all") is no longer within it
+fn list_within<T: PartialEq>(requested: &[T], offered: &[T]) -> bool {
+    offered.is_empty() || (!requested.is_empty() && requested.iter().all(|x| offered.contains(x)))
+}
+
+fn limit_within(requested: Option<u64>, offered: Option<u64>) -> bool {
+    match (requested, offered) {
+        (_, None) => true,
+        (Some(requested), Some(offered)) => requested <= offered,
+        (None, Some(_)) => false,
+    }
+}
+
+// Check that a license only asks for rights the owner offers for the song
+fn check_usage_rights(license: &License, rights: &UsageRights) -> Result<(), Error> {
+    let mut violations: Vec<&str> = Vec::new();
+
+    if !rights.license_types.is_empty() && !rights.license_types.contains(&license.license_type) {
+        violations.push("license type");
+    }
+    if !list_within(&license.media, &rights.media) {
+        violations.push("media");
+    }
+    if !list_within(&license.territories, &rights.territories) {
+        violations.push("territories");
+    }
+    if !limit_within(license.max_copies, rights.max_copies) {
+        violations.push("max copies");
+    }
+    if !limit_within(license.max_streams, rights.max_streams) {
+        violations.push("max streams");
+    }
+    if license.exclusive && !rights.exclusive_allowed {
+        violations.push("exclusivity");
+    }
+
+    match violations.len() {
+        0 => Ok(()),
+        _ => Err(validation_error(
+            "rights",
+            format!(
+                "license exceeds the rights offered for song id:{}: {}",
+                license.song_id,
+                violations.join(", ")
+            ),
+        )),
+    }
+}
+
+// Two lists overlap when either is empty (meaning "all") or they share an entry
+fn lists_overlap<T: PartialEq>(a: &[T], b: &[T]) -> bool {
+    a.is_empty() || b.is_empty() || a.iter().any(|x| b.contains(x))
+}
+
+fn scopes_overlap(a: &License, b: &License) -> Result<bool, Error> {
+    let (a_start, a_end) = license_window(a)?;
+    let (b_start, b_end) = license_window(b)?;
+
+    Ok(a_start <= b_end
+        && b_start <= a_end
+        && lists_overlap(&a.media, &b.media)
+        && lists_overlap(&a.territories, &b.territories))
+}
+
+// Reject a license whose scope overlaps an active exclusive license on the same song.
+// An exclusive license also conflicts with any active license it overlaps.
+fn check_exclusive_conflicts(license: &License) -> Result<(), Error> {
+    let licenses_vec: Vec<(u64, License)> = LICENSE_STORAGE.with(|s| s.borrow().iter().collect());
+    let mut conflicting_ids: Vec<u64> = Vec::new();
+
+    for (_, other) in licenses_vec {
+        if other.id == license.id || other.song_id != license.song_id || !other.approved {
+            continue;
+        }
+
+        // Sublicenses are granted within their parent's rights and never conflict with them
+        if is_ancestor_license(other.id, license) || is_ancestor_license(license.id, &other) {
+            continue;
+        }
+
+        if (other.exclusive || license.exclusive) && scopes_overlap(license, &other)? {
+            conflicting_ids.push(other.id);
+        }
+    }
+
+    match conflicting_ids.len() {
+        0 => Ok(()),
+        _ => Err(Error::Conflict {
+            msg: format!(
+                "license scope overlaps exclusive rights on song id:{}",
+                license.song_id
+            ),
+            license_ids: conflicting_ids,
+        }),
+    }
+}
+
+#[ic_cdk::query]
+fn get_licensee(id: u64) -> Result<ReturnLicensee, Error> {
+    match _get_licensee(&id) {
+        Some(licensee) => Ok(ReturnLicensee {
+            licenses: licensee_license_ids(&licensee),
+            id: licensee.id,
+            name: licensee.name,
+            email: licensee.email,
+            principal: licensee.principal,
+        }),
+        None => Err(Error::NotFound {
+            msg: format!("licensee id:{} could not be found", id),
+        }),
+    }
+}
+
+fn _get_licensee(id: &u64) -> Option<Licensee> {
+    LICENSEE_STORAGE.with(|s| s.borrow().get(id))
+}
+
+#[ic_cdk::update]
+fn create_licensee(payload: LicenseePayload) -> Result<Licensee, Error> {
+    let (name, email) = check_party(&payload.name, &payload.email, &payload.auth_key)?;
+
+    // Increment the global ID counter to get a new unique ID
+    let id = ID_COUNTER
+        .with(|counter| {
+            let current_id = *counter.borrow().get();
+            counter.borrow_mut().set(current_id + 1)
+        })
+        .expect("Cannot increment Ids");
+
+    let licensee = Licensee {
+        id,
+        name,
+        email,
+        auth_key: payload.auth_key.clone(),
+        licenses: Vec::new(),
+        principal: None,
+    };
+    check_licensee_size(&licensee)?;
+
+    match LICENSEE_STORAGE.with(|s| s.borrow_mut().insert(id, licensee.clone())) {
+        None => Ok(licensee),
+        Some(_) => Err(Error::InternalError {
+            msg: format!("licensee name:{} could not be created", payload.name),
+        }),
+    }
+}
+
+fn check_licensee_size(licensee: &Licensee) -> Result<(), Error> {
+    match Encode!(licensee).map_or(true, |bytes| bytes.len() > Licensee::MAX_SIZE as usize) {
+        true => Err(validation_error(
+            "licensee",
+            format!(
+                "licensee id:{} exceeds the maximum size of {} bytes",
+                licensee.id,
+                Licensee::MAX_SIZE
+            ),
+        )),
+        false => Ok(()),
+    }
+}
+
+// Licensees stored before auth keys existed have an empty key, which never matches
+fn licensee_key_matches(licensee: &Licensee, auth_key: &str) -> bool {
+    !licensee.auth_key.is_empty() && licensee.auth_key == auth_key
+}
+
+// Controllers give a licensee a new auth key, e.g. one stored before auth keys existed
+#[ic_cdk::update]
+fn set_licensee_auth_key(id: u64, auth_key: String) -> Result<ReturnLicensee, Error> {
+    authorize_controller("set licensee auth keys")?;
+    validation::auth_key(&auth_key).map_err(|reason| validation_error("auth_key", reason))?;
+
+    let mut licensee = match _get_licensee(&id) {
+        Some(licensee) => licensee,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("licensee id:{} could not be found", id),
+            })
+        }
+    };
+
+    licensee.auth_key = auth_key;
+    check_licensee_size(&licensee)?;
+    LICENSEE_STORAGE.with(|s| s.borrow_mut().insert(id, licensee.clone()));
+    get_licensee(id)
+}
+
+// Link the calling principal to a licensee so it can hold the licensee's license tokens
+#[ic_cdk::update]
+fn set_licensee_principal(auth_key: String, id: u64) -> Result<ReturnLicensee, Error> {
+    let licensee = match _get_licensee(&id) {
+        Some(licensee) => licensee,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("licensee id:{} could not be found", id),
+            })
+        }
+    };
+
+    if !licensee_key_matches(&licensee, &auth_key) {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only the licensee can set its principal",
+                auth_key
+            ),
+        });
+    }
+
+    let caller = runtime::caller();
+    if caller == Principal::anonymous() {
+        return Err(Error::Forbidden {
+            msg: "the anonymous principal cannot hold license tokens".to_string(),
+        });
+    }
+
+    if let Some(other) = licensee_by_principal(&caller).filter(|other| other.id != id) {
+        return Err(Error::Conflict {
+            msg: format!(
+                "principal:{} is already linked to licensee id:{}",
+                caller, other.id
+            ),
+            license_ids: Vec::new(),
+        });
+    }
+
+    let mut new_licensee = licensee.clone();
+    new_licensee.principal = Some(caller);
+    LICENSEE_STORAGE.with(|s| s.borrow_mut().insert(id, new_licensee.clone()));
+
+    get_licensee(id)
+}
+
+fn licensee_by_principal(principal: &Principal) -> Option<Licensee> {
+    LICENSEE_STORAGE.with(|s| {
+        s.borrow()
+            .iter()
+            .map(|(_, licensee)| licensee)
+            .find(|licensee| licensee.principal.as_ref() == Some(principal))
+    })
+}
+
+#[ic_cdk::query]
+fn get_offer(id: u64) -> Result<LicenseOffer, Error> {
+    match _get_offer(&id) {
+        Some(offer) => Ok(offer),
+        None => Err(Error::NotFound {
+            msg: format!("offer id:{} could not be found", id),
+        }),
+    }
+}
+
+fn _get_offer(id: &u64) -> Option<LicenseOffer> {
+    OFFER_STORAGE.with(|s| s.borrow().get(id))
+}
+
+// Define query functions to get the active offers published for a song
+#[ic_cdk::query]
+fn get_song_offers(song_id: u64) -> Result<Vec<LicenseOffer>, Error> {
+    let offers_vec: Vec<(u64, LicenseOffer)> = OFFER_STORAGE.with(|s| s.borrow().iter().collect());
+    let offers: Vec<LicenseOffer> = offers_vec
+        .into_iter()
+        .map(|(_, offer)| offer)
+        .filter(|offer| offer.song_id == song_id && offer.active)
+        .collect();
+
+    match offers.len() {
+        0 => Err(Error::NotFound {
+            msg: format!("no offers could be found for song id:{}", song_id),
+        }),
+        _ => Ok(offers),
+    }
+}
+
+#[ic_cdk::update]
+fn create_offer(payload: OfferPayload) -> Result<LicenseOffer, Error> {
+    let song = match _get_song(&payload.song_id) {
+        Some(song) => song,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("song id:{} could not be found", payload.song_id),
+            })
+        }
+    };
+
+    let owner = match _get_owner(&song.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", song.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != payload.auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only song owner can create offers",
+                payload.auth_key
+            ),
+        });
+    }
+
+    let name = check_text("name", &payload.name, validation::MAX_NAME_LENGTH)?;
+
+    if payload.duration_days == 0 {
+        return Err(validation_error(
+            "end_date",
+            format!("offer name:{} must last at least one day", payload.name),
+        ));
+    }
+
+    // Increment the global ID counter to get a new unique ID
+    let id = ID_COUNTER
+        .with(|counter| {
+            let current_id = *counter.borrow().get();
+            counter.borrow_mut().set(current_id + 1)
+        })
+        .expect("Cannot increment Ids");
+
+    let offer = LicenseOffer {
+        id,
+        song_id: song.id,
+        owner_id: song.owner_id,
+        name,
+        price: payload.price,
+        license_type: payload.license_type,
+        media: payload.media,
+        territories: normalize_territories(payload.territories),
+        exclusive: payload.exclusive,
+        duration_days: payload.duration_days,
+        max_copies: payload.max_copies,
+        max_streams: payload.max_streams,
+        instant: payload.instant,
+        active: true,
+    };
+
+    validate_territories(&offer.territories)?;
+    check_usage_rights(&license_from_offer(&offer, 0, 0, 0), &song.rights)?;
+
+    match OFFER_STORAGE.with(|s| s.borrow_mut().insert(id, offer.clone())) {
+        None => Ok(offer),
+        Some(_) => Err(Error::InternalError {
+            msg: format!("offer name:{} could not be created", payload.name),
+        }),
+    }
+}
+
+#[ic_cdk::update]
+fn withdraw_offer(auth_key: String, id: u64) -> Result<LicenseOffer, Error> {
+    let offer = match _get_offer(&id) {
+        Some(offer) => offer,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("offer id:{} could not be found", id),
+            })
+        }
+    };
+
+    let owner = match _get_owner(&offer.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", offer.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only song owner can withdraw offers",
+                auth_key
+            ),
+        });
+    }
+
+    let mut new_offer = offer.clone();
+    new_offer.active = false;
+
+    match OFFER_STORAGE.with(|s| s.borrow_mut().insert(id, new_offer.clone())) {
+        Some(_) => Ok(new_offer),
+        None => Err(Error::InternalError {
+            msg: format!("offer id:{} could not be withdrawn", id),
+        }),
+    }
+}
+
+// Build the license an offer grants, running duration_days from the start day
+fn license_from_offer(offer: &LicenseOffer, id: u64, licensee_id: u64, start: i64) -> License {
+    License {
+        id,
+        song_id: offer.song_id,
+        owner_id: offer.owner_id,
+        licensee_id,
+        approved: false,
+        price: offer.price,
+        start_date: format_date(start),
+        end_date: format_date(start + offer.duration_days as i64 - 1),
+        exclusive: offer.exclusive,
+        media: offer.media.clone(),
+        territories: offer.territories.clone(),
+        license_type: offer.license_type,
+        max_copies: offer.max_copies,
+        max_streams: offer.max_streams,
+        attribution: None,
+        offer_id: Some(offer.id),
+        previous_license_id: None,
+        next_license_id: None,
+        auto_renew: false,
+        parent_license_id: None,
+        pending_transfer_to: None,
+        revoked: false,
+        release_id: None,
+        bundle_id: None,
+        transferable: false,
+        counter_price: None,
+    }
+}
+
+// Request a license on the terms of a published offer; instant offers are approved immediately
+#[ic_cdk::update]
+fn create_license_from_offer(payload: OfferLicensePayload) -> Result<License, Error> {
+    let offer = match _get_offer(&payload.offer_id) {
+        Some(offer) if offer.active => offer,
+        _ => {
+            return Err(Error::NotFound {
+                msg: format!("offer id:{} could not be found", payload.offer_id),
+            })
+        }
+    };
+
+    let song = match _get_song(&offer.song_id) {
+        Some(song) => song,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("song id:{} could not be found", offer.song_id),
+            })
+        }
+    };
+
+    authorize_party(
+        MessageParty::Licensee,
+        payload.licensee_id,
+        &payload.auth_key,
+    )?;
+
+    let start = parse_date(&payload.start_date).ok_or(validation_error(
+        "start_date",
+        format!(
+            "start date:{} is invalid, expected YYYY-MM-DD",
+            payload.start_date
+        ),
+    ))?;
+
+    // The id the license gets once it passes validation
+    let id = ID_COUNTER.with(|counter| *counter.borrow().get());
+
+    let mut license = license_from_offer(&offer, id, payload.licensee_id, start);
+    license.attribution = song.rights.attribution.clone();
+
+    // The song's rights may have been narrowed since the offer was published
+    check_usage_rights(&license, &song.rights)?;
+    check_exclusive_conflicts(&license)?;
+
+    // Increment the global ID counter to get a new unique ID
+    ID_COUNTER
+        .with(|counter| counter.borrow_mut().set(id + 1))
+        .expect("Cannot increment Ids");
+
+    // Without payment an instant offer waits for the owner like any other
+    if offer.instant && check_payment(&license).is_ok() {
+        license = activate_license(&license, offer.price)?;
+        collect_payment(&license);
+    }
+
+    match store_license(license.clone()) {
+        None => Ok(license),
+        Some(_) => Err(Error::InternalError {
+            msg: format!("license id:{} could not be created", id),
+        }),
+    }
+}
+
+#[ic_cdk::query]
+fn get_release(id: u64) -> Result<Release, Error> {
+    match _get_release(&id) {
+        Some(release) => Ok(release),
+        None => Err(Error::NotFound {
+            msg: format!("release id:{} could not be found", id),
+        }),
+    }
+}
+
+fn _get_release(id: &u64) -> Option<Release> {
+    RELEASE_STORAGE.with(|s| s.borrow().get(id))
+}
+
+// Define query functions to get the songs of a release in track order
+#[ic_cdk::query]
+fn get_release_songs(id: u64) -> Result<Vec<Song>, Error> {
+    let release = match _get_release(&id) {
+        Some(release) => release,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("release id:{} could not be found", id),
+            })
+        }
+    };
+
+    Ok(release.track_ids.iter().filter_map(_get_song).collect())
+}
+
+#[ic_cdk::query]
+fn get_owner_releases(owner_id: u64) -> Result<Vec<Release>, Error> {
+    let releases = find_releases(|release| release.owner_id == owner_id);
+
+    match releases.len() {
+        0 => Err(Error::NotFound {
+            msg: format!("no releases could be found for owner id:{}", owner_id),
+        }),
+        _ => Ok(releases),
+    }
+}
+
+#[ic_cdk::query]
+fn get_song_releases(song_id: u64) -> Result<Vec<Release>, Error> {
+    let releases = find_releases(|release| release.track_ids.contains(&song_id));
+
+    match releases.len() {
+        0 => Err(Error::NotFound {
+            msg: format!("no releases could be found for song id:{}", song_id),
+        }),
+        _ => Ok(releases),
+    }
+}
+
+fn find_releases(filter: impl Fn(&Release) -> bool) -> Vec<Release> {
+    let releases_vec: Vec<(u64, Release)> = RELEASE_STORAGE.with(|s| s.borrow().iter().collect());
+    releases_vec
+        .into_iter()
+        .map(|(_, release)| release)
+        .filter(|release| filter(release))
+        .collect()
+}
+
+// Check a release's date, UPC and track list, which must hold distinct songs of its owner
+fn check_release(release: &mut Release) -> Result<(), Error> {
+    release.title = check_text("title", &release.title, validation::MAX_TITLE_LENGTH)?;
+
+    if parse_date(&release.release_date).is_none() {
+        return Err(validation_error(
+            "release_date",
+            format!(
+                "release date:{} is invalid, expected YYYY-MM-DD",
+                release.release_date
+            ),
+        ));
+    }
+
+    if let Some(upc) = &release.upc {
+        release.upc = Some(
+            identifiers::normalize_upc(upc).map_err(|reason| validation_error("upc", reason))?,
+        );
+    }
+
+    if release.track_ids.is_empty() {
+        return Err(validation_error(
+            "track_ids",
+            format!("release title:{} has no tracks", release.title),
+        ));
+    }
+
+    for (i, song_id) in release.track_ids.iter().enumerate() {
+        if release.track_ids[..i].contains(song_id) {
+            return Err(validation_error(
+                "track_ids",
+                format!("song id:{} appears twice in the track list", song_id),
+            ));
+        }
+
+        match _get_song(song_id) {
+            Some(song) if song.owner_id == release.owner_id => (),
+            Some(_) => {
+                return Err(Error::Forbidden {
+                    msg: format!(
+                        "song id:{} is not owned by owner id:{}",
+                        song_id, release.owner_id
+                    ),
+                })
+            }
+            None => {
+                return Err(Error::NotFound {
+                    msg: format!("song id:{} could not be found", song_id),
+                })
+            }
+        }
+    }
+
+    if Encode!(&*release).map_or(true, |bytes| bytes.len() > Release::MAX_SIZE as usize) {
+        return Err(validation_error(
+            "release",
+            format!(
+                "release title:{} exceeds the maximum size of {} bytes",
+                release.title,
+                Release::MAX_SIZE
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+#[ic_cdk::update]
+fn create_release(payload: ReleasePayload) -> Result<Release, Error> {
+    let owner = match _get_owner(&payload.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", payload.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != payload.auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only the owner can create releases",
+                payload.auth_key
+            ),
+        });
+    }
+
+    let mut release = Release {
+        id: 0,
+        owner_id: owner.id,
+        title: payload.title.clone(),
+        release_type: payload.release_type,
+        release_date: payload.release_date,
+        upc: payload.upc,
+        track_ids: payload.track_ids,
+        bundle_price: payload.bundle_price,
+    };
+    check_release(&mut release)?;
+
+    // Increment the global ID counter to get a new unique ID
+    release.id = ID_COUNTER
+        .with(|counter| {
+            let current_id = *counter.borrow().get();
+            counter.borrow_mut().set(current_id + 1)
+        })
+        .expect("Cannot increment Ids");
+
+    match RELEASE_STORAGE.with(|s| s.borrow_mut().insert(release.id, release.clone())) {
+        None => Ok(release),
+        Some(_) => Err(Error::InternalError {
+            msg: format!("release title:{} could not be created", payload.title),
+        }),
+    }
+}
+
+#[ic_cdk::update]
+fn update_release(payload: UpdateReleasePayload) -> Result<Release, Error> {
+    let release = match _get_release(&payload.id) {
+        Some(release) => release,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("release id:{} could not be found", payload.id),
+            })
+        }
+    };
+
+    let owner = match _get_owner(&release.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", release.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != payload.auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only the owner can update releases",
+                payload.auth_key
+            ),
+        });
+    }
+
+    let mut new_release = release.clone();
+    new_release.title = payload.title;
+    new_release.release_type = payload.release_type;
+    new_release.release_date = payload.release_date;
+    new_release.upc = payload.upc;
+    new_release.track_ids = payload.track_ids;
+    new_release.bundle_price = payload.bundle_price;
+    check_release(&mut new_release)?;
+
+    match RELEASE_STORAGE.with(|s| s.borrow_mut().insert(payload.id, new_release.clone())) {
+        Some(_) => Ok(new_release),
+        None => Err(Error::InternalError {
+            msg: format!("release id:{} could not be updated", payload.id),
+        }),
+    }
+}
+
+#[ic_cdk::update]
+fn delete_release(auth_key: String, id: u64) -> Result<Release, Error> {
+    let release = match _get_release(&id) {
+        Some(release) => release,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("release id:{} could not be found", id),
+            })
+        }
+    };
+
+    let owner = match _get_owner(&release.owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", release.owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only the owner can delete releases",
+                auth_key
+            ),
+        });
+    }
+
+    remove_entity_assets(id);
+
+    match RELEASE_STORAGE.with(|s| s.borrow_mut().remove(&id)) {
+        Some(release) => Ok(release),
+        None => Err(Error::InternalError {
+            msg: format!("release id:{} could not be deleted", id),
+        }),
+    }
+}
+
+// Drop a song from every release listing it, e.g. once it is deleted or changes owner. A
+// release left without tracks is deleted along with its files.
+fn remove_song_from_releases(song_id: u64) {
+    for mut release in find_releases(|release| release.track_ids.contains(&song_id)) {
+        release.track_ids.retain(|id| *id != song_id);
+        if release.track_ids.is_empty() {
+            remove_entity_assets(release.id);
+            RELEASE_STORAGE.with(|s| s.borrow_mut().remove(&release.id));
+        } else {
+            RELEASE_STORAGE.with(|s| s.borrow_mut().insert(release.id, release));
+        }
+    }
+}
+
+// Request one license per track of a release, linked by a shared bundle id so the owner can
+// approve them together at the release's bundled price
+#[ic_cdk::update]
+fn create_release_license_request(payload: ReleaseLicensePayload) -> Result<Vec<License>, Error> {
+    let release = match _get_release(&payload.release_id) {
+        Some(release) => release,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("release id:{} could not be found", payload.release_id),
+            })
+        }
+    };
+
+    if _get_licensee(&payload.licensee_id).is_none() {
+        return Err(Error::NotFound {
+            msg: format!("licensee id:{} could not be found", payload.licensee_id),
+        });
+    }
+
+    let songs: Vec<Song> = release.track_ids.iter().filter_map(_get_song).collect();
+    if songs.is_empty() {
+        return Err(Error::Conflict {
+            msg: format!("release id:{} has no tracks", payload.release_id),
+            license_ids: Vec::new(),
+        });
+    }
+
+    // Increment the global ID counter to get a new unique ID
+    let bundle_id = ID_COUNTER
+        .with(|counter| {
+            let current_id = *counter.borrow().get();
+            counter.borrow_mut().set(current_id + 1)
+        })
+        .expect("Cannot increment Ids");
+
+    let mut licenses: Vec<License> = Vec::new();
+    for (song, price) in songs
+        .iter()
+        .zip(split_price(release.bundle_price, songs.len()))
+    {
+        let license = License {
+            id: 0,
+            song_id: song.id,
+            owner_id: song.owner_id,
+            licensee_id: payload.licensee_id,
+            approved: false,
+            price,
+            start_date: payload.start_date.clone(),
+            end_date: payload.end_date.clone(),
+            exclusive: payload.exclusive,
+            media: payload.media.clone(),
+            territories: normalize_territories(payload.territories.clone()),
+            license_type: payload.license_type,
+            max_copies: payload.max_copies,
+            max_streams: payload.max_streams,
+            attribution: song.rights.attribution.clone(),
+            offer_id: None,
+            previous_license_id: None,
+            next_license_id: None,
+            auto_renew: false,
+            parent_license_id: None,
+            pending_transfer_to: None,
+            revoked: false,
+            release_id: Some(release.id),
+            bundle_id: Some(bundle_id),
+            transferable: false,
+            counter_price: None,
+        };
+
+        validate_license_scope(&license)?;
+        check_usage_rights(&license, &song.rights)?;
+        check_exclusive_conflicts(&license)?;
+        licenses.push(license);
+    }
+
+    for license in licenses.iter_mut() {
+        license.id = ID_COUNTER
+            .with(|counter| {
+                let current_id = *counter.borrow().get();
+                counter.borrow_mut().set(current_id + 1)
+            })
+            .expect("Cannot increment Ids");
+        store_license(license.clone());
+    }
+
+    Ok(licenses)
+}
+
+// Split a bundled price across tracks, giving any remainder to the first track
+fn split_price(total: u32, parts: usize) -> Vec<u32> {
+    let parts_u32 = parts as u32;
+    (0..parts_u32)
+        .map(|i| total / parts_u32 + if i == 0 { total % parts_u32 } else { 0 })
+        .collect()
+}
+
+// Owner approves every pending license of a release bundle at the price it was requested at
+#[ic_cdk::update]
+fn approve_release_license(payload: ApproveBundlePayload) -> Result<Vec<License>, Error> {
+    let licenses_vec: Vec<(u64, License)> = LICENSE_STORAGE.with(|s| s.borrow().iter().collect());
+    let pending: Vec<License> = licenses_vec
+        .into_iter()
+        .map(|(_, license)| license)
+        .filter(|license| {
+            license.bundle_id == Some(payload.bundle_id) && !license.approved && !license.revoked
+        })
+        .collect();
+
+    if pending.is_empty() {
+        return Err(Error::NotFound {
+            msg: format!(
+                "no pending licenses could be found for bundle id:{}",
+                payload.bundle_id
+            ),
+        });
+    }
+
+    for license in &pending {
+        let owner = match _get_owner(&license.owner_id) {
+            Some(owner) => owner,
+            None => {
+                return Err(Error::NotFound {
+                    msg: format!("owner id:{} could not be found", license.owner_id),
+                })
+            }
+        };
+
+        if owner.auth_key != payload.auth_key {
+            return Err(Error::Unauthorized {
+                msg: format!(
+                    "auth key:{} is invalid, only song owner can approve",
+                    payload.auth_key
+                ),
+            });
+        }
+
+        check_exclusive_conflicts(license)?;
+
+        if _get_licensee(&license.licensee_id).is_none() {
+            return Err(Error::NotFound {
+                msg: format!("licensee id:{} could not be found", license.licensee_id),
+            });
+        }
+    }
+
+    // With payments enabled the licensee pays the bundle price once, for all tracks. It is
+    // checked before any license is activated, so a bundle is approved whole or not at all.
+    let licensee_id = pending[0].licensee_id;
+    let owner_id = pending[0].owner_id;
+    let price: u64 = pending.iter().map(|license| license.price as u64).sum();
+    let charged = payment_ledger().is_ok();
+    let available = balance(MessageParty::Licensee, licensee_id);
+    if charged && available < price {
+        return Err(Error::PaymentFailed {
+            msg: format!(
+                "balance of {} is less than the price of {} for bundle id:{}",
+                available, price, payload.bundle_id
+            ),
+        });
+    }
+
+    let mut approved: Vec<License> = Vec::new();
+    for license in &pending {
+        approved.push(activate_license(license, license.price)?);
+    }
+
+    for license in &approved {
+        store_license(license.clone());
+    }
+
+    if charged {
+        set_balance(MessageParty::Licensee, licensee_id, available - price);
+        credit_balance(MessageParty::Owner, owner_id, price);
+
+        notify(
+            MessageParty::Owner,
+            owner_id,
+            NotificationKind::PaymentReceived,
+            &approved[0],
+            format!(
+                "licensee id:{} paid {} for bundle id:{}",
+                licensee_id, price, payload.bundle_id
+            ),
+        );
+    }
+
+    Ok(approved)
+}
+
+// Define query functions to get the files stored for a song
+#[ic_cdk::query]
+fn get_song_assets(song_id: u64) -> Result<Vec<Asset>, Error> {
+    let assets = entity_assets(song_id);
+
+    match assets.len() {
+        0 => Err(Error::NotFound {
+            msg: format!("no files could be found for song id:{}", song_id),
+        }),
+        _ => Ok(assets),
+    }
+}
+
+#[ic_cdk::query]
+fn get_release_assets(release_id: u64) -> Result<Vec<Asset>, Error> {
+    let assets = entity_assets(release_id);
+
+    match assets.len() {
+        0 => Err(Error::NotFound {
+            msg: format!("no files could be found for release id:{}", release_id),
+        }),
+        _ => Ok(assets),
+    }
+}
+
+fn entity_assets(entity_id: u64) -> Vec<Asset> {
+    ASSET_STORAGE.with(|s| {
+        s.borrow()
+            .range((entity_id, 0)..=(entity_id, u8::MAX))
+            .map(|(_, asset)| asset)
+            .collect()
+    })
+}
+
+fn _get_asset(entity_id: u64, kind: AssetKind) -> Option<Asset> {
+    ASSET_STORAGE.with(|s| s.borrow().get(&(entity_id, kind as u8)))
+}
+
+// Look up the owner of a song or release and check their auth key
+fn authorize_asset_owner(entity_id: u64, auth_key: &str) -> Result<Owner, Error> {
+    let owner_id = match (_get_song(&entity_id), _get_release(&entity_id)) {
+        (Some(song), _) => song.owner_id,
+        (None, Some(release)) => release.owner_id,
+        (None, None) => {
+            return Err(Error::NotFound {
+                msg: format!("song or release id:{} could not be found", entity_id),
+            })
+        }
+    };
+
+    let owner = match _get_owner(&owner_id) {
+        Some(owner) => owner,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("owner id:{} could not be found", owner_id),
+            })
+        }
+    };
+
+    if owner.auth_key != auth_key {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only the owner can manage files",
+                auth_key
+            ),
+        });
+    }
+
+    Ok(owner)
+}
+
+// Load an upload session for the owner who started it
+fn authorize_upload(session_id: u64, auth_key: &str) -> Result<UploadSession, Error> {
+    let session = match UPLOAD_STORAGE.with(|s| s.borrow().get(&session_id)) {
+        Some(session) => session,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("upload session id:{} could not be found", session_id),
+            })
+        }
+    };
+
+    let owner = authorize_asset_owner(session.entity_id, auth_key)?;
+    if owner.id != session.owner_id {
+        return Err(Error::Forbidden {
+            msg: format!(
+                "upload session id:{} was started by a previous owner of id:{}",
+                session_id, session.entity_id
+            ),
+        });
+    }
+
+    Ok(session)
+}
+
+fn normalize_sha256(value: &str) -> Result<String, Error> {
+    let sha256 = value.trim().to_lowercase();
+    match sha256.len() == 64 && sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+        true => Ok(sha256),
+        false => Err(validation_error(
+            "sha256",
+            format!("sha256:{} is invalid, expected 64 hex digits", value),
+        )),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn chunk_count(size: u64, chunk_size: u32) -> u32 {
+    size.div_ceil(chunk_size as u64) as u32
+}
+
+// Start a resumable upload of a song or release file. The file is sent in chunks of
+// `chunk_size` bytes and checked against the SHA-256 given here when the upload is finished.
+#[ic_cdk::update]
+fn start_upload(payload: StartUploadPayload) -> Result<UploadSession, Error> {
+    let owner = authorize_asset_owner(payload.entity_id, &payload.auth_key)?;
+
+    if payload.kind != AssetKind::Cover && _get_song(&payload.entity_id).is_none() {
+        return Err(validation_error(
+            "kind",
+            format!(
+                "id:{} is not a song, releases only have cover images",
+                payload.entity_id
+            ),
+        ));
+    }
+
+    let max_size = match payload.kind {
+        AssetKind::Master => MAX_ASSET_SIZE,
+        AssetKind::Preview => MAX_PREVIEW_SIZE,
+        AssetKind::Cover => MAX_COVER_SIZE,
+    };
+    if payload.size == 0 || payload.size > max_size {
+        return Err(validation_error(
+            "size",
+            format!(
+                "file size:{} is invalid, files must be between 1 and {} bytes",
+                payload.size, max_size
+            ),
+        ));
+    }
+
+    let content_type = payload.content_type.trim().to_lowercase();
+    let valid_type = match payload.kind {
+        AssetKind::Master | AssetKind::Preview => content_type.starts_with("audio/"),
+        AssetKind::Cover => COVER_TYPES.iter().any(|(t, _)| *t == content_type),
+    };
+    if !valid_type {
+        return Err(validation_error(
+            "content_type",
+            format!(
+                "content type:{} is invalid, masters and previews must be audio and covers \
+                 PNG, JPEG or WebP images",
+                payload.content_type
+            ),
+        ));
+    }
+
+    let sha256 = normalize_sha256(&payload.sha256)?;
+    let file_name = check_text(
+        "file_name",
+        &payload.file_name,
+        validation::MAX_TITLE_LENGTH,
+    )?;
+    remove_stale_uploads();
+
     // Increment the global ID counter to get a new unique ID
     let id = ID_COUNTER
         .with(|counter| {
@@ -247,306 +5623,1667 @@ fn create_song(payload: SongPayload) -> Result<Song, Error> {
         })
         .expect("Cannot increment Ids");
 
-    let song = Song {
-        id,
-        title: payload.title.clone(),
-        artist: payload.artist,
-        owner_id: payload.owner_id,
-        year: payload.year,
-        genre: payload.genre,
-        price: payload.price,
+    let session = UploadSession {
+        id,
+        entity_id: payload.entity_id,
+        owner_id: owner.id,
+        kind: payload.kind,
+        file_name,
+        content_type,
+        size: payload.size,
+        sha256,
+        chunk_size: MAX_CHUNK_SIZE,
+        received: vec![false; chunk_count(payload.size, MAX_CHUNK_SIZE) as usize],
+        created_at: runtime::time(),
+    };
+
+    if Encode!(&session).map_or(true, |bytes| bytes.len() > UploadSession::MAX_SIZE as usize) {
+        return Err(validation_error(
+            "file_name",
+            format!(
+                "file name:{} exceeds the maximum size of an upload session",
+                session.file_name
+            ),
+        ));
+    }
+
+    UPLOAD_STORAGE.with(|s| s.borrow_mut().insert(id, session.clone()));
+    Ok(session)
+}
+
+// Store one chunk of an upload. Sending a chunk again replaces it, so an interrupted upload
+// resumes by sending the chunks still marked as not received.
+#[ic_cdk::update]
+fn upload_chunk(payload: UploadChunkPayload) -> Result<UploadSession, Error> {
+    let mut session = authorize_upload(payload.session_id, &payload.auth_key)?;
+
+    let count = session.received.len() as u32;
+    if payload.index >= count {
+        return Err(validation_error(
+            "index",
+            format!(
+                "chunk index:{} is invalid, upload has {} chunks",
+                payload.index, count
+            ),
+        ));
+    }
+
+    let expected_len = match payload.index + 1 == count {
+        true => session.size - payload.index as u64 * session.chunk_size as u64,
+        false => session.chunk_size as u64,
+    };
+    if payload.data.len() as u64 != expected_len {
+        return Err(validation_error(
+            "data",
+            format!(
+                "chunk index:{} has {} bytes, expected {}",
+                payload.index,
+                payload.data.len(),
+                expected_len
+            ),
+        ));
+    }
+
+    let sha256 = normalize_sha256(&payload.sha256)?;
+    if to_hex(&Sha256::digest(&payload.data)) != sha256 {
+        return Err(validation_error(
+            "data",
+            format!(
+                "chunk index:{} does not match its sha256, resend it",
+                payload.index
+            ),
+        ));
+    }
+
+    CHUNK_STORAGE.with(|s| {
+        s.borrow_mut().insert(
+            (session.id, payload.index),
+            AssetChunk(payload.data.into_vec()),
+        )
+    });
+    session.received[payload.index as usize] = true;
+    UPLOAD_STORAGE.with(|s| s.borrow_mut().insert(session.id, session.clone()));
+
+    Ok(session)
+}
+
+#[ic_cdk::query]
+fn get_upload_session(auth_key: String, session_id: u64) -> Result<UploadSession, Error> {
+    authorize_upload(session_id, &auth_key)
+}
+
+// Verify a fully received upload against its SHA-256 and make it the song's or release's
+// file of that kind, replacing any earlier one
+#[ic_cdk::update]
+fn finish_upload(auth_key: String, session_id: u64) -> Result<Asset, Error> {
+    let session = authorize_upload(session_id, &auth_key)?;
+
+    let missing: Vec<String> = session
+        .received
+        .iter()
+        .enumerate()
+        .filter(|(_, received)| !**received)
+        .map(|(index, _)| index.to_string())
+        .collect();
+    if !missing.is_empty() {
+        return Err(Error::Conflict {
+            msg: format!(
+                "upload session id:{} is missing chunks:{}",
+                session_id,
+                missing.join(",")
+            ),
+            license_ids: Vec::new(),
+        });
+    }
+
+    let count = session.received.len() as u32;
+    let mut hasher = Sha256::new();
+    CHUNK_STORAGE.with(|s| {
+        for (_, chunk) in s.borrow().range((session.id, 0)..(session.id, count)) {
+            hasher.update(&chunk.0);
+        }
+    });
+    if to_hex(&hasher.finalize()) != session.sha256 {
+        remove_chunks(session.id, count);
+        UPLOAD_STORAGE.with(|s| s.borrow_mut().remove(&session.id));
+        return Err(validation_error(
+            "sha256",
+            format!(
+                "upload session id:{} does not match sha256:{}, the upload was discarded",
+                session_id, session.sha256
+            ),
+        ));
+    }
+
+    if session.kind == AssetKind::Cover && !is_cover_image(&session) {
+        remove_chunks(session.id, count);
+        UPLOAD_STORAGE.with(|s| s.borrow_mut().remove(&session.id));
+        return Err(validation_error(
+            "content_type",
+            format!(
+                "upload session id:{} is not a {} image, the upload was discarded",
+                session_id, session.content_type
+            ),
+        ));
+    }
+
+    let asset = Asset {
+        id: session.id,
+        entity_id: session.entity_id,
+        kind: session.kind,
+        file_name: session.file_name,
+        content_type: session.content_type,
+        size: session.size,
+        sha256: session.sha256,
+        chunk_size: session.chunk_size,
+        chunk_count: count,
+        uploaded_at: runtime::time(),
+    };
+
+    if let Some(previous) = ASSET_STORAGE.with(|s| {
+        s.borrow_mut()
+            .insert((asset.entity_id, asset.kind as u8), asset.clone())
+    }) {
+        remove_chunks(previous.id, previous.chunk_count);
+    }
+    UPLOAD_STORAGE.with(|s| s.borrow_mut().remove(&session.id));
+
+    if asset.kind == AssetKind::Cover {
+        certify_cover(&asset);
+    }
+
+    Ok(asset)
+}
+
+#[ic_cdk::update]
+fn cancel_upload(auth_key: String, session_id: u64) -> Result<UploadSession, Error> {
+    let session = authorize_upload(session_id, &auth_key)?;
+    remove_chunks(session.id, session.received.len() as u32);
+
+    match UPLOAD_STORAGE.with(|s| s.borrow_mut().remove(&session_id)) {
+        Some(session) => Ok(session),
+        None => Err(Error::InternalError {
+            msg: format!("upload session id:{} could not be cancelled", session_id),
+        }),
+    }
+}
+
+#[ic_cdk::update]
+fn delete_asset(auth_key: String, entity_id: u64, kind: AssetKind) -> Result<Asset, Error> {
+    authorize_asset_owner(entity_id, &auth_key)?;
+
+    match _get_asset(entity_id, kind) {
+        Some(asset) => {
+            remove_asset(&asset);
+            Ok(asset)
+        }
+        None => Err(Error::NotFound {
+            msg: format!("no file could be found for id:{}", entity_id),
+        }),
+    }
+}
+
+fn remove_asset(asset: &Asset) {
+    if asset.kind == AssetKind::Cover {
+        uncertify_cover(asset.entity_id);
+    }
+    remove_chunks(asset.id, asset.chunk_count);
+    ASSET_STORAGE.with(|s| s.borrow_mut().remove(&(asset.entity_id, asset.kind as u8)));
+}
+
+// Read one chunk of a song file. Only the song owner and licensees holding an approved
+// license that is in effect today may download it.
+#[ic_cdk::query]
+fn download_chunk(payload: DownloadChunkPayload) -> Result<ByteBuf, Error> {
+    let licensee = match _get_licensee(&payload.licensee_id) {
+        Some(licensee) => licensee,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("licensee id:{} could not be found", payload.licensee_id),
+            })
+        }
+    };
+
+    if !licensee_key_matches(&licensee, &payload.auth_key) {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only the licensee can download",
+                payload.auth_key
+            ),
+        });
+    }
+
+    if !has_active_license(licensee.id, payload.song_id) {
+        return Err(Error::Forbidden {
+            msg: format!(
+                "licensee id:{} holds no active license for song id:{}",
+                licensee.id, payload.song_id
+            ),
+        });
+    }
+
+    read_chunk(payload.song_id, payload.kind, payload.index)
+}
+
+// Owners download their own files with their auth key
+#[ic_cdk::query]
+fn download_own_chunk(
+    auth_key: String,
+    entity_id: u64,
+    kind: AssetKind,
+    index: u32,
+) -> Result<ByteBuf, Error> {
+    authorize_asset_owner(entity_id, &auth_key)?;
+    read_chunk(entity_id, kind, index)
+}
+
+// Previews are public, so anyone can read them without a license
+#[ic_cdk::query]
+fn get_preview_chunk(song_id: u64, index: u32) -> Result<ByteBuf, Error> {
+    read_chunk(song_id, AssetKind::Preview, index)
+}
+
+fn read_chunk(entity_id: u64, kind: AssetKind, index: u32) -> Result<ByteBuf, Error> {
+    let asset = match _get_asset(entity_id, kind) {
+        Some(asset) => asset,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("no file could be found for id:{}", entity_id),
+            })
+        }
+    };
+
+    match CHUNK_STORAGE.with(|s| s.borrow().get(&(asset.id, index))) {
+        Some(chunk) => Ok(ByteBuf::from(chunk.0)),
+        None => Err(Error::NotFound {
+            msg: format!(
+                "chunk index:{} could not be found, file has {} chunks",
+                index, asset.chunk_count
+            ),
+        }),
+    }
+}
+
+// Read the inclusive byte range start..=end of an asset from its chunks
+fn read_asset_range(asset: &Asset, start: u64, end: u64) -> Vec<u8> {
+    let chunk_size = asset.chunk_size as u64;
+    let first = (start / chunk_size) as u32;
+    let last = (end / chunk_size) as u32;
+    let mut body: Vec<u8> = Vec::with_capacity((end - start + 1) as usize);
+
+    CHUNK_STORAGE.with(|s| {
+        for (index, chunk) in s.borrow().range((asset.id, first)..=(asset.id, last)) {
+            let offset = index.1 as u64 * chunk_size;
+            let from = start.saturating_sub(offset) as usize;
+            let to = ((end + 1 - offset) as usize).min(chunk.0.len());
+            body.extend_from_slice(&chunk.0[from..to]);
+        }
+    });
+
+    body
+}
+
+// Serve the HTTP interface of the canister:
+//   GET /api/...             read-only JSON API, see api_request
+//   GET /songs/{id}/preview  public preview clip, with Range support
+//   GET /songs/{id}/cover    certified cover image of a song
+//   GET /releases/{id}/cover certified cover image of a release
+//   GET /licenses/{id}/certificate[.json]  certified license certificate (HTML or JSON)
+#[ic_cdk::query]
+fn http_request(request: HttpRequest) -> HttpResponse {
+    if !matches!(request.method.as_str(), "GET" | "HEAD") {
+        return HttpResponse::text(405, "method not allowed").with_header("Allow", "GET, HEAD");
+    }
+
+    let mut response = match request.segments().as_slice() {
+        ["api", route @ ..] => api_request(route),
+        ["songs", id, "preview"] => match id.parse::<u64>() {
+            Ok(song_id) => serve_preview(&request, song_id),
+            Err(_) => HttpResponse::text(400, &format!("song id:{} is invalid", id)),
+        },
+        [collection @ ("songs" | "releases"), id, "cover"] => match id.parse::<u64>() {
+            Ok(entity_id) if cover_path(entity_id) == format!("/{}/{}/cover", collection, id) => {
+                serve_cover(entity_id)
+            }
+            Ok(_) => HttpResponse::text(404, "not found"),
+            Err(_) => HttpResponse::text(400, &format!("id:{} is invalid", id)),
+        },
+        ["licenses", id, document @ ("certificate" | "certificate.json")] => {
+            match id.parse::<u64>() {
+                Ok(license_id) => {
+                    serve_license_certificate(license_id, *document == "certificate.json")
+                }
+                Err(_) => HttpResponse::text(400, &format!("license id:{} is invalid", id)),
+            }
+        }
+        _ => HttpResponse::text(404, "not found"),
+    };
+
+    if request.method == "HEAD" {
+        response.body = ByteBuf::new();
+    }
+    response
+}
+
+// Read-only JSON API over the public queries. Errors are returned as
+// {"error": "<Error variant>", ...its fields} with a matching status code.
+fn api_request(route: &[&str]) -> HttpResponse {
+    let id = |value: &str| {
+        value
+            .parse::<u64>()
+            .map_err(|_| validation_error("id", format!("id:{} is invalid", value)))
     };
 
-    match _get_owner(&id) {
-        Some(_) => {
-            return Err(Error::InvalidPayload {
-                msg: format!("owner id:{} could not be found", id),
-            })
+    match route {
+        ["songs"] => json_response(get_all_songs().map(public_songs)),
+        ["genres"] => json_response(Ok(get_genres())),
+        ["songs", song_id] => json_response(id(song_id).and_then(get_song).map(public_song)),
+        ["songs", song_id, "owner"] => json_response(
+            id(song_id)
+                .and_then(get_song)
+                .and_then(|song| get_owner_profile(song.owner_id)),
+        ),
+        ["songs", song_id, "offers"] => json_response(id(song_id).and_then(get_song_offers)),
+        ["songs", song_id, "releases"] => json_response(id(song_id).and_then(get_song_releases)),
+        ["owners", owner_id] => json_response(id(owner_id).and_then(get_owner_profile)),
+        ["owners", owner_id, "songs"] => {
+            json_response(id(owner_id).and_then(get_owner_songs).map(public_songs))
+        }
+        ["owners", owner_id, "releases"] => {
+            json_response(id(owner_id).and_then(get_owner_releases))
+        }
+        ["owners", owner_id, "licenses"] => {
+            json_response(id(owner_id).and_then(get_owner_license_requests))
+        }
+        ["releases", release_id] => json_response(id(release_id).and_then(get_release)),
+        ["releases", release_id, "songs"] => {
+            json_response(id(release_id).and_then(get_release_songs).map(public_songs))
+        }
+        ["licenses", license_id] => json_response(id(license_id).and_then(get_license)),
+        ["licenses", license_id, "history"] => {
+            json_response(id(license_id).and_then(get_license_history))
+        }
+        ["licenses", license_id, "signature"] => {
+            json_response(id(license_id).and_then(get_license_signature))
+        }
+        ["licensees", licensee_id] => {
+            json_response(id(licensee_id).and_then(get_licensee).map(public_licensee))
         }
-        None => (),
+        ["licensees", licensee_id, "licenses"] => {
+            json_response(id(licensee_id).and_then(get_licensee_licenses))
+        }
+        _ => json_response::<()>(Err(Error::NotFound {
+            msg: format!("no API route matches /api/{}", route.join("/")),
+        })),
     }
+}
 
-    match add_song_to_owner(song.owner_id, song.id) {
-        Ok(_) => (),
-        Err(e) => return Err(e),
+// A song as the JSON API shows it, without the owner's auto-approval rules
+fn public_song(song: Song) -> serde_json::Value {
+    let mut value = serde_json::to_value(song).unwrap_or_default();
+    if let Some(fields) = value.as_object_mut() {
+        fields.remove("auto_approval");
     }
+    value
+}
 
-    match SONG_STORAGE.with(|s| s.borrow_mut().insert(id, song.clone())) {
-        None => Ok(song),
-        Some(_) => Err(Error::InvalidPayload {
-            msg: format!("song title:{} could not be created", payload.title),
-        }),
+fn public_songs(songs: Vec<Song>) -> Vec<serde_json::Value> {
+    songs.into_iter().map(public_song).collect()
+}
+
+// A licensee as the JSON API shows it, without their email
+fn public_licensee(licensee: ReturnLicensee) -> serde_json::Value {
+    let mut value = serde_json::to_value(licensee).unwrap_or_default();
+    if let Some(fields) = value.as_object_mut() {
+        fields.remove("email");
     }
+    value
 }
 
-// Define query functions to get owners by id
+fn json_response<T: serde::Serialize>(result: Result<T, Error>) -> HttpResponse {
+    match result {
+        Ok(value) => HttpResponse::json(200, &value),
+        Err(error) => HttpResponse::json(error_status(&error), &error),
+    }
+}
+
+fn serve_preview(request: &HttpRequest, song_id: u64) -> HttpResponse {
+    let asset = match _get_asset(song_id, AssetKind::Preview) {
+        Some(asset) => asset,
+        None => {
+            return HttpResponse::text(
+                404,
+                &format!("no preview could be found for song id:{}", song_id),
+            )
+        }
+    };
+
+    match http::parse_range(request.header("Range"), asset.size) {
+        Ok(None) => HttpResponse::new(
+            200,
+            &asset.content_type,
+            read_asset_range(&asset, 0, asset.size - 1),
+        )
+        .with_header("Accept-Ranges", "bytes"),
+        Ok(Some((start, end))) => HttpResponse::new(
+            206,
+            &asset.content_type,
+            read_asset_range(&asset, start, end),
+        )
+        .with_header("Accept-Ranges", "bytes")
+        .with_header(
+            "Content-Range",
+            &format!("bytes {}-{}/{}", start, end, asset.size),
+        ),
+        Err(()) => HttpResponse::text(416, "range not satisfiable")
+            .with_header("Content-Range", &format!("bytes */{}", asset.size)),
+    }
+}
+
+fn serve_cover(entity_id: u64) -> HttpResponse {
+    let asset = match _get_asset(entity_id, AssetKind::Cover) {
+        Some(asset) => asset,
+        None => {
+            return HttpResponse::text(
+                404,
+                &format!("no cover could be found for id:{}", entity_id),
+            )
+        }
+    };
+
+    let mut response = HttpResponse::new(
+        200,
+        &asset.content_type,
+        read_asset_range(&asset, 0, asset.size - 1),
+    )
+    .with_header("Cache-Control", COVER_CACHE_CONTROL)
+    .with_header("ETag", &format!("\"{}\"", asset.sha256));
+
+    if let Some((name, value)) =
+        CERTIFIED_TREE.with(|t| http::certificate_header(&t.borrow(), &cover_path(entity_id)))
+    {
+        response = response.with_header(&name, &value);
+    }
+    response
+}
+
+fn cover_path(entity_id: u64) -> String {
+    match _get_song(&entity_id) {
+        Some(_) => format!("/songs/{}/cover", entity_id),
+        None => format!("/releases/{}/cover", entity_id),
+    }
+}
+
+// Check the leading bytes of an uploaded cover against its declared image type
+fn is_cover_image(session: &UploadSession) -> bool {
+    let head = match CHUNK_STORAGE.with(|s| s.borrow().get(&(session.id, 0))) {
+        Some(chunk) => chunk.0,
+        None => return false,
+    };
+
+    COVER_TYPES
+        .iter()
+        .find(|(content_type, _)| *content_type == session.content_type)
+        .is_some_and(|(content_type, magic)| {
+            head.starts_with(magic)
+                && (*content_type != "image/webp" || head.get(8..12) == Some(b"WEBP".as_slice()))
+        })
+}
+
+// Add a cover's hash to the certified tree under its HTTP path
+fn certify_cover(asset: &Asset) {
+    let mut hash: Hash = [0; 32];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&asset.sha256[2 * i..2 * i + 2], 16).unwrap_or_default();
+    }
+
+    CERTIFIED_TREE.with(|t| {
+        let mut tree = t.borrow_mut();
+        tree.insert(cover_path(asset.entity_id), hash);
+        http::set_certified_tree(&tree);
+    });
+}
+
+fn uncertify_cover(entity_id: u64) {
+    CERTIFIED_TREE.with(|t| {
+        let mut tree = t.borrow_mut();
+        tree.delete(cover_path(entity_id).as_bytes());
+        http::set_certified_tree(&tree);
+    });
+}
+
+#[ic_cdk::init]
+fn init() {
+    seed_genres();
+    start_token_sweep();
+    start_signing_runs();
+}
+
+// Certified data and timers do not survive an upgrade, so they are set up again
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    migrate_stored_records();
+    seed_genres();
+    start_token_sweep();
+    start_signing_runs();
+
+    let covers: Vec<Asset> = ASSET_STORAGE.with(|s| {
+        s.borrow()
+            .iter()
+            .map(|(_, asset)| asset)
+            .filter(|asset| asset.kind == AssetKind::Cover)
+            .collect()
+    });
+
+    for asset in covers {
+        certify_cover(&asset);
+    }
+
+    rebuild_license_certificates(0);
+}
+
+// Re-index and recertify the licenses from id `from` on, one batch now and the rest in
+// timers right after it, so an upgrade does not grow with the number of licenses. Until a
+// license's batch has run its certificate is served without a valid certificate header.
+fn rebuild_license_certificates(from: u64) {
+    if let Some(next) = recertify_licenses(from, RECERTIFY_BATCH_SIZE) {
+        runtime::set_timer(std::time::Duration::ZERO, move || {
+            rebuild_license_certificates(next)
+        });
+    }
+}
+
+// Index the end dates and certify the certificates of up to `batch` licenses from id `from`
+// on. Licenses stored before the end date index existed are indexed here. Returns the id to
+// continue from, if any licenses are left.
+fn recertify_licenses(from: u64, batch: usize) -> Option<u64> {
+    let licenses: Vec<License> = LICENSE_STORAGE.with(|s| {
+        s.borrow()
+            .range(from..)
+            .take(batch + 1)
+            .map(|(_, license)| license)
+            .collect()
+    });
+
+    CERTIFIED_TREE.with(|t| {
+        let mut tree = t.borrow_mut();
+        for license in licenses.iter().take(batch) {
+            index_license_end(license);
+            insert_license_certificates(&mut tree, license);
+        }
+        http::set_certified_tree(&tree);
+    });
+
+    licenses.get(batch).map(|license| license.id)
+}
+
+// Move songs and licenses out of the maps earlier versions created for smaller records.
+// Records of any earlier layout decode with defaults for the fields they lack; the custody
+// history licenses carried moves to its own map.
+fn migrate_stored_records() {
+    let songs = legacy::drain_records(MEMORY_MANAGER.with(|m| m.borrow().get(LEGACY_SONG_MEMORY)));
+    for (id, bytes) in songs {
+        let song = Song::from_bytes(Cow::Owned(bytes));
+        SONG_STORAGE.with(|s| s.borrow_mut().insert(id, song));
+    }
+
+    let licenses =
+        legacy::drain_records(MEMORY_MANAGER.with(|m| m.borrow().get(LEGACY_LICENSE_MEMORY)));
+    for (id, bytes) in licenses {
+        for transfer in legacy::license_custody(&bytes) {
+            CUSTODY_STORAGE.with(|s| s.borrow_mut().insert((id, transfer.seq), transfer));
+        }
+        let license = License::from_bytes(Cow::Owned(bytes));
+        LICENSE_STORAGE.with(|s| s.borrow_mut().insert(id, license));
+    }
+}
+
+// Define query functions to check that a license covers a licensee's use of a song today
 #[ic_cdk::query]
-fn get_song_owner(id: u64) -> Result<ReturnOwner, Error> {
-    let song = match _get_song(&id) {
-        Some(song) => song,
+fn verify_license(
+    license_id: u64,
+    licensee_id: u64,
+    song_id: u64,
+) -> Result<LicenseVerification, Error> {
+    let license = match _get_license(&license_id) {
+        Some(license) => license,
         None => {
             return Err(Error::NotFound {
-                msg: format!("song id:{} could not be found", id),
+                msg: format!("license id:{} could not be found", license_id),
             })
         }
     };
 
-    match _get_owner(&song.owner_id) {
-        Some(owner) => Ok(ReturnOwner {
-            id: owner.id,
-            name: owner.name,
-            email: owner.email,
-        }),
+    let today = (runtime::time() / 86_400_000_000_000) as i64;
+    let status = license_status(&license, today);
+
+    let reason = if license.licensee_id != licensee_id {
+        Some(format!(
+            "license id:{} is held by licensee id:{}, not licensee id:{}",
+            license_id, license.licensee_id, licensee_id
+        ))
+    } else if license.song_id != song_id {
+        Some(format!(
+            "license id:{} covers song id:{}, not song id:{}",
+            license_id, license.song_id, song_id
+        ))
+    } else {
+        match status {
+            LicenseStatus::Active => None,
+            LicenseStatus::Pending => Some("license has not been approved".to_string()),
+            LicenseStatus::NotYetValid => {
+                Some(format!("license term starts on {}", license.start_date))
+            }
+            LicenseStatus::Expired => Some(format!("license term ended on {}", license.end_date)),
+            LicenseStatus::Revoked => Some("license was revoked".to_string()),
+        }
+    };
+
+    Ok(LicenseVerification {
+        valid: reason.is_none(),
+        status,
+        reason,
+        certificate: license_certificate(&license),
+    })
+}
+
+fn license_status(license: &License, today: i64) -> LicenseStatus {
+    if license.revoked {
+        return LicenseStatus::Revoked;
+    }
+    if !license.approved {
+        return LicenseStatus::Pending;
+    }
+
+    match license_window(license) {
+        Ok((start, _)) if today < start => LicenseStatus::NotYetValid,
+        Ok((_, end)) if today > end => LicenseStatus::Expired,
+        Ok(_) => LicenseStatus::Active,
+        Err(_) => LicenseStatus::Expired,
+    }
+}
+
+fn license_certificate(license: &License) -> LicenseCertificate {
+    let song = _get_song(&license.song_id);
+
+    LicenseCertificate {
+        license_id: license.id,
+        approved: license.approved,
+        revoked: license.revoked,
+        song_id: license.song_id,
+        song_title: song.as_ref().map(|s| s.title.clone()).unwrap_or_default(),
+        artist: song.as_ref().map(|s| s.artist.clone()).unwrap_or_default(),
+        isrc: song.and_then(|s| s.isrc),
+        owner_id: license.owner_id,
+        owner_name: _get_owner(&license.owner_id)
+            .map(|owner| owner.name)
+            .unwrap_or_default(),
+        licensee_id: license.licensee_id,
+        licensee_name: _get_licensee(&license.licensee_id)
+            .map(|licensee| licensee.name)
+            .unwrap_or_default(),
+        license_type: license.license_type,
+        exclusive: license.exclusive,
+        media: license.media.clone(),
+        territories: license.territories.clone(),
+        max_copies: license.max_copies,
+        max_streams: license.max_streams,
+        attribution: license.attribution.clone(),
+        start_date: license.start_date.clone(),
+        end_date: license.end_date.clone(),
+        parent_license_id: license.parent_license_id,
+        previous_license_id: license.previous_license_id,
+        next_license_id: license.next_license_id,
+    }
+}
+
+// Printable HTML version of a license certificate
+fn certificate_html(certificate: &LicenseCertificate) -> String {
+    let list = |values: &[String]| match values.len() {
+        0 => "all".to_string(),
+        _ => values.join(", "),
+    };
+    let media: Vec<String> = certificate
+        .media
+        .iter()
+        .map(|media| format!("{:?}", media))
+        .collect();
+    let state = match (certificate.revoked, certificate.approved) {
+        (true, _) => "Revoked",
+        (false, true) => "Approved",
+        (false, false) => "Pending approval",
+    };
+    let limit = |value: Option<u64>| value.map_or("unlimited".to_string(), |v| v.to_string());
+
+    let rows = [
+        ("Status", state.to_string()),
+        (
+            "Song",
+            format!("{} (id {})", certificate.song_title, certificate.song_id),
+        ),
+        ("Artist", certificate.artist.clone()),
+        ("ISRC", certificate.isrc.clone().unwrap_or_default()),
+        (
+            "Licensor",
+            format!(
+                "{} (owner id {})",
+                certificate.owner_name, certificate.owner_id
+            ),
+        ),
+        (
+            "Licensee",
+            format!(
+                "{} (licensee id {})",
+                certificate.licensee_name, certificate.licensee_id
+            ),
+        ),
+        ("License type", format!("{:?}", certificate.license_type)),
+        (
+            "Exclusive",
+            if certificate.exclusive { "Yes" } else { "No" }.to_string(),
+        ),
+        ("Media", list(&media)),
+        ("Territories", list(&certificate.territories)),
+        ("Max copies", limit(certificate.max_copies)),
+        ("Max streams", limit(certificate.max_streams)),
+        (
+            "Attribution",
+            certificate.attribution.clone().unwrap_or_default(),
+        ),
+        (
+            "Term",
+            format!("{} to {}", certificate.start_date, certificate.end_date),
+        ),
+    ];
+
+    let table: String = rows
+        .iter()
+        .map(|(label, value)| {
+            format!(
+                "<tr><th>{}</th><td>{}</td></tr>",
+                label,
+                http::escape_html(value)
+            )
+        })
+        .collect();
+
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>License certificate {id}</title>\
+         <style>body{{font-family:sans-serif;max-width:40em;margin:2em auto}}\
+         th{{text-align:left;padding-right:2em}}</style></head>\
+         <body><h1>License certificate {id}</h1><table>{table}</table>\
+         <p>Machine-readable version: <a href=\"certificate.json\">certificate.json</a></p>\
+         </body></html>",
+        id = certificate.license_id,
+        table = table
+    )
+}
+
+fn license_certificate_bodies(license: &License) -> [(String, Vec<u8>); 2] {
+    let certificate = license_certificate(license);
+    [
+        (
+            format!("/licenses/{}/certificate", license.id),
+            certificate_html(&certificate).into_bytes(),
+        ),
+        (
+            format!("/licenses/{}/certificate.json", license.id),
+            serde_json::to_vec(&certificate).unwrap_or_default(),
+        ),
+    ]
+}
+
+// Store a license and refresh the certified hashes of its certificate documents
+fn store_license(license: License) -> Option<License> {
+    let id = license.id;
+    let previous = LICENSE_STORAGE.with(|s| s.borrow_mut().insert(id, license.clone()));
+    if let Some(key) = previous.as_ref().and_then(license_end_key) {
+        LICENSE_END_INDEX.with(|i| i.borrow_mut().remove(&key));
+    }
+    index_license_end(&license);
+    // A thread is between the license's current owner and licensee; it is closed when either
+    // changes, so the new party never reads what the previous one wrote
+    if let Some(previous) = &previous {
+        if previous.owner_id != license.owner_id || previous.licensee_id != license.licensee_id {
+            remove_license_messages(previous);
+        }
+    }
+    notify_license_change(previous.as_ref(), &license);
+    certify_license(id);
+    queue_license_signature(id);
+    sync_license_token(id);
+    previous
+}
+
+// Define query functions to get the signed certificate of an approved license
+#[ic_cdk::query]
+fn get_license_signature(license_id: u64) -> Result<LicenseSignature, Error> {
+    match SIGNATURE_STORAGE.with(|s| s.borrow().get(&license_id)) {
+        Some(signature) => Ok(signature),
         None => Err(Error::NotFound {
-            msg: format!("owner id:{} could not be found", song.owner_id),
+            msg: format!("no signature could be found for license id:{}", license_id),
         }),
     }
 }
 
+// Owner or licensee has a license's current certificate signed now instead of waiting for the
+// next signing run. Returns the stored signature if it already covers the current certificate.
 #[ic_cdk::update]
-fn update_song(payload: UpdateSongPayload) -> Result<Song, Error> {
-    let song = match _get_song(&payload.id) {
-        Some(song) => song,
+async fn request_license_signature(
+    auth_key: String,
+    license_id: u64,
+) -> Result<LicenseSignature, Error> {
+    let license = match _get_license(&license_id) {
+        Some(license) => license,
         None => {
             return Err(Error::NotFound {
-                msg: format!("song id:{} could not be found", payload.id),
+                msg: format!("license id:{} could not be found", license_id),
             })
         }
     };
 
-    let owner = match _get_owner(&song.owner_id) {
-        Some(owner) => owner,
+    if message_party(&license, &auth_key).is_none() {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "auth key:{} is invalid, only the owner or licensee can request a signature",
+                auth_key
+            ),
+        });
+    }
+
+    let (document, sha256) = match unsigned_certificate(license_id) {
+        Ok(Some(unsigned)) => unsigned,
+        Ok(None) => return get_license_signature(license_id),
+        Err(e) => return Err(e),
+    };
+
+    sign_license_certificate(license_id, document, sha256)
+        .await
+        .map_err(|msg| Error::InternalError { msg })
+}
+
+// Public key that verifies license signatures (SEC1 compressed secp256k1, hex)
+#[ic_cdk::update]
+async fn get_signing_public_key() -> Result<SigningKey, Error> {
+    let config = SIGNING_CONFIG.with(|c| c.borrow().get().clone());
+    let public_key = signing_public_key()
+        .await
+        .map_err(|msg| Error::InternalError { msg })?;
+
+    Ok(SigningKey {
+        key_name: config.key_name,
+        algorithm: SIGNATURE_ALGORITHM.to_string(),
+        public_key,
+    })
+}
+
+// Controllers choose the threshold key, e.g. dfx_test_key locally and key_1 on mainnet
+#[ic_cdk::update]
+fn set_signing_key(key_name: String) -> Result<(), Error> {
+    authorize_controller("set the signing key")?;
+
+    SIGNING_CONFIG
+        .with(|c| {
+            c.borrow_mut().set(SigningConfig {
+                key_name,
+                public_key: None,
+            })
+        })
+        .map(|_| ())
+        .map_err(|_| Error::InternalError {
+            msg: "signing key could not be stored".to_string(),
+        })
+}
+
+const SIGNATURE_ALGORITHM: &str = "ecdsa-secp256k1-sha256";
+
+fn signing_key_id() -> EcdsaKeyId {
+    EcdsaKeyId {
+        curve: EcdsaCurve::Secp256k1,
+        name: SIGNING_CONFIG.with(|c| c.borrow().get().key_name.clone()),
+    }
+}
+
+fn signing_derivation_path() -> Vec<Vec<u8>> {
+    vec![b"license-certificates".to_vec()]
+}
+
+// Certificate JSON and its SHA-256 when an approved license has no signature for it yet
+fn unsigned_certificate(license_id: u64) -> Result<Option<(String, String)>, Error> {
+    let license = match _get_license(&license_id) {
+        Some(license) => license,
         None => {
             return Err(Error::NotFound {
-                msg: format!("owner id:{} could not be found", song.owner_id),
+                msg: format!("license id:{} could not be found", license_id),
             })
         }
     };
 
-    if owner.auth_key != payload.auth_key {
-        return Err(Error::InvalidPayload {
+    if !license.approved {
+        return Err(Error::Conflict {
+            msg: format!("license id:{} has not been approved", license_id),
+            license_ids: Vec::new(),
+        });
+    }
+
+    let document = serde_json::to_string(&license_certificate(&license)).unwrap_or_default();
+    let sha256 = to_hex(&Sha256::digest(document.as_bytes()));
+
+    match SIGNATURE_STORAGE.with(|s| s.borrow().get(&license_id)) {
+        Some(signature) if signature.sha256 == sha256 => Ok(None),
+        _ => Ok(Some((document, sha256))),
+    }
+}
+
+// Queue an approved license whose certificate is not signed yet for the next signing run
+fn queue_license_signature(license_id: u64) {
+    if let Ok(Some(_)) = unsigned_certificate(license_id) {
+        SIGNING_QUEUE.with(|q| q.borrow_mut().insert(license_id, ()));
+    }
+}
+
+fn start_signing_runs() {
+    runtime::set_timer_interval(
+        std::time::Duration::from_secs(SIGNING_INTERVAL_SECS),
+        || runtime::spawn(sign_queued_licenses()),
+    );
+}
+
+// Sign the certificates of up to a batch of queued licenses. A run stops at the first failed
+// signature, e.g. for lack of cycles, and leaves the rest queued for the next run.
+async fn sign_queued_licenses() {
+    if SIGNING_RUN_ACTIVE.with(|active| active.replace(true)) {
+        return;
+    }
+
+    let license_ids: Vec<u64> = SIGNING_QUEUE.with(|q| {
+        q.borrow()
+            .iter()
+            .map(|(id, _)| id)
+            .take(SIGNING_BATCH_SIZE)
+            .collect()
+    });
+    for license_id in license_ids {
+        match unsigned_certificate(license_id) {
+            Ok(Some((document, sha256))) => {
+                if sign_license_certificate(license_id, document, sha256)
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            // Signed in the meantime, no longer approved or deleted
+            _ => {
+                SIGNING_QUEUE.with(|q| q.borrow_mut().remove(&license_id));
+            }
+        }
+    }
+
+    SIGNING_RUN_ACTIVE.with(|active| *active.borrow_mut() = false);
+}
+
+async fn sign_license_certificate(
+    license_id: u64,
+    document: String,
+    sha256: String,
+) -> Result<LicenseSignature, String> {
+    let public_key = signing_public_key().await?;
+    let (response,) = sign_with_ecdsa(SignWithEcdsaArgument {
+        message_hash: Sha256::digest(document.as_bytes()).to_vec(),
+        derivation_path: signing_derivation_path(),
+        key_id: signing_key_id(),
+    })
+    .await
+    .map_err(|(code, msg)| format!("sign_with_ecdsa failed ({:?}): {}", code, msg))?;
+
+    let signature = LicenseSignature {
+        license_id,
+        document,
+        sha256,
+        signature: to_hex(&response.signature),
+        algorithm: SIGNATURE_ALGORITHM.to_string(),
+        key_name: signing_key_id().name,
+        public_key,
+        signed_at: runtime::time(),
+    };
+
+    // The license may have changed while signing; only a signature of its current
+    // certificate is kept, the newer change schedules its own
+    let current = _get_license(&license_id)
+        .map(|license| serde_json::to_string(&license_certificate(&license)).unwrap_or_default());
+    if current.as_ref() != Some(&signature.document) {
+        return Err(format!(
+            "license id:{} changed while its certificate was signed",
+            license_id
+        ));
+    }
+
+    SIGNATURE_STORAGE.with(|s| s.borrow_mut().insert(license_id, signature.clone()));
+    SIGNING_QUEUE.with(|q| q.borrow_mut().remove(&license_id));
+    Ok(signature)
+}
+
+async fn signing_public_key() -> Result<String, String> {
+    if let Some(public_key) = SIGNING_CONFIG.with(|c| c.borrow().get().public_key.clone()) {
+        return Ok(public_key);
+    }
+
+    let (response,) = ecdsa_public_key(EcdsaPublicKeyArgument {
+        canister_id: None,
+        derivation_path: signing_derivation_path(),
+        key_id: signing_key_id(),
+    })
+    .await
+    .map_err(|(code, msg)| format!("ecdsa_public_key failed ({:?}): {}", code, msg))?;
+
+    let public_key = to_hex(&response.public_key);
+    SIGNING_CONFIG
+        .with(|c| {
+            let mut config = c.borrow().get().clone();
+            config.public_key = Some(public_key.clone());
+            c.borrow_mut().set(config)
+        })
+        .map_err(|_| "public key could not be stored".to_string())?;
+
+    Ok(public_key)
+}
+
+// Song details appear on license certificates, so they are refreshed when the song changes
+fn certify_song_licenses(song_id: u64) {
+    let licenses_vec: Vec<(u64, License)> = LICENSE_STORAGE.with(|s| s.borrow().iter().collect());
+    for (id, license) in licenses_vec {
+        if license.song_id == song_id {
+            certify_license(id);
+        }
+    }
+}
+
+fn certify_license(id: u64) {
+    let license = match _get_license(&id) {
+        Some(license) => license,
+        None => return,
+    };
+
+    CERTIFIED_TREE.with(|t| {
+        let mut tree = t.borrow_mut();
+        insert_license_certificates(&mut tree, &license);
+        http::set_certified_tree(&tree);
+    });
+}
+
+fn insert_license_certificates(tree: &mut RbTree<String, Hash>, license: &License) {
+    for (path, body) in license_certificate_bodies(license) {
+        tree.insert(path, Sha256::digest(&body).into());
+    }
+}
+
+fn uncertify_license(id: u64) {
+    CERTIFIED_TREE.with(|t| {
+        let mut tree = t.borrow_mut();
+        tree.delete(format!("/licenses/{}/certificate", id).as_bytes());
+        tree.delete(format!("/licenses/{}/certificate.json", id).as_bytes());
+        http::set_certified_tree(&tree);
+    });
+}
+
+fn serve_license_certificate(license_id: u64, json: bool) -> HttpResponse {
+    let license = match _get_license(&license_id) {
+        Some(license) => license,
+        None => {
+            return HttpResponse::text(
+                404,
+                &format!("license id:{} could not be found", license_id),
+            )
+        }
+    };
+
+    let [(html_path, html), (json_path, json_body)] = license_certificate_bodies(&license);
+    let (path, mut response) = match json {
+        true => (
+            json_path,
+            HttpResponse::new(200, "application/json", json_body)
+                .with_header("Access-Control-Allow-Origin", "*"),
+        ),
+        false => (
+            html_path,
+            HttpResponse::new(200, "text/html; charset=utf-8", html),
+        ),
+    };
+
+    if let Some((name, value)) =
+        CERTIFIED_TREE.with(|t| http::certificate_header(&t.borrow(), &path))
+    {
+        response = response.with_header(&name, &value);
+    }
+    response
+}
+
+// Define update functions to represent an active license as an ICRC-7 token. The token is
+// held by the principal linked to the licensee with set_licensee_principal.
+#[ic_cdk::update]
+fn mint_license_token(auth_key: String, license_id: u64) -> Result<LicenseToken, Error> {
+    let license = authorize_token_licensee(&auth_key, license_id)?;
+
+    if TOKEN_STORAGE.with(|s| s.borrow().contains_key(&license_id)) {
+        return Err(Error::Conflict {
             msg: format!(
-                "auth key:{} is invalid, only song owner can update",
-                payload.auth_key
+                "a token for license id:{} has already been minted",
+                license_id
+            ),
+            license_ids: vec![license_id],
+        });
+    }
+
+    let today = (runtime::time() / 86_400_000_000_000) as i64;
+    let status = license_status(&license, today);
+    if status != LicenseStatus::Active {
+        return Err(Error::Conflict {
+            msg: format!(
+                "license id:{} is {:?}, only active licenses can be minted",
+                license_id, status
+            ),
+            license_ids: Vec::new(),
+        });
+    }
+
+    if token_owner(&license).is_none() {
+        return Err(Error::Conflict {
+            msg: format!(
+                "licensee id:{} has no principal to hold the token",
+                license.licensee_id
             ),
+            license_ids: Vec::new(),
         });
     }
 
-    let mut new_song = song.clone();
-    new_song.title = payload.title.clone();
-    new_song.artist = payload.artist;
-    new_song.year = payload.year;
-    new_song.genre = payload.genre;
-    new_song.price = payload.price;
+    let token = LicenseToken {
+        license_id,
+        minted_at: runtime::time(),
+    };
+    TOKEN_STORAGE.with(|s| s.borrow_mut().insert(license_id, token.clone()));
+    Ok(token)
+}
 
-    match SONG_STORAGE.with(|s| s.borrow_mut().insert(payload.id, new_song.clone())) {
-        Some(_) => Ok(new_song),
-        None => Err(Error::InvalidPayload {
-            msg: format!(
-                "song title:{} id: {} could not be updated",
-                payload.title, payload.id
-            ),
+// The licensee may give up a license's token; the license itself is unaffected
+#[ic_cdk::update]
+fn burn_license_token(auth_key: String, license_id: u64) -> Result<LicenseToken, Error> {
+    authorize_token_licensee(&auth_key, license_id)?;
+
+    match burn_token(license_id) {
+        Some(token) => Ok(token),
+        None => Err(Error::NotFound {
+            msg: format!("no token could be found for license id:{}", license_id),
         }),
     }
 }
 
-#[ic_cdk::update]
-fn delete_song(auth_key: String, id: u64) -> Result<Song, Error> {
-    let song = match _get_song(&id) {
-        Some(song) => song,
+fn authorize_token_licensee(auth_key: &str, license_id: u64) -> Result<License, Error> {
+    let license = match _get_license(&license_id) {
+        Some(license) => license,
         None => {
             return Err(Error::NotFound {
-                msg: format!("song id:{} could not be found", id),
+                msg: format!("license id:{} could not be found", license_id),
             })
         }
     };
 
-    let owner = match _get_owner(&song.owner_id) {
-        Some(owner) => owner,
+    let licensee = match _get_licensee(&license.licensee_id) {
+        Some(licensee) => licensee,
         None => {
             return Err(Error::NotFound {
-                msg: format!("owner id:{} could not be found", song.owner_id),
+                msg: format!("licensee id:{} could not be found", license.licensee_id),
             })
         }
     };
 
-    if owner.auth_key != auth_key {
-        return Err(Error::InvalidPayload {
+    if !licensee_key_matches(&licensee, auth_key) {
+        return Err(Error::Unauthorized {
             msg: format!(
-                "auth key:{} is invalid, only song owner can delete",
+                "auth key:{} is invalid, only the licensee can manage the license token",
                 auth_key
             ),
         });
     }
 
-    match remove_song_from_owner(id) {
-        Ok(_) => (),
-        Err(e) => return Err(e),
+    Ok(license)
+}
+
+// Account holding a license token: the default account of the licensee's principal
+fn token_owner(license: &License) -> Option<icrc7::Account> {
+    _get_licensee(&license.licensee_id)?
+        .principal
+        .map(|owner| icrc7::Account {
+            owner,
+            subaccount: None,
+        })
+}
+
+fn burn_token(license_id: u64) -> Option<LicenseToken> {
+    TOKEN_STORAGE.with(|s| s.borrow_mut().remove(&license_id))
+}
+
+// A token only exists while its license is active and its licensee can hold it, so it is
+// burned when the license is revoked or expires, or passes to a licensee without a principal
+fn sync_license_token(license_id: u64) {
+    if !TOKEN_STORAGE.with(|s| s.borrow().contains_key(&license_id)) {
+        return;
     }
 
-    match remove_song_from_licensee(id) {
-        Ok(_) => (),
-        Err(e) => return Err(e),
+    let today = (runtime::time() / 86_400_000_000_000) as i64;
+    let backed = _get_license(&license_id).is_some_and(|license| {
+        license_status(&license, today) == LicenseStatus::Active && token_owner(&license).is_some()
+    });
+
+    if !backed {
+        burn_token(license_id);
     }
+}
 
-    match SONG_STORAGE.with(|s| s.borrow_mut().remove(&id)) {
-        Some(song) => Ok(song),
-        None => Err(Error::InvalidPayload {
-            msg: format!("song id:{} could not be deleted", id),
-        }),
+// Expiry is not a write, so tokens of expired licenses are burned by a periodic sweep
+fn burn_expired_tokens() {
+    let token_ids: Vec<u64> = TOKEN_STORAGE.with(|s| s.borrow().iter().map(|(id, _)| id).collect());
+    for id in token_ids {
+        sync_license_token(id);
     }
 }
 
-fn _get_owner(id: &u64) -> Option<Owner> {
-    OWNER_STORAGE.with(|s| s.borrow().get(id))
+fn start_token_sweep() {
+    runtime::set_timer_interval(
+        std::time::Duration::from_secs(TOKEN_SWEEP_INTERVAL_SECS),
+        || {
+            burn_expired_tokens();
+            notify_expiring_licenses();
+        },
+    );
 }
 
-fn add_song_to_owner(owner_id: u64, song_id: u64) -> Result<(), Error> {
-    let mut owner = match _get_owner(&owner_id) {
-        Some(owner) => owner,
-        None => {
-            return Err(Error::NotFound {
-                msg: format!("owner id:{} could not be found", owner_id),
-            })
-        }
-    };
+fn token_accounts() -> Vec<(u64, icrc7::Account)> {
+    let token_ids: Vec<u64> = TOKEN_STORAGE.with(|s| s.borrow().iter().map(|(id, _)| id).collect());
+    token_ids
+        .into_iter()
+        .filter_map(|id| Some((id, token_owner(&_get_license(&id)?)?)))
+        .collect()
+}
 
-    owner.song_ids.push(song_id);
+// Token ids after `prev`, at most `take` of them
+fn page_token_ids(ids: Vec<u64>, prev: Option<Nat>, take: Option<Nat>) -> Vec<Nat> {
+    let prev = prev.map(|prev| icrc7::token_id(&prev).unwrap_or(u64::MAX));
+    let take = take
+        .and_then(|take| usize::try_from(&take.0).ok())
+        .unwrap_or(icrc7::DEFAULT_TAKE_VALUE)
+        .min(icrc7::MAX_TAKE_VALUE);
 
-    match OWNER_STORAGE.with(|s| s.borrow_mut().insert(owner_id, owner.clone())) {
-        Some(_) => Ok(()),
-        None => Err(Error::InvalidPayload {
-            msg: format!(
-                "song id:{} could not be added to owner id:{}",
-                song_id, owner_id
+    ids.into_iter()
+        .filter(|id| prev.is_none_or(|prev| *id > prev))
+        .take(take)
+        .map(Nat::from)
+        .collect()
+}
+
+fn token_metadata(license: &License) -> Vec<(String, icrc7::Value)> {
+    let song = _get_song(&license.song_id);
+    let licensee = _get_licensee(&license.licensee_id);
+    let title = song
+        .as_ref()
+        .map(|song| song.title.clone())
+        .unwrap_or_default();
+
+    let mut metadata = vec![
+        (
+            "icrc7:name".to_string(),
+            icrc7::text(&format!("License #{}: {}", license.id, title)),
+        ),
+        (
+            "icrc7:description".to_string(),
+            icrc7::text(&format!(
+                "{:?} license for {} by {}",
+                license.license_type,
+                title,
+                song.as_ref()
+                    .map(|song| song.artist.as_str())
+                    .unwrap_or_default()
+            )),
+        ),
+        ("license:id".to_string(), icrc7::nat(license.id)),
+        ("license:song_id".to_string(), icrc7::nat(license.song_id)),
+        ("license:song_title".to_string(), icrc7::text(&title)),
+        (
+            "license:licensee".to_string(),
+            icrc7::text(&licensee.map(|licensee| licensee.name).unwrap_or_default()),
+        ),
+        (
+            "license:type".to_string(),
+            icrc7::text(&format!("{:?}", license.license_type)),
+        ),
+        (
+            "license:media".to_string(),
+            icrc7::Value::Array(
+                license
+                    .media
+                    .iter()
+                    .map(|media| icrc7::text(&format!("{:?}", media)))
+                    .collect(),
             ),
-        }),
+        ),
+        (
+            "license:territories".to_string(),
+            icrc7::Value::Array(license.territories.iter().map(|t| icrc7::text(t)).collect()),
+        ),
+        (
+            "license:start_date".to_string(),
+            icrc7::text(&license.start_date),
+        ),
+        ("license:expiry".to_string(), icrc7::text(&license.end_date)),
+        (
+            "license:exclusive".to_string(),
+            icrc7::text(&license.exclusive.to_string()),
+        ),
+        (
+            "license:transferable".to_string(),
+            icrc7::text(&license.transferable.to_string()),
+        ),
+        (
+            "license:certificate".to_string(),
+            icrc7::text(&format!("/licenses/{}/certificate", license.id)),
+        ),
+    ];
+
+    if let Some(max_copies) = license.max_copies {
+        metadata.push(("license:max_copies".to_string(), icrc7::nat(max_copies)));
+    }
+    if let Some(max_streams) = license.max_streams {
+        metadata.push(("license:max_streams".to_string(), icrc7::nat(max_streams)));
     }
+    metadata
 }
 
-#[ic_cdk::update]
-fn create_owner(payload: OwnerPayload) -> Result<Owner, Error> {
-    // Increment the global ID counter to get a new unique ID
-    let id = ID_COUNTER
-        .with(|counter| {
-            let current_id = *counter.borrow().get();
-            counter.borrow_mut().set(current_id + 1)
+// Define query functions of the ICRC-7 interface
+#[ic_cdk::query]
+fn icrc7_symbol() -> String {
+    icrc7::SYMBOL.to_string()
+}
+
+#[ic_cdk::query]
+fn icrc7_name() -> String {
+    icrc7::NAME.to_string()
+}
+
+#[ic_cdk::query]
+fn icrc7_description() -> Option<String> {
+    Some(icrc7::DESCRIPTION.to_string())
+}
+
+#[ic_cdk::query]
+fn icrc7_logo() -> Option<String> {
+    None
+}
+
+#[ic_cdk::query]
+fn icrc7_total_supply() -> Nat {
+    Nat::from(TOKEN_STORAGE.with(|s| s.borrow().len()))
+}
+
+#[ic_cdk::query]
+fn icrc7_supply_cap() -> Option<Nat> {
+    None
+}
+
+#[ic_cdk::query]
+fn icrc7_max_query_batch_size() -> Option<Nat> {
+    Some(Nat::from(icrc7::MAX_QUERY_BATCH_SIZE))
+}
+
+#[ic_cdk::query]
+fn icrc7_max_update_batch_size() -> Option<Nat> {
+    Some(Nat::from(icrc7::MAX_UPDATE_BATCH_SIZE))
+}
+
+#[ic_cdk::query]
+fn icrc7_default_take_value() -> Option<Nat> {
+    Some(Nat::from(icrc7::DEFAULT_TAKE_VALUE))
+}
+
+#[ic_cdk::query]
+fn icrc7_max_take_value() -> Option<Nat> {
+    Some(Nat::from(icrc7::MAX_TAKE_VALUE))
+}
+
+#[ic_cdk::query]
+fn icrc7_max_memo_size() -> Option<Nat> {
+    Some(Nat::from(icrc7::MAX_MEMO_SIZE))
+}
+
+#[ic_cdk::query]
+fn icrc7_atomic_batch_transfers() -> Option<bool> {
+    Some(false)
+}
+
+// Transfers are not deduplicated, so there is no transaction window
+#[ic_cdk::query]
+fn icrc7_tx_window() -> Option<Nat> {
+    None
+}
+
+#[ic_cdk::query]
+fn icrc7_permitted_drift() -> Option<Nat> {
+    None
+}
+
+#[ic_cdk::query]
+fn icrc7_collection_metadata() -> Vec<(String, icrc7::Value)> {
+    vec![
+        ("icrc7:symbol".to_string(), icrc7::text(icrc7::SYMBOL)),
+        ("icrc7:name".to_string(), icrc7::text(icrc7::NAME)),
+        (
+            "icrc7:description".to_string(),
+            icrc7::text(icrc7::DESCRIPTION),
+        ),
+        (
+            "icrc7:total_supply".to_string(),
+            icrc7::Value::Nat(icrc7_total_supply()),
+        ),
+        (
+            "icrc7:max_query_batch_size".to_string(),
+            icrc7::nat(icrc7::MAX_QUERY_BATCH_SIZE as u64),
+        ),
+        (
+            "icrc7:max_update_batch_size".to_string(),
+            icrc7::nat(icrc7::MAX_UPDATE_BATCH_SIZE as u64),
+        ),
+        (
+            "icrc7:default_take_value".to_string(),
+            icrc7::nat(icrc7::DEFAULT_TAKE_VALUE as u64),
+        ),
+        (
+            "icrc7:max_take_value".to_string(),
+            icrc7::nat(icrc7::MAX_TAKE_VALUE as u64),
+        ),
+        (
+            "icrc7:max_memo_size".to_string(),
+            icrc7::nat(icrc7::MAX_MEMO_SIZE as u64),
+        ),
+    ]
+}
+
+#[ic_cdk::query]
+fn icrc7_token_metadata(token_ids: Vec<Nat>) -> Vec<Option<Vec<(String, icrc7::Value)>>> {
+    token_ids
+        .iter()
+        .take(icrc7::MAX_QUERY_BATCH_SIZE)
+        .map(|id| {
+            let id = icrc7::token_id(id)?;
+            TOKEN_STORAGE.with(|s| s.borrow().get(&id))?;
+            _get_license(&id).map(|license| token_metadata(&license))
         })
-        .expect("Cannot increment Ids");
+        .collect()
+}
 
-    let owner = Owner {
-        id,
-        name: payload.name.clone(),
-        email: payload.email.clone(),
-        auth_key: payload.auth_key.clone(),
-        song_ids: Vec::new(),
-        license_ids: Vec::new(),
-    };
+#[ic_cdk::query]
+fn icrc7_owner_of(token_ids: Vec<Nat>) -> Vec<Option<icrc7::Account>> {
+    token_ids
+        .iter()
+        .take(icrc7::MAX_QUERY_BATCH_SIZE)
+        .map(|id| {
+            let id = icrc7::token_id(id)?;
+            TOKEN_STORAGE.with(|s| s.borrow().get(&id))?;
+            token_owner(&_get_license(&id)?)
+        })
+        .collect()
+}
 
-    match OWNER_STORAGE.with(|s| s.borrow_mut().insert(id, owner.clone())) {
-        None => Ok(owner),
-        Some(_) => Err(Error::InvalidPayload {
-            msg: format!("owner name:{} could not be created", payload.name),
-        }),
-    }
+#[ic_cdk::query]
+fn icrc7_balance_of(accounts: Vec<icrc7::Account>) -> Vec<Nat> {
+    let owners = token_accounts();
+    accounts
+        .iter()
+        .take(icrc7::MAX_QUERY_BATCH_SIZE)
+        .map(|account| {
+            Nat::from(
+                owners
+                    .iter()
+                    .filter(|(_, owner)| owner.same_as(account))
+                    .count(),
+            )
+        })
+        .collect()
 }
 
 #[ic_cdk::query]
-fn get_license(id: u64) -> Result<License, Error> {
-    match _get_license(&id) {
-        Some(license) => Ok(license),
-        None => Err(Error::NotFound {
-            msg: format!("license id:{} could not be found", id),
-        }),
-    }
+fn icrc7_tokens(prev: Option<Nat>, take: Option<Nat>) -> Vec<Nat> {
+    let ids: Vec<u64> = TOKEN_STORAGE.with(|s| s.borrow().iter().map(|(id, _)| id).collect());
+    page_token_ids(ids, prev, take)
 }
 
 #[ic_cdk::query]
-fn get_owner_license_requests(id: u64) -> Result<Vec<License>, Error> {
-    let licenses_vec: Vec<(u64, License)> = LICENSE_STORAGE.with(|s| s.borrow().iter().collect());
-    let licenses: Vec<License> = licenses_vec
+fn icrc7_tokens_of(account: icrc7::Account, prev: Option<Nat>, take: Option<Nat>) -> Vec<Nat> {
+    let ids: Vec<u64> = token_accounts()
         .into_iter()
-        .map(|(_, license)| license)
+        .filter(|(_, owner)| owner.same_as(&account))
+        .map(|(id, _)| id)
         .collect();
-    let mut owner_licenses: Vec<License> = Vec::new();
+    page_token_ids(ids, prev, take)
+}
 
-    for license in licenses {
-        if license.owner_id == id {
-            owner_licenses.push(license);
-        }
-    }
+#[ic_cdk::query]
+fn icrc10_supported_standards() -> Vec<icrc7::SupportedStandard> {
+    vec![
+        icrc7::SupportedStandard {
+            name: "ICRC-7".to_string(),
+            url: "https://github.com/dfinity/ICRC/tree/main/ICRCs/ICRC-7".to_string(),
+        },
+        icrc7::SupportedStandard {
+            name: "ICRC-10".to_string(),
+            url: "https://github.com/dfinity/ICRC/tree/main/ICRCs/ICRC-10".to_string(),
+        },
+    ]
+}
 
-    match owner_licenses.len() {
-        0 => Err(Error::NotFound {
-            msg: format!("no licenses could be found for owner id:{}", id),
-        }),
-        _ => Ok(owner_licenses),
+// Transfer license tokens between licensees. The license moves with its token, so a token can
+// only be sent to a principal linked to a licensee and only when the owner made it transferable.
+#[ic_cdk::update]
+fn icrc7_transfer(args: Vec<icrc7::TransferArg>) -> Vec<Option<icrc7::TransferResult>> {
+    if args.len() > icrc7::MAX_UPDATE_BATCH_SIZE {
+        return vec![Some(icrc7::TransferResult::Err(
+            icrc7::TransferError::GenericBatchError {
+                error_code: Nat::from(1u64),
+                message: format!(
+                    "at most {} transfers are allowed per call",
+                    icrc7::MAX_UPDATE_BATCH_SIZE
+                ),
+            },
+        ))];
     }
+
+    let caller = runtime::caller();
+    args.into_iter()
+        .map(|arg| {
+            Some(match transfer_token(caller, arg) {
+                Ok(tx_id) => icrc7::TransferResult::Ok(Nat::from(tx_id)),
+                Err(e) => icrc7::TransferResult::Err(e),
+            })
+        })
+        .collect()
 }
 
-#[ic_cdk::query]
-fn get_licensee_licenses(id: u64) -> Result<Vec<License>, Error> {
-    let licenses_vec: Vec<(u64, License)> = LICENSE_STORAGE.with(|s| s.borrow().iter().collect());
-    let licenses: Vec<License> = licenses_vec
-        .into_iter()
-        .map(|(_, license)| license)
-        .collect();
-    let mut licensee_licenses: Vec<License> = Vec::new();
+fn transfer_token(caller: Principal, arg: icrc7::TransferArg) -> Result<u64, icrc7::TransferError> {
+    let generic_error = |message: String| icrc7::TransferError::GenericError {
+        error_code: Nat::from(0u64),
+        message,
+    };
 
-    for license in licenses {
-        if license.licensee_id == id {
-            licensee_licenses.push(license);
-        }
+    if arg
+        .memo
+        .as_ref()
+        .is_some_and(|memo| memo.len() > icrc7::MAX_MEMO_SIZE)
+    {
+        return Err(generic_error(format!(
+            "memo is longer than {} bytes",
+            icrc7::MAX_MEMO_SIZE
+        )));
     }
 
-    match licensee_licenses.len() {
-        0 => Err(Error::NotFound {
-            msg: format!("no licenses could be found for licensee id:{}", id),
-        }),
-        _ => Ok(licensee_licenses),
+    let now = runtime::time();
+    if arg
+        .created_at_time
+        .is_some_and(|created_at| created_at > now)
+    {
+        return Err(icrc7::TransferError::CreatedInFuture { ledger_time: now });
     }
-}
 
-#[ic_cdk::update]
-fn create_license_request(payload: LicensePayload) -> Result<License, Error> {
+    let license = match icrc7::token_id(&arg.token_id)
+        .filter(|id| TOKEN_STORAGE.with(|s| s.borrow().contains_key(id)))
+        .and_then(|id| _get_license(&id))
+    {
+        Some(license) => license,
+        None => return Err(icrc7::TransferError::NonExistingTokenId),
+    };
+
+    let from = icrc7::Account {
+        owner: caller,
+        subaccount: arg.from_subaccount,
+    };
+    if !from.is_valid() {
+        return Err(generic_error(
+            "from_subaccount must be 32 bytes long".to_string(),
+        ));
+    }
+    if !token_owner(&license).is_some_and(|owner| owner.same_as(&from)) {
+        return Err(icrc7::TransferError::Unauthorized);
+    }
+
+    if !license.transferable {
+        return Err(generic_error(format!(
+            "license id:{} is not transferable",
+            license.id
+        )));
+    }
+
+    // Licensees hold their tokens in the default account of their principal
+    let recipient = match licensee_by_principal(&arg.to.owner) {
+        Some(recipient) if arg.to.is_valid() && arg.to.subaccount() == [0u8; 32] => recipient,
+        _ => return Err(icrc7::TransferError::InvalidRecipient),
+    };
+    if recipient.id == license.licensee_id {
+        return Err(icrc7::TransferError::InvalidRecipient);
+    }
+
+    transfer_license(&license, recipient.id).map_err(|e| generic_error(error_message(e)))?;
+
     // Increment the global ID counter to get a new unique ID
-    let id = ID_COUNTER
+    let tx_id = ID_COUNTER
         .with(|counter| {
             let current_id = *counter.borrow().get();
             counter.borrow_mut().set(current_id + 1)
         })
         .expect("Cannot increment Ids");
 
-    let song = match _get_song(&payload.song_id) {
-        Some(song) => song,
-        None => {
-            return Err(Error::NotFound {
-                msg: format!("song id:{} could not be found", payload.song_id),
-            })
-        }
-    };
-
-    let license = License {
-        id,
-        song_id: payload.song_id,
-        owner_id: song.owner_id,
-        licensee_id: payload.licensee_id,
-        approved: false,
-        price: 0,
-        start_date: payload.start_date,
-        end_date: payload.end_date,
-    };
-
-    match LICENSE_STORAGE.with(|s| s.borrow_mut().insert(id, license.clone())) {
-        None => Ok(license),
-        Some(_) => Err(Error::InvalidPayload {
-            msg: format!("license id:{} could not be created", id),
-        }),
-    }
+    Ok(tx_id)
 }
 
+// Send a message in the thread of a license, as its licensee or the owner of its song
 #[ic_cdk::update]
-fn approve_license(payload: Approvepayload) -> Result<License, Error> {
+fn send_license_message(payload: MessagePayload) -> Result<LicenseMessage, Error> {
     let license = match _get_license(&payload.license_id) {
         Some(license) => license,
         None => {
@@ -556,198 +7293,606 @@ fn approve_license(payload: Approvepayload) -> Result<License, Error> {
         }
     };
 
-    let owner = match _get_owner(&license.owner_id) {
-        Some(owner) => owner,
+    let (sender, sender_id) = match message_party(&license, &payload.auth_key) {
+        Some(party) => party,
+        None => {
+            return Err(Error::Unauthorized {
+                msg: format!(
+                    "auth key:{} is invalid, only the licensee and the owner can send messages",
+                    payload.auth_key
+                ),
+            })
+        }
+    };
+
+    let body =
+        validation::message(&payload.body).map_err(|reason| validation_error("body", reason))?;
+    count_sent_message(sender, sender_id)?;
+
+    // Increment the global ID counter to get a new unique ID
+    let id = ID_COUNTER
+        .with(|counter| {
+            let current_id = *counter.borrow().get();
+            counter.borrow_mut().set(current_id + 1)
+        })
+        .expect("Cannot increment Ids");
+
+    let message = LicenseMessage {
+        id,
+        license_id: license.id,
+        sender,
+        sender_id,
+        body,
+        sent_at: runtime::time(),
+    };
+    MESSAGE_STORAGE.with(|s| s.borrow_mut().insert((license.id, id), message.clone()));
+
+    // Replying counts as having read the thread
+    let (recipient, recipient_id) = match sender {
+        MessageParty::Owner => (MessageParty::Licensee, license.licensee_id),
+        MessageParty::Licensee => (MessageParty::Owner, license.owner_id),
+    };
+    set_unread_count(sender, sender_id, license.id, 0);
+    let unread = unread_count(recipient, recipient_id, license.id);
+    set_unread_count(
+        recipient,
+        recipient_id,
+        license.id,
+        unread.saturating_add(1),
+    );
+
+    Ok(message)
+}
+
+// Define query functions to page through the thread of a license, oldest message first,
+// continuing after the message prev. Canister controllers can read every thread.
+#[ic_cdk::query]
+fn get_license_messages(
+    auth_key: String,
+    license_id: u64,
+    prev: Option<u64>,
+    take: Option<u32>,
+) -> Result<Vec<LicenseMessage>, Error> {
+    let license = match _get_license(&license_id) {
+        Some(license) => license,
         None => {
             return Err(Error::NotFound {
-                msg: format!("owner id:{} could not be found", license.owner_id),
+                msg: format!("license id:{} could not be found", license_id),
             })
         }
     };
 
-    if owner.auth_key != payload.auth_key {
-        return Err(Error::InvalidPayload {
-            msg: format!(
-                "auth key:{} is invalid, only song owner can approve",
-                payload.auth_key
-            ),
-        });
-    }
-
-    if license.approved {
-        return Err(Error::AlreadyApproved {
+    if !runtime::is_controller(&runtime::caller()) && message_party(&license, &auth_key).is_none() {
+        return Err(Error::Unauthorized {
             msg: format!(
-                "license id:{} has already been approved",
-                payload.license_id
+                "auth key:{} is invalid, only the licensee and the owner can read messages",
+                auth_key
             ),
         });
     }
 
-    let mut new_license = license.clone();
-    new_license.approved = true;
-    new_license.price = payload.cost;
-
-    match add_license_to_owner(license.owner_id, license.id) {
-        Ok(_) => (),
-        Err(e) => return Err(e),
-    }
-
-    match add_license_to_licensee(license.licensee_id, license.id) {
-        Ok(_) => (),
-        Err(e) => return Err(e),
-    }
+    let take = take
+        .map_or(DEFAULT_PAGE_TAKE, |take| take as usize)
+        .min(MAX_PAGE_TAKE);
+    let start = prev.map_or(0, |prev| prev.saturating_add(1));
 
-    match LICENSE_STORAGE.with(|s| {
-        s.borrow_mut()
-            .insert(payload.license_id, new_license.clone())
-    }) {
-        Some(_) => Ok(new_license),
-        None => Err(Error::InvalidPayload {
-            msg: format!("license id:{} could not be approved", payload.license_id),
-        }),
-    }
+    Ok(MESSAGE_STORAGE.with(|s| {
+        s.borrow()
+            .range((license_id, start)..=(license_id, u64::MAX))
+            .take(take)
+            .map(|(_, message)| message)
+            .collect()
+    }))
 }
 
+// Mark every message of a license thread as read for the calling party
 #[ic_cdk::update]
-fn revoke_license(payload: ProtectedPayload) -> Result<License, Error> {
-    let license = match _get_license(&payload.license_id) {
+fn mark_license_messages_read(auth_key: String, license_id: u64) -> Result<(), Error> {
+    let license = match _get_license(&license_id) {
         Some(license) => license,
         None => {
             return Err(Error::NotFound {
-                msg: format!("license id:{} could not be found", payload.license_id),
+                msg: format!("license id:{} could not be found", license_id),
             })
         }
     };
 
-    let owner = match _get_owner(&license.owner_id) {
-        Some(owner) => owner,
-        None => {
-            return Err(Error::NotFound {
-                msg: format!("owner id:{} could not be found", license.owner_id),
-            })
+    match message_party(&license, &auth_key) {
+        Some((party, party_id)) => {
+            set_unread_count(party, party_id, license_id, 0);
+            Ok(())
         }
-    };
-
-    if owner.auth_key != payload.auth_key {
-        return Err(Error::InvalidPayload {
+        None => Err(Error::Unauthorized {
             msg: format!(
-                "auth key:{} is invalid, only song owner can revoke",
-                payload.auth_key
+                "auth key:{} is invalid, only the licensee and the owner can read messages",
+                auth_key
             ),
-        });
+        }),
     }
+}
 
-    let mut new_license = license.clone();
-    new_license.approved = false;
+// Define query functions to get the unread messages of an owner or licensee, per license
+#[ic_cdk::query]
+fn get_unread_message_counts(
+    party: MessageParty,
+    id: u64,
+    auth_key: String,
+) -> Result<Vec<UnreadCount>, Error> {
+    authorize_party(party, id, &auth_key)?;
 
-    match remove_license_from_owner(license.owner_id, license.id) {
-        Ok(_) => (),
-        Err(e) => return Err(e),
-    }
+    Ok(UNREAD_COUNTS.with(|u| {
+        u.borrow()
+            .range(((party as u8, id), 0)..=((party as u8, id), u64::MAX))
+            .map(|((_, license_id), unread)| UnreadCount { license_id, unread })
+            .collect()
+    }))
+}
 
-    match remove_license_from_licensee(license.licensee_id, license.id) {
-        Ok(_) => (),
-        Err(e) => return Err(e),
-    }
+// Check the auth key of an owner or licensee
+fn authorize_party(party: MessageParty, id: u64, auth_key: &str) -> Result<(), Error> {
+    let (name, key_matches) = match party {
+        MessageParty::Owner => (
+            "owner",
+            _get_owner(&id).map(|owner| owner.auth_key == auth_key),
+        ),
+        MessageParty::Licensee => (
+            "licensee",
+            _get_licensee(&id).map(|licensee| licensee_key_matches(&licensee, auth_key)),
+        ),
+    };
 
-    match LICENSE_STORAGE.with(|s| {
-        s.borrow_mut()
-            .insert(payload.license_id, new_license.clone())
-    }) {
-        Some(_) => Ok(new_license),
-        None => Err(Error::InvalidPayload {
-            msg: format!("license id:{} could not be revoked", payload.license_id),
+    match key_matches {
+        None => Err(Error::NotFound {
+            msg: format!("{} id:{} could not be found", name, id),
         }),
+        Some(false) => Err(Error::Unauthorized {
+            msg: format!("auth key:{} is invalid for {} id:{}", auth_key, name, id),
+        }),
+        Some(true) => Ok(()),
     }
 }
 
-fn _get_license(id: &u64) -> Option<License> {
-    LICENSE_STORAGE.with(|s| s.borrow().get(id))
+// The party of a license authenticated by the auth key, if any
+fn message_party(license: &License, auth_key: &str) -> Option<(MessageParty, u64)> {
+    if _get_licensee(&license.licensee_id)
+        .is_some_and(|licensee| licensee_key_matches(&licensee, auth_key))
+    {
+        return Some((MessageParty::Licensee, license.licensee_id));
+    }
+    if _get_owner(&license.owner_id).is_some_and(|owner| owner.auth_key == auth_key) {
+        return Some((MessageParty::Owner, license.owner_id));
+    }
+    None
+}
+
+// Counts a message against the sender's rate window, or fails once the window is used up
+fn count_sent_message(party: MessageParty, party_id: u64) -> Result<(), Error> {
+    let now = runtime::time();
+    let window = MESSAGE_RATE_WINDOW_SECS * 1_000_000_000;
+    MESSAGE_RATE.with(|rate| {
+        let mut rate = rate.borrow_mut();
+        let (start, sent) = rate
+            .get(&(party as u8, party_id))
+            .copied()
+            .filter(|(start, _)| now < start + window)
+            .unwrap_or((now, 0));
+        if sent >= MESSAGE_RATE_LIMIT {
+            return Err(Error::RateLimited {
+                msg: format!(
+                    "at most {} messages can be sent every {} minutes",
+                    MESSAGE_RATE_LIMIT,
+                    MESSAGE_RATE_WINDOW_SECS / 60
+                ),
+                retry_after_secs: (start + window - now).div_ceil(1_000_000_000),
+            });
+        }
+        rate.insert((party as u8, party_id), (start, sent + 1));
+        Ok(())
+    })
+}
+
+fn unread_count(party: MessageParty, party_id: u64, license_id: u64) -> u32 {
+    UNREAD_COUNTS
+        .with(|u| u.borrow().get(&((party as u8, party_id), license_id)))
+        .unwrap_or(0)
+}
+
+fn set_unread_count(party: MessageParty, party_id: u64, license_id: u64, unread: u32) {
+    UNREAD_COUNTS.with(|u| {
+        let mut counts = u.borrow_mut();
+        match unread {
+            0 => counts.remove(&((party as u8, party_id), license_id)),
+            _ => counts.insert(((party as u8, party_id), license_id), unread),
+        }
+    });
+}
+
+fn remove_license_messages(license: &License) {
+    let keys: Vec<(u64, u64)> = MESSAGE_STORAGE.with(|s| {
+        s.borrow()
+            .range((license.id, 0)..=(license.id, u64::MAX))
+            .map(|(key, _)| key)
+            .collect()
+    });
+    MESSAGE_STORAGE.with(|s| {
+        let mut storage = s.borrow_mut();
+        for key in keys {
+            storage.remove(&key);
+        }
+    });
+    set_unread_count(MessageParty::Owner, license.owner_id, license.id, 0);
+    set_unread_count(MessageParty::Licensee, license.licensee_id, license.id, 0);
 }
 
+// Define query functions to page through the notifications of an owner or licensee
 #[ic_cdk::query]
-fn get_licensee(id: u64) -> Result<Licensee, Error> {
-    match _get_licensee(&id) {
-        Some(licensee) => Ok(licensee),
-        None => Err(Error::NotFound {
-            msg: format!("licensee id:{} could not be found", id),
-        }),
-    }
+fn get_notifications(query: NotificationQuery) -> Result<Vec<Notification>, Error> {
+    authorize_party(query.party, query.id, &query.auth_key)?;
+
+    let take = query
+        .take
+        .map_or(DEFAULT_NOTIFICATION_TAKE, |take| take as usize)
+        .min(MAX_NOTIFICATION_TAKE);
+    let key = (query.party as u8, query.id);
+    let end = query.prev.unwrap_or(u64::MAX);
+
+    Ok(NOTIFICATION_STORAGE.with(|s| {
+        s.borrow()
+            .range((key, 0)..(key, end))
+            .map(|(_, notification)| notification)
+            .filter(|notification| !query.unread_only || !notification.read)
+            .collect::<Vec<Notification>>()
+            .into_iter()
+            .rev()
+            .take(take)
+            .collect()
+    }))
 }
 
-fn _get_licensee(id: &u64) -> Option<Licensee> {
-    LICENSEE_STORAGE.with(|s| s.borrow().get(id))
+// Define query functions to count the unread notifications of an owner or licensee
+#[ic_cdk::query]
+fn get_unread_notification_count(
+    party: MessageParty,
+    id: u64,
+    auth_key: String,
+) -> Result<u64, Error> {
+    authorize_party(party, id, &auth_key)?;
+
+    Ok(party_notifications(party, id)
+        .iter()
+        .filter(|notification| !notification.read)
+        .count() as u64)
 }
 
+// Mark notifications as read, returning how many were unread
 #[ic_cdk::update]
-fn create_licensee(payload: LicenseePayload) -> Result<Licensee, Error> {
+fn mark_notifications_read(payload: MarkNotificationsPayload) -> Result<u64, Error> {
+    authorize_party(payload.party, payload.id, &payload.auth_key)?;
+
+    let unread: Vec<Notification> = party_notifications(payload.party, payload.id)
+        .into_iter()
+        .filter(|notification| {
+            !notification.read
+                && payload
+                    .notification_ids
+                    .as_ref()
+                    .is_none_or(|ids| ids.contains(&notification.id))
+        })
+        .collect();
+
+    NOTIFICATION_STORAGE.with(|s| {
+        let mut storage = s.borrow_mut();
+        for notification in &unread {
+            let mut new_notification = notification.clone();
+            new_notification.read = true;
+            storage.insert(
+                ((payload.party as u8, payload.id), notification.id),
+                new_notification,
+            );
+        }
+    });
+
+    Ok(unread.len() as u64)
+}
+
+#[ic_cdk::query]
+fn get_notification_preferences(
+    party: MessageParty,
+    id: u64,
+    auth_key: String,
+) -> Result<NotificationPreferences, Error> {
+    authorize_party(party, id, &auth_key)?;
+    Ok(notification_preferences(party, id))
+}
+
+// Turn kinds of notification off or back on; turned off kinds are not recorded at all
+#[ic_cdk::update]
+fn set_notification_preferences(
+    payload: NotificationPreferencesPayload,
+) -> Result<NotificationPreferences, Error> {
+    authorize_party(payload.party, payload.id, &payload.auth_key)?;
+
+    let preferences = NotificationPreferences {
+        disabled: unique(&payload.preferences.disabled),
+    };
+    NOTIFICATION_PREFERENCES.with(|p| {
+        p.borrow_mut()
+            .insert((payload.party as u8, payload.id), preferences.clone())
+    });
+
+    Ok(preferences)
+}
+
+fn party_notifications(party: MessageParty, id: u64) -> Vec<Notification> {
+    let key = (party as u8, id);
+    NOTIFICATION_STORAGE.with(|s| {
+        s.borrow()
+            .range((key, 0)..=(key, u64::MAX))
+            .map(|(_, notification)| notification)
+            .collect()
+    })
+}
+
+fn notification_preferences(party: MessageParty, id: u64) -> NotificationPreferences {
+    NOTIFICATION_PREFERENCES
+        .with(|p| p.borrow().get(&(party as u8, id)))
+        .unwrap_or_default()
+}
+
+// Add a notification to a party's feed unless they turned its kind off, dropping the oldest
+// notifications beyond MAX_NOTIFICATIONS
+fn notify(
+    party: MessageParty,
+    id: u64,
+    kind: NotificationKind,
+    license: &License,
+    message: String,
+) {
+    if notification_preferences(party, id).disabled.contains(&kind) {
+        return;
+    }
+
     // Increment the global ID counter to get a new unique ID
-    let id = ID_COUNTER
+    let notification_id = ID_COUNTER
         .with(|counter| {
             let current_id = *counter.borrow().get();
             counter.borrow_mut().set(current_id + 1)
         })
         .expect("Cannot increment Ids");
 
-    let licensee = Licensee {
-        id,
-        name: payload.name.clone(),
-        email: payload.email.clone(),
-        licenses: Vec::new(),
-    };
+    let key = (party as u8, id);
+    NOTIFICATION_STORAGE.with(|s| {
+        let mut storage = s.borrow_mut();
+        storage.insert(
+            (key, notification_id),
+            Notification {
+                id: notification_id,
+                kind,
+                license_id: license.id,
+                song_id: license.song_id,
+                message,
+                created_at: runtime::time(),
+                read: false,
+            },
+        );
 
-    match LICENSEE_STORAGE.with(|s| s.borrow_mut().insert(id, licensee.clone())) {
-        None => Ok(licensee),
-        Some(_) => Err(Error::InvalidPayload {
-            msg: format!("licensee name:{} could not be created", payload.name),
-        }),
-    }
+        let ids: Vec<u64> = storage
+            .range((key, 0)..=(key, u64::MAX))
+            .map(|((_, id), _)| id)
+            .collect();
+        for id in ids.iter().take(ids.len().saturating_sub(MAX_NOTIFICATIONS)) {
+            storage.remove(&(key, *id));
+        }
+    });
 }
 
-fn add_license_to_owner(owner_id: u64, license_id: u64) -> Result<(), Error> {
-    let mut owner = match _get_owner(&owner_id) {
-        Some(owner) => owner,
-        None => {
-            return Err(Error::NotFound {
-                msg: format!("owner id:{} could not be found", owner_id),
-            })
+// Notify the parties of a license of its request, approval or revocation
+fn notify_license_change(previous: Option<&License>, license: &License) {
+    let song_title = _get_song(&license.song_id)
+        .map(|song| song.title)
+        .unwrap_or_default();
+
+    // Licenses approved as they are created (auto-approval, instant offers and auto-renewals)
+    // are announced by their Approved and PaymentReceived notifications instead
+    if previous.is_none() && !license.approved {
+        notify(
+            MessageParty::Owner,
+            license.owner_id,
+            NotificationKind::NewRequest,
+            license,
+            format!(
+                "licensee id:{} requested license id:{} for song title:{}",
+                license.licensee_id, license.id, song_title
+            ),
+        );
+    }
+
+    if let Some(price) = license.counter_price {
+        if previous.is_none_or(|previous| previous.counter_price != Some(price)) {
+            notify(
+                MessageParty::Licensee,
+                license.licensee_id,
+                NotificationKind::CounterOffer,
+                license,
+                format!(
+                    "owner id:{} asks {} instead of {} for license id:{} for song title:{}",
+                    license.owner_id, price, license.price, license.id, song_title
+                ),
+            );
         }
-    };
+    }
 
-    owner.license_ids.push(license_id);
+    if license.approved && !previous.is_some_and(|previous| previous.approved) {
+        notify(
+            MessageParty::Licensee,
+            license.licensee_id,
+            NotificationKind::Approved,
+            license,
+            format!(
+                "license id:{} for song title:{} has been approved",
+                license.id, song_title
+            ),
+        );
+    }
 
-    match OWNER_STORAGE.with(|s| s.borrow_mut().insert(owner_id, owner.clone())) {
-        Some(_) => Ok(()),
-        None => Err(Error::InvalidPayload {
-            msg: format!(
-                "license id:{} could not be added to owner id:{}",
-                license_id, owner_id
+    if license.revoked && !previous.is_some_and(|previous| previous.revoked) {
+        notify(
+            MessageParty::Licensee,
+            license.licensee_id,
+            NotificationKind::Revoked,
+            license,
+            format!(
+                "license id:{} for song title:{} has been revoked",
+                license.id, song_title
             ),
-        }),
+        );
     }
 }
 
-fn add_license_to_licensee(licensee_id: u64, license_id: u64) -> Result<(), Error> {
-    let mut licensee = match _get_licensee(&licensee_id) {
-        Some(licensee) => licensee,
-        None => {
-            return Err(Error::NotFound {
-                msg: format!("licensee id:{} could not be found", licensee_id),
-            })
+fn license_end_key(license: &License) -> Option<(u64, u64)> {
+    parse_date(&license.end_date).map(|end| (end.max(0) as u64, license.id))
+}
+
+fn index_license_end(license: &License) {
+    if let Some(key) = license_end_key(license) {
+        LICENSE_END_INDEX.with(|i| i.borrow_mut().insert(key, ()));
+    }
+}
+
+// Tell both parties once when an active license that has not been renewed ends soon
+fn notify_expiring_licenses() {
+    let today = (runtime::time() / 86_400_000_000_000) as i64;
+    let last_day = (today + EXPIRY_NOTICE_DAYS) as u64;
+    let ending: Vec<u64> = LICENSE_END_INDEX.with(|i| {
+        i.borrow()
+            .range((today as u64, 0)..=(last_day, u64::MAX))
+            .map(|((_, id), _)| id)
+            .collect()
+    });
+    let licenses_vec: Vec<(u64, License)> = ending
+        .into_iter()
+        .filter_map(|id| _get_license(&id).map(|license| (id, license)))
+        .collect();
+
+    for (id, license) in licenses_vec {
+        if license.next_license_id.is_some()
+            || license_status(&license, today) != LicenseStatus::Active
+            || EXPIRY_NOTICES.with(|n| n.borrow().contains_key(&id))
+        {
+            continue;
         }
-    };
 
-    licensee.licenses.push(license_id);
+        let days_left = match license_window(&license) {
+            Ok((_, end)) if end - today <= EXPIRY_NOTICE_DAYS => end - today,
+            _ => continue,
+        };
 
-    match LICENSEE_STORAGE.with(|s| s.borrow_mut().insert(licensee_id, licensee.clone())) {
-        Some(_) => Ok(()),
-        None => Err(Error::InvalidPayload {
-            msg: format!(
-                "license id:{} could not be added to licensee id:{}",
-                license_id, licensee_id
-            ),
-        }),
+        let message = format!(
+            "license id:{} ends on {}, in {} day(s)",
+            id, license.end_date, days_left
+        );
+        for (party, party_id) in [
+            (MessageParty::Licensee, license.licensee_id),
+            (MessageParty::Owner, license.owner_id),
+        ] {
+            notify(
+                party,
+                party_id,
+                NotificationKind::ExpiringSoon,
+                &license,
+                message.clone(),
+            );
+        }
+        EXPIRY_NOTICES.with(|n| n.borrow_mut().insert(id, ()));
+    }
+}
+
+// An approved, unrevoked license whose window includes today
+fn has_active_license(licensee_id: u64, song_id: u64) -> bool {
+    let today = (runtime::time() / 86_400_000_000_000) as i64;
+    let licenses_vec: Vec<(u64, License)> = LICENSE_STORAGE.with(|s| s.borrow().iter().collect());
+
+    licenses_vec.into_iter().any(|(_, license)| {
+        license.licensee_id == licensee_id
+            && license.song_id == song_id
+            && license.approved
+            && !license.revoked
+            && license_window(&license).is_ok_and(|(start, end)| start <= today && today <= end)
+    })
+}
+
+fn remove_chunks(asset_id: u64, chunk_count: u32) {
+    CHUNK_STORAGE.with(|s| {
+        let mut chunks = s.borrow_mut();
+        for index in 0..chunk_count {
+            chunks.remove(&(asset_id, index));
+        }
+    });
+}
+
+// Drop upload sessions that were left unfinished for too long
+fn remove_stale_uploads() {
+    let now = runtime::time();
+    let sessions_vec: Vec<(u64, UploadSession)> =
+        UPLOAD_STORAGE.with(|s| s.borrow().iter().collect());
+
+    for (id, session) in sessions_vec {
+        if now.saturating_sub(session.created_at) > UPLOAD_SESSION_TTL_NS {
+            remove_chunks(id, session.received.len() as u32);
+            UPLOAD_STORAGE.with(|s| s.borrow_mut().remove(&id));
+        }
+    }
+}
+
+// Drop a deleted song's or release's files and unfinished uploads
+fn remove_entity_assets(entity_id: u64) {
+    for asset in entity_assets(entity_id) {
+        remove_asset(&asset);
+    }
+
+    let sessions_vec: Vec<(u64, UploadSession)> =
+        UPLOAD_STORAGE.with(|s| s.borrow().iter().collect());
+    for (id, session) in sessions_vec {
+        if session.entity_id == entity_id {
+            remove_chunks(id, session.received.len() as u32);
+            UPLOAD_STORAGE.with(|s| s.borrow_mut().remove(&id));
+        }
+    }
+}
+
+// License ids of a licensee: those still listed on the licensee record, then the indexed ones
+fn licensee_license_ids(licensee: &Licensee) -> Vec<u64> {
+    let mut license_ids = licensee.licenses.clone();
+    LICENSEE_LICENSE_INDEX.with(|i| {
+        for ((_, license_id), _) in i.borrow().range((licensee.id, 0)..=(licensee.id, u64::MAX)) {
+            if !license_ids.contains(&license_id) {
+                license_ids.push(license_id);
+            }
+        }
+    });
+    license_ids
+}
+
+fn add_license_to_owner(owner_id: u64, license_id: u64) -> Result<(), Error> {
+    if _get_owner(&owner_id).is_none() {
+        return Err(Error::NotFound {
+            msg: format!("owner id:{} could not be found", owner_id),
+        });
+    }
+
+    OWNER_LICENSE_INDEX.with(|i| i.borrow_mut().insert((owner_id, license_id), ()));
+    Ok(())
+}
+
+fn add_license_to_licensee(licensee_id: u64, license_id: u64) -> Result<(), Error> {
+    if _get_licensee(&licensee_id).is_none() {
+        return Err(Error::NotFound {
+            msg: format!("licensee id:{} could not be found", licensee_id),
+        });
     }
+
+    LICENSEE_LICENSE_INDEX.with(|i| i.borrow_mut().insert((licensee_id, license_id), ()));
+    Ok(())
 }
 
 fn remove_license_from_owner(owner_id: u64, license_id: u64) -> Result<(), Error> {
@@ -760,6 +7905,13 @@ fn remove_license_from_owner(owner_id: u64, license_id: u64) -> Result<(), Error
         }
     };
 
+    if OWNER_LICENSE_INDEX
+        .with(|i| i.borrow_mut().remove(&(owner_id, license_id)))
+        .is_some()
+    {
+        return Ok(());
+    }
+
     let mut index = 0;
     let mut found = false;
     for (i, id) in owner.license_ids.iter().enumerate() {
@@ -783,7 +7935,7 @@ fn remove_license_from_owner(owner_id: u64, license_id: u64) -> Result<(), Error
 
     match OWNER_STORAGE.with(|s| s.borrow_mut().insert(owner_id, owner.clone())) {
         Some(_) => Ok(()),
-        None => Err(Error::InvalidPayload {
+        None => Err(Error::InternalError {
             msg: format!(
                 "license id:{} could not be removed from owner id:{}",
                 license_id, owner_id
@@ -802,6 +7954,13 @@ fn remove_license_from_licensee(licensee_id: u64, license_id: u64) -> Result<(),
         }
     };
 
+    if LICENSEE_LICENSE_INDEX
+        .with(|i| i.borrow_mut().remove(&(licensee_id, license_id)))
+        .is_some()
+    {
+        return Ok(());
+    }
+
     let mut index = 0;
     let mut found = false;
     for (i, id) in licensee.licenses.iter().enumerate() {
@@ -825,7 +7984,7 @@ fn remove_license_from_licensee(licensee_id: u64, license_id: u64) -> Result<(),
 
     match LICENSEE_STORAGE.with(|s| s.borrow_mut().insert(licensee_id, licensee.clone())) {
         Some(_) => Ok(()),
-        None => Err(Error::InvalidPayload {
+        None => Err(Error::InternalError {
             msg: format!(
                 "license id:{} could not be removed from licensee id:{}",
                 license_id, licensee_id
@@ -843,6 +8002,13 @@ fn remove_song_from_owner(id: u64) -> Result<(), Error> {
         msg: format!("owner id:{} could not be found", song.owner_id),
     })?;
 
+    if OWNER_SONG_INDEX
+        .with(|i| i.borrow_mut().remove(&(owner.id, song.id)))
+        .is_some()
+    {
+        return Ok(());
+    }
+
     let index = owner
         .song_ids
         .iter()
@@ -858,7 +8024,7 @@ fn remove_song_from_owner(id: u64) -> Result<(), Error> {
 
     match OWNER_STORAGE.with(|s| s.borrow_mut().insert(owner.id, owner.clone())) {
         Some(_) => Ok(()),
-        None => Err(Error::InvalidPayload {
+        None => Err(Error::InternalError {
             msg: format!(
                 "song id:{} could not be removed from owner id:{}",
                 song.id, owner.id
@@ -867,6 +8033,23 @@ fn remove_song_from_owner(id: u64) -> Result<(), Error> {
     }
 }
 
+fn remove_song_offers(song_id: u64) {
+    let offer_ids: Vec<u64> = OFFER_STORAGE.with(|s| {
+        s.borrow()
+            .iter()
+            .filter(|(_, offer)| offer.song_id == song_id)
+            .map(|(id, _)| id)
+            .collect()
+    });
+
+    OFFER_STORAGE.with(|s| {
+        let mut storage = s.borrow_mut();
+        for id in offer_ids {
+            storage.remove(&id);
+        }
+    });
+}
+
 fn remove_song_from_licensee(id: u64) -> Result<(), Error> {
     let song = _get_song(&id).ok_or(Error::NotFound {
         msg: format!("song id:{} could not be found", id),
@@ -886,45 +8069,70 @@ fn remove_song_from_licensee(id: u64) -> Result<(), Error> {
     }
 
     for license in licenses_to_remove {
-        let mut licensee = _get_licensee(&license.licensee_id).ok_or(Error::NotFound {
-            msg: format!("licensee id:{} could not be found", license.licensee_id),
-        })?;
+        remove_license_from_licensee(license.licensee_id, license.id)?;
+        burn_token(license.id);
+    }
 
-        let index = licensee
-            .licenses
-            .iter()
-            .position(|&x| x == license.id)
-            .ok_or(Error::NotFound {
-                msg: format!(
-                    "license id:{} could not be found in licensee id:{}",
-                    license.id, licensee.id
-                ),
-            })?;
+    Ok(())
+}
 
-        licensee.licenses.remove(index);
+// HTTP status code matching an error, for the JSON API of http_request
+fn error_status(error: &Error) -> u16 {
+    match error {
+        Error::ValidationFailed { .. } => 400,
+        Error::Unauthorized { .. } => 401,
+        Error::PaymentFailed { .. } => 402,
+        Error::Forbidden { .. } => 403,
+        Error::NotFound { .. } => 404,
+        Error::Conflict { .. } => 409,
+        Error::RateLimited { .. } => 429,
+        Error::InternalError { .. } => 500,
+    }
+}
 
-        match LICENSEE_STORAGE.with(|s| s.borrow_mut().insert(licensee.id, licensee.clone())) {
-            Some(_) => (),
-            None => {
-                return Err(Error::InvalidPayload {
-                    msg: format!(
-                        "license id:{} could not be removed from licensee id:{}",
-                        license.id, licensee.id
-                    ),
-                })
-            }
-        }
+// Message of an error, for reports that collect errors per row
+fn error_message(error: Error) -> String {
+    match error {
+        Error::ValidationFailed { field, reason } => format!("{}: {}", field, reason),
+        Error::NotFound { msg }
+        | Error::Unauthorized { msg }
+        | Error::Forbidden { msg }
+        | Error::Conflict { msg, .. }
+        | Error::RateLimited { msg, .. }
+        | Error::PaymentFailed { msg }
+        | Error::InternalError { msg } => msg,
     }
+}
 
-    Ok(())
+fn validation_error(field: &str, reason: String) -> Error {
+    Error::ValidationFailed {
+        field: field.to_string(),
+        reason,
+    }
 }
 
-// Define an Error enum for handling errors
+// Define an Error enum for handling errors. The variant is the stable, machine-readable code
+// of the error.
 #[derive(candid::CandidType, Deserialize, Serialize)]
+#[serde(tag = "error")]
+#[allow(clippy::enum_variant_names)]
 enum Error {
+    // The entity does not exist
     NotFound { msg: String },
-    InvalidPayload { msg: String },
-    AlreadyApproved { msg: String },
+    // The auth key does not authenticate the party the call acts for
+    Unauthorized { msg: String },
+    // The caller is authenticated but not allowed to perform the call
+    Forbidden { msg: String },
+    // The call conflicts with the current state, e.g. with other licenses
+    Conflict { msg: String, license_ids: Vec<u64> },
+    // A payload field is malformed or out of range
+    ValidationFailed { field: String, reason: String },
+    // Too many calls; the call may be retried after retry_after_secs
+    RateLimited { msg: String, retry_after_secs: u64 },
+    // The licensee's payment could not be collected
+    PaymentFailed { msg: String },
+    // The canister could not complete the call, e.g. a write or a system API call failed
+    InternalError { msg: String },
 }
 
 // Candid generator for Candid interface
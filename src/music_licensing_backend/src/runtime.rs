@@ -0,0 +1,105 @@
+// System API calls the canister makes outside of stable memory. Unit tests run natively,
+// where the real calls trap, so they get a settable clock, caller and controller set instead.
+
+pub use system::*;
+
+#[cfg(not(test))]
+mod system {
+    use candid::Principal;
+    use std::future::Future;
+    use std::time::Duration;
+
+    pub fn time() -> u64 {
+        ic_cdk::api::time()
+    }
+
+    pub fn caller() -> Principal {
+        ic_cdk::caller()
+    }
+
+    pub fn is_controller(principal: &Principal) -> bool {
+        ic_cdk::api::is_controller(principal)
+    }
+
+    pub fn set_certified_data(data: &[u8]) {
+        ic_cdk::api::set_certified_data(data)
+    }
+
+    pub fn data_certificate() -> Option<Vec<u8>> {
+        ic_cdk::api::data_certificate()
+    }
+
+    pub fn spawn<F: 'static + Future<Output = ()>>(future: F) {
+        ic_cdk::spawn(future)
+    }
+
+    pub fn set_timer_interval(interval: Duration, func: impl FnMut() + 'static) {
+        ic_cdk_timers::set_timer_interval(interval, func);
+    }
+
+    pub fn set_timer(delay: Duration, func: impl FnOnce() + 'static) {
+        ic_cdk_timers::set_timer(delay, func);
+    }
+}
+
+#[cfg(test)]
+mod system {
+    use candid::Principal;
+    use std::cell::{Cell, RefCell};
+    use std::future::Future;
+    use std::time::Duration;
+
+    // 2024-01-01T00:00:00Z
+    const DEFAULT_TIME: u64 = 1_704_067_200_000_000_000;
+
+    thread_local! {
+        static TIME: Cell<u64> = const { Cell::new(DEFAULT_TIME) };
+        static CALLER: Cell<Principal> = const { Cell::new(Principal::anonymous()) };
+        static CONTROLLERS: RefCell<Vec<Principal>> = const { RefCell::new(Vec::new()) };
+        static CERTIFIED_DATA: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    }
+
+    pub fn time() -> u64 {
+        TIME.with(|t| t.get())
+    }
+
+    pub fn caller() -> Principal {
+        CALLER.with(|c| c.get())
+    }
+
+    pub fn is_controller(principal: &Principal) -> bool {
+        CONTROLLERS.with(|c| c.borrow().contains(principal))
+    }
+
+    pub fn set_certified_data(data: &[u8]) {
+        CERTIFIED_DATA.with(|d| *d.borrow_mut() = data.to_vec())
+    }
+
+    pub fn data_certificate() -> Option<Vec<u8>> {
+        None
+    }
+
+    // Futures are dropped, not run: their inter-canister calls have no native counterpart
+    pub fn spawn<F: 'static + Future<Output = ()>>(_future: F) {}
+
+    // Tests call the timer callbacks themselves
+    pub fn set_timer_interval(_interval: Duration, _func: impl FnMut() + 'static) {}
+
+    pub fn set_timer(_delay: Duration, _func: impl FnOnce() + 'static) {}
+
+    pub fn set_caller(principal: Principal) {
+        CALLER.with(|c| c.set(principal))
+    }
+
+    pub fn add_controller(principal: Principal) {
+        CONTROLLERS.with(|c| c.borrow_mut().push(principal))
+    }
+
+    pub fn advance_time(nanos: u64) {
+        TIME.with(|t| t.set(t.get() + nanos))
+    }
+
+    pub fn certified_data() -> Vec<u8> {
+        CERTIFIED_DATA.with(|d| d.borrow().clone())
+    }
+}
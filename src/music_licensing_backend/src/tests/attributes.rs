@@ -0,0 +1,203 @@
+use super::*;
+
+fn song_with(
+    owner_id: u64,
+    title: &str,
+    edit: impl FnOnce(&mut SongPayload),
+) -> Result<Song, Error> {
+    let mut payload = song_payload(owner_id, title);
+    edit(&mut payload);
+    create_song(payload)
+}
+
+fn failed_field(result: Result<Song, Error>) -> String {
+    match err(result) {
+        Error::ValidationFailed { field, .. } => field,
+        _ => panic!("expected a validation error"),
+    }
+}
+
+fn titles(songs: Vec<Song>) -> Vec<String> {
+    let mut titles: Vec<String> = songs.into_iter().map(|song| song.title).collect();
+    titles.sort();
+    titles
+}
+
+#[test]
+fn bpm_is_bounded() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+
+    for bpm in [validation::MIN_BPM, validation::MAX_BPM] {
+        ok(song_with(owner.id, "In Bounds", |p| p.bpm = Some(bpm)));
+    }
+    for bpm in [0, validation::MIN_BPM - 1, validation::MAX_BPM + 1] {
+        let result = song_with(owner.id, "Out Of Bounds", |p| p.bpm = Some(bpm));
+        assert_eq!(failed_field(result), "bpm");
+    }
+}
+
+#[test]
+fn duration_is_bounded() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+
+    for secs in [1, validation::MAX_DURATION_SECS] {
+        ok(song_with(owner.id, "In Bounds", |p| {
+            p.duration_secs = Some(secs)
+        }));
+    }
+    for secs in [0, validation::MAX_DURATION_SECS + 1] {
+        let result = song_with(owner.id, "Out Of Bounds", |p| p.duration_secs = Some(secs));
+        assert_eq!(failed_field(result), "duration_secs");
+    }
+}
+
+#[test]
+fn languages_are_two_letter_codes() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+
+    let upper = ok(song_with(owner.id, "Upper", |p| {
+        p.language = Some(" EN ".to_string())
+    }));
+    let blank = ok(song_with(owner.id, "Blank", |p| {
+        p.language = Some(" ".to_string())
+    }));
+
+    assert_eq!(upper.language.as_deref(), Some("en"));
+    assert!(blank.language.is_none());
+    for code in ["eng", "e", "e1", "én"] {
+        let result = song_with(owner.id, "Invalid", |p| p.language = Some(code.to_string()));
+        assert_eq!(failed_field(result), "language", "{}", code);
+    }
+}
+
+#[test]
+fn instrumentals_have_no_language() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+
+    let result = song_with(owner.id, "Instrumental", |p| {
+        p.instrumental = Some(true);
+        p.language = Some("en".to_string());
+    });
+
+    assert_eq!(failed_field(result), "language");
+}
+
+#[test]
+fn keys_are_parsed_with_sharps_flats_and_modes() {
+    let key = |tonic, mode| MusicalKey { tonic, mode };
+
+    for (value, expected) in [
+        ("A minor", key(PitchClass::A, KeyMode::Minor)),
+        ("am", key(PitchClass::A, KeyMode::Minor)),
+        ("F# major", key(PitchClass::FSharp, KeyMode::Major)),
+        ("Bbm", key(PitchClass::ASharp, KeyMode::Minor)),
+        ("C♭", key(PitchClass::B, KeyMode::Major)),
+        ("E♯ min", key(PitchClass::F, KeyMode::Minor)),
+        ("c", key(PitchClass::C, KeyMode::Major)),
+    ] {
+        assert_eq!(import::parse_key(value), Ok(expected), "{}", value);
+    }
+    for value in ["", "H minor", "A dorian", "#A", "Ab minor minor"] {
+        assert!(import::parse_key(value).is_err(), "{}", value);
+    }
+}
+
+#[test]
+fn search_bounds_are_inclusive() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    for (title, bpm, secs) in [("Slow", 60, 300), ("Mid", 120, 200), ("Fast", 180, 100)] {
+        ok(song_with(owner.id, title, |p| {
+            p.bpm = Some(bpm);
+            p.duration_secs = Some(secs);
+        }));
+    }
+    ok(song_with(owner.id, "Unknown", |_| ()));
+
+    let by_bpm = ok(search_songs(SongFilter {
+        min_bpm: Some(60),
+        max_bpm: Some(120),
+        ..Default::default()
+    }));
+    let by_duration = ok(search_songs(SongFilter {
+        min_duration_secs: Some(200),
+        ..Default::default()
+    }));
+    let both = ok(search_songs(SongFilter {
+        max_bpm: Some(120),
+        max_duration_secs: Some(200),
+        ..Default::default()
+    }));
+    let unbounded = ok(search_songs(SongFilter::default()));
+
+    assert_eq!(titles(by_bpm), vec!["Mid", "Slow"]);
+    assert_eq!(titles(by_duration), vec!["Mid", "Slow"]);
+    assert_eq!(titles(both), vec!["Mid"]);
+    assert_eq!(unbounded.len(), 4);
+}
+
+#[test]
+fn search_combines_bounds_with_key_and_language() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let a_minor = MusicalKey {
+        tonic: PitchClass::A,
+        mode: KeyMode::Minor,
+    };
+    ok(song_with(owner.id, "Slow", |p| {
+        p.bpm = Some(60);
+        p.key = Some(a_minor);
+        p.language = Some("en".to_string());
+    }));
+    ok(song_with(owner.id, "Fast", |p| {
+        p.bpm = Some(180);
+        p.key = Some(a_minor);
+        p.language = Some("en".to_string());
+    }));
+    ok(song_with(owner.id, "French", |p| {
+        p.bpm = Some(60);
+        p.key = Some(a_minor);
+        p.language = Some("fr".to_string());
+    }));
+
+    let songs = ok(search_songs(SongFilter {
+        max_bpm: Some(100),
+        key: Some(a_minor),
+        language: Some("EN".to_string()),
+        ..Default::default()
+    }));
+
+    assert_eq!(titles(songs), vec!["Slow"]);
+}
+
+#[test]
+fn search_rejects_invalid_bounds() {
+    setup();
+
+    let bpm = search_songs(SongFilter {
+        min_bpm: Some(121),
+        max_bpm: Some(120),
+        ..Default::default()
+    });
+    let duration = search_songs(SongFilter {
+        min_duration_secs: Some(10),
+        max_duration_secs: Some(9),
+        ..Default::default()
+    });
+    let language = search_songs(SongFilter {
+        language: Some("english".to_string()),
+        ..Default::default()
+    });
+
+    for (result, expected) in [
+        (bpm, "bpm"),
+        (duration, "duration_secs"),
+        (language, "language"),
+    ] {
+        assert!(matches!(err(result), Error::ValidationFailed { field, .. } if field == expected));
+    }
+}
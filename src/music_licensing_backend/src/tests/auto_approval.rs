@@ -0,0 +1,180 @@
+use super::*;
+
+fn rules() -> AutoApprovalRules {
+    AutoApprovalRules {
+        licensee_ids: Vec::new(),
+        min_price: None,
+        allow_exclusive: false,
+        max_duration_days: Some(366),
+    }
+}
+
+fn set_rules(song_id: u64, rules: Option<AutoApprovalRules>) -> Result<Song, Error> {
+    set_auto_approval(AutoApprovalPayload {
+        auth_key: OWNER_KEY.to_string(),
+        song_id,
+        rules,
+    })
+}
+
+fn enable_payments() {
+    let controller = Principal::from_slice(&[1; 29]);
+    runtime::add_controller(controller);
+    runtime::set_caller(controller);
+    ok(set_payment_ledger(Some(Principal::from_slice(&[2; 29]))));
+    runtime::set_caller(Principal::anonymous());
+}
+
+#[test]
+fn paid_requests_within_the_rules_are_approved_at_once() {
+    setup();
+    enable_payments();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    ok(set_rules(song.id, Some(rules())));
+    credit_balance(MessageParty::Licensee, licensee.id, 150);
+
+    let license = request(song.id, licensee.id);
+
+    assert!(license.approved);
+    assert_eq!(
+        ok(get_balance(
+            MessageParty::Licensee,
+            licensee.id,
+            LICENSEE_KEY.to_string()
+        )),
+        50
+    );
+    assert_eq!(
+        ok(get_balance(
+            MessageParty::Owner,
+            owner.id,
+            OWNER_KEY.to_string()
+        )),
+        100
+    );
+}
+
+#[test]
+fn unpaid_requests_wait_for_the_owner() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    ok(set_rules(song.id, Some(rules())));
+
+    // Payments are off
+    assert!(!request(song.id, licensee.id).approved);
+
+    // The balance does not cover the price
+    enable_payments();
+    credit_balance(MessageParty::Licensee, licensee.id, 99);
+    let license = request(song.id, licensee.id);
+    assert!(!license.approved);
+    assert_eq!(balance(MessageParty::Licensee, licensee.id), 99);
+
+    assert!(approve(license.id).approved);
+}
+
+#[test]
+fn requests_need_the_licensee_key() {
+    setup();
+    enable_payments();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    ok(set_rules(song.id, Some(rules())));
+    credit_balance(MessageParty::Licensee, licensee.id, 150);
+
+    let result = create_license_request(LicensePayload {
+        auth_key: "stranger".to_string(),
+        ..license_payload(song.id, licensee.id)
+    });
+
+    assert!(matches!(err(result), Error::Unauthorized { .. }));
+    assert_eq!(balance(MessageParty::Licensee, licensee.id), 150);
+    assert_eq!(balance(MessageParty::Owner, owner.id), 0);
+    assert_eq!(LICENSE_STORAGE.with(|s| s.borrow().len()), 0);
+}
+
+#[test]
+fn requests_outside_the_rules_wait_for_the_owner() {
+    setup();
+    enable_payments();
+    let owner = owner("Olivia", OWNER_KEY);
+    let other = licensee("Lena", "other-key");
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    ok(set_rules(
+        song.id,
+        Some(AutoApprovalRules {
+            licensee_ids: vec![other.id],
+            min_price: Some(200),
+            ..rules()
+        }),
+    ));
+    credit_balance(MessageParty::Licensee, licensee.id, 1000);
+    credit_balance(MessageParty::Licensee, other.id, 1000);
+
+    let cheap = request(song.id, other.id);
+    let stranger = ok(create_license_request(LicensePayload {
+        price: 200,
+        ..license_payload(song.id, licensee.id)
+    }));
+    let exclusive = ok(create_license_request(LicensePayload {
+        price: 200,
+        exclusive: true,
+        ..license_payload(song.id, other.id)
+    }));
+    let long = ok(create_license_request(LicensePayload {
+        price: 200,
+        end_date: "2026-01-01".to_string(),
+        ..license_payload(song.id, other.id)
+    }));
+
+    for license in [cheap, stranger, exclusive, long] {
+        assert!(!license.approved);
+    }
+    assert_eq!(balance(MessageParty::Licensee, other.id), 1000);
+
+    let approved = ok(create_license_request(LicensePayload {
+        price: 200,
+        ..license_payload(song.id, other.id)
+    }));
+    assert!(approved.approved);
+    assert_eq!(balance(MessageParty::Licensee, other.id), 800);
+}
+
+#[test]
+fn auto_approval_rules_are_bounded() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+
+    let result = set_rules(
+        song.id,
+        Some(AutoApprovalRules {
+            licensee_ids: (0..=MAX_AUTO_APPROVAL_LICENSEES as u64).collect(),
+            ..rules()
+        }),
+    );
+
+    assert!(matches!(err(result), Error::ValidationFailed { .. }));
+    assert!(ok(get_song(song.id)).auto_approval.is_none());
+}
+
+#[test]
+fn only_the_owner_sets_auto_approval() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+
+    let result = set_auto_approval(AutoApprovalPayload {
+        auth_key: LICENSEE_KEY.to_string(),
+        song_id: song.id,
+        rules: Some(rules()),
+    });
+
+    assert!(matches!(err(result), Error::Unauthorized { .. }));
+}
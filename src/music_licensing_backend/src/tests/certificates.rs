@@ -0,0 +1,50 @@
+use super::*;
+
+// The certified hashes of a license's certificate match the bodies served for it
+fn certified_current(license: &License) -> bool {
+    CERTIFIED_TREE.with(|t| {
+        let tree = t.borrow();
+        license_certificate_bodies(license)
+            .iter()
+            .all(|(path, body)| tree.get(path.as_bytes()) == Some(&Sha256::digest(body).into()))
+    })
+}
+
+#[test]
+fn certificates_are_refreshed_when_the_song_is_deleted() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let license = approve(request(song.id, licensee.id).id);
+    assert!(certified_current(&license));
+
+    ok(delete_song(OWNER_KEY.to_string(), song.id));
+
+    let license = _get_license(&license.id).unwrap();
+    assert!(certified_current(&license));
+    assert!(license_certificate(&license).song_title.is_empty());
+}
+
+#[test]
+fn certificates_are_rebuilt_in_batches() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let licenses: Vec<License> = (0..5)
+        .map(|_| approve(request(song.id, licensee.id).id))
+        .collect();
+    CERTIFIED_TREE.with(|t| *t.borrow_mut() = RbTree::new());
+
+    let mut batches = 1;
+    let mut next = recertify_licenses(0, 2);
+    assert!(!certified_current(&licenses[2]));
+    while let Some(from) = next {
+        next = recertify_licenses(from, 2);
+        batches += 1;
+    }
+
+    assert_eq!(batches, 3);
+    assert!(licenses.iter().all(certified_current));
+}
@@ -0,0 +1,97 @@
+use super::*;
+
+const GUEST_KEY: &str = "guest-key";
+
+fn credited(owner_id: u64, guest_id: Option<u64>) -> SongPayload {
+    let mut payload = song_payload(owner_id, "First Light");
+    payload.contributors = vec![Contributor {
+        name: "Gwen".to_string(),
+        role: ContributorRole::Composer,
+        owner_id: guest_id,
+    }];
+    payload
+}
+
+fn link(guest_id: u64, song_id: u64, auth_key: &str) -> Result<Song, Error> {
+    link_contributor(ContributorLinkPayload {
+        auth_key: auth_key.to_string(),
+        owner_id: guest_id,
+        song_id,
+        name: "gwen".to_string(),
+        role: ContributorRole::Composer,
+    })
+}
+
+#[test]
+fn other_owners_cannot_be_linked_without_their_consent() {
+    setup();
+    let guest = owner("Gwen", GUEST_KEY);
+    let owner = owner("Olivia", OWNER_KEY);
+
+    let result = create_song(credited(owner.id, Some(guest.id)));
+
+    assert!(matches!(err(result), Error::ValidationFailed { .. }));
+}
+
+#[test]
+fn owners_link_themselves_to_a_credit() {
+    setup();
+    let guest = owner("Gwen", GUEST_KEY);
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = ok(create_song(credited(owner.id, None)));
+
+    assert!(matches!(
+        err(link(guest.id, song.id, OWNER_KEY)),
+        Error::Unauthorized { .. }
+    ));
+    let song = ok(link(guest.id, song.id, GUEST_KEY));
+
+    assert_eq!(song.contributors[0].owner_id, Some(guest.id));
+    assert_eq!(ok(get_owner_contributions(guest.id)).len(), 1);
+}
+
+#[test]
+fn confirmed_links_survive_updates() {
+    setup();
+    let guest = owner("Gwen", GUEST_KEY);
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = ok(create_song(credited(owner.id, None)));
+    ok(link(guest.id, song.id, GUEST_KEY));
+    let payload = credited(owner.id, Some(guest.id));
+
+    let song = ok(update_song(UpdateSongPayload {
+        auth_key: OWNER_KEY.to_string(),
+        id: song.id,
+        title: payload.title,
+        artist: payload.artist,
+        year: payload.year,
+        genre: payload.genre,
+        price: 150,
+        rights: payload.rights,
+        isrc: payload.isrc,
+        iswc: payload.iswc,
+        contributors: payload.contributors,
+        tags: payload.tags,
+        moods: payload.moods,
+        tempo: payload.tempo,
+        instruments: payload.instruments,
+        duration_secs: payload.duration_secs,
+        bpm: payload.bpm,
+        key: payload.key,
+        instrumental: payload.instrumental,
+        explicit: payload.explicit,
+        language: payload.language,
+    }));
+
+    assert_eq!(song.contributors[0].owner_id, Some(guest.id));
+
+    let song = ok(unlink_contributor(ContributorLinkPayload {
+        auth_key: GUEST_KEY.to_string(),
+        owner_id: guest.id,
+        song_id: song.id,
+        name: "Gwen".to_string(),
+        role: ContributorRole::Composer,
+    }));
+
+    assert!(song.contributors[0].owner_id.is_none());
+}
@@ -0,0 +1,137 @@
+use super::*;
+
+fn png(fill: u8, len: usize) -> Vec<u8> {
+    let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+    data.resize(len, fill);
+    data
+}
+
+// The hash certified for a cover path, if any
+fn certified(path: &str) -> Option<Hash> {
+    CERTIFIED_TREE.with(|t| t.borrow().get(path.as_bytes()).copied())
+}
+
+fn get_cover(path: &str) -> HttpResponse {
+    http_request(HttpRequest {
+        method: "GET".to_string(),
+        url: path.to_string(),
+        headers: Vec::new(),
+        body: ByteBuf::new(),
+    })
+}
+
+#[test]
+fn covers_are_certified_until_deleted() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+    let path = format!("/songs/{}/cover", song.id);
+    let first = png(1, 100);
+    let second = png(2, 200);
+
+    ok(upload(song.id, AssetKind::Cover, "image/png", &first));
+    assert_eq!(certified(&path), Some(Sha256::digest(&first).into()));
+    assert_eq!(get_cover(&path).body.to_vec(), first);
+
+    ok(upload(song.id, AssetKind::Cover, "image/png", &second));
+    assert_eq!(certified(&path), Some(Sha256::digest(&second).into()));
+    assert_eq!(get_cover(&path).body.to_vec(), second);
+
+    ok(delete_asset(
+        OWNER_KEY.to_string(),
+        song.id,
+        AssetKind::Cover,
+    ));
+    assert!(certified(&path).is_none());
+    assert_eq!(get_cover(&path).status_code, 404);
+}
+
+#[test]
+fn release_covers_are_certified_under_the_release() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+    let release = ok(create_release(ReleasePayload {
+        auth_key: OWNER_KEY.to_string(),
+        owner_id: owner.id,
+        title: "Daybreak".to_string(),
+        release_type: ReleaseType::Single,
+        release_date: "2023-06-01".to_string(),
+        upc: None,
+        track_ids: vec![song.id],
+        bundle_price: 100,
+    }));
+    let cover = png(1, 100);
+
+    ok(upload(release.id, AssetKind::Cover, "image/png", &cover));
+
+    let path = format!("/releases/{}/cover", release.id);
+    assert_eq!(certified(&path), Some(Sha256::digest(&cover).into()));
+    assert_eq!(get_cover(&path).body.to_vec(), cover);
+}
+
+#[test]
+fn covers_are_limited_to_one_mebibyte() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+
+    let largest = upload(song.id, AssetKind::Cover, "image/png", &png(1, 1024 * 1024));
+    let oversized = start(
+        song.id,
+        AssetKind::Cover,
+        "image/png",
+        &png(1, 1024 * 1024 + 1),
+    );
+
+    ok(largest);
+    assert!(matches!(err(oversized), Error::ValidationFailed { field, .. } if field == "size"));
+}
+
+#[test]
+fn covers_must_be_images() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+
+    for content_type in ["image/gif", "audio/mpeg", "text/html"] {
+        let result = start(song.id, AssetKind::Cover, content_type, &png(1, 100));
+
+        assert!(
+            matches!(err(result), Error::ValidationFailed { field, .. } if field == "content_type")
+        );
+    }
+}
+
+#[test]
+fn covers_must_match_their_content_type() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+    let mut riff = b"RIFF\0\0\0\0WAVE".to_vec();
+    riff.resize(100, 0);
+
+    let jpeg_as_png = upload(song.id, AssetKind::Cover, "image/jpeg", &png(1, 100));
+    let wave_as_webp = upload(song.id, AssetKind::Cover, "image/webp", &riff);
+
+    for result in [jpeg_as_png, wave_as_webp] {
+        assert!(
+            matches!(err(result), Error::ValidationFailed { field, .. } if field == "content_type")
+        );
+    }
+    assert!(_get_asset(song.id, AssetKind::Cover).is_none());
+    assert!(certified(&format!("/songs/{}/cover", song.id)).is_none());
+}
+
+#[test]
+fn webp_covers_are_accepted() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+    let mut webp = b"RIFF\0\0\0\0WEBPVP8 ".to_vec();
+    webp.resize(100, 0);
+
+    let asset = ok(upload(song.id, AssetKind::Cover, "image/webp", &webp));
+
+    assert_eq!(asset.content_type, "image/webp");
+}
@@ -0,0 +1,97 @@
+use super::*;
+
+fn ern(territory: &str) -> String {
+    format!(
+        r#"<NewReleaseMessage>
+  <MessageHeader><MessageId>MSG-1</MessageId></MessageHeader>
+  <ResourceList>
+    <SoundRecording>
+      <ResourceReference>A1</ResourceReference>
+      <DisplayTitleText>First Light</DisplayTitleText>
+      <DisplayArtistName>The Testers</DisplayArtistName>
+      <GenreText>Rock</GenreText>
+      <PLine><Year>2020</Year></PLine>
+    </SoundRecording>
+  </ResourceList>
+  <ReleaseList>
+    <Release>
+      <ReleaseReference>R0</ReleaseReference>
+      <ReleaseResourceReference>A1</ReleaseResourceReference>
+    </Release>
+  </ReleaseList>
+  <DealList>
+    <ReleaseDeal>
+      <DealReleaseReference>R0</DealReleaseReference>
+      <Deal>
+        <DealTerms>
+          <TerritoryCode>{}</TerritoryCode>
+          <UseType>Stream</UseType>
+        </DealTerms>
+      </Deal>
+    </ReleaseDeal>
+  </DealList>
+</NewReleaseMessage>"#,
+        territory
+    )
+}
+
+fn ingest(owner_id: u64, xml: String) -> DdexReport {
+    ok(ingest_ddex_ern(DdexPayload {
+        auth_key: OWNER_KEY.to_string(),
+        owner_id,
+        xml,
+    }))
+}
+
+#[test]
+fn deals_become_offers() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+
+    let report = ingest(owner.id, ern("us"));
+
+    assert!(report.committed);
+    assert_eq!(report.offer_ids.len(), 1);
+    let offer = _get_offer(&report.offer_ids[0]).unwrap();
+    assert_eq!(offer.territories, vec!["US".to_string()]);
+}
+
+#[test]
+fn deals_with_invalid_territories_are_rejected() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+
+    let report = ingest(owner.id, ern("USA"));
+
+    assert!(!report.committed);
+    assert!(report.songs[0].error.is_some());
+    assert!(owner_song_ids(&_get_owner(&owner.id).unwrap()).is_empty());
+}
+
+// More recordings than the ids an owner record could hold
+#[test]
+fn large_messages_are_ingested() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let recordings: String = (0..200)
+        .map(|i| {
+            format!(
+                "<SoundRecording><ResourceReference>A{}</ResourceReference>\
+                 <DisplayTitleText>Song {}</DisplayTitleText>\
+                 <DisplayArtistName>The Testers</DisplayArtistName>\
+                 <GenreText>Rock</GenreText><PLine><Year>2020</Year></PLine></SoundRecording>",
+                i, i
+            )
+        })
+        .collect();
+    let xml = format!(
+        "<NewReleaseMessage><MessageHeader><MessageId>MSG-2</MessageId></MessageHeader>\
+         <ResourceList>{}</ResourceList></NewReleaseMessage>",
+        recordings
+    );
+
+    let report = ingest(owner.id, xml);
+
+    assert!(report.committed);
+    assert_eq!(ok(get_owner_profile(owner.id)).song_ids.len(), 200);
+}
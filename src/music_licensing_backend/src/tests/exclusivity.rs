@@ -0,0 +1,88 @@
+use super::*;
+
+fn exclusive_payload(song_id: u64, licensee_id: u64) -> LicensePayload {
+    LicensePayload {
+        exclusive: true,
+        ..license_payload(song_id, licensee_id)
+    }
+}
+
+fn conflicting_ids(error: Error) -> Vec<u64> {
+    match error {
+        Error::Conflict { license_ids, .. } => license_ids,
+        e => panic!("expected a conflict, got: {}", error_message(e)),
+    }
+}
+
+#[test]
+fn exclusive_license_blocks_overlapping_requests() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let first = licensee("Liam", LICENSEE_KEY);
+    let second = licensee("Lena", "second-key");
+    let song = song(owner.id, "First Light");
+
+    let exclusive = approve(ok(create_license_request(exclusive_payload(song.id, first.id))).id);
+
+    let result = create_license_request(license_payload(song.id, second.id));
+    assert_eq!(conflicting_ids(err(result)), vec![exclusive.id]);
+}
+
+#[test]
+fn exclusive_request_conflicts_with_active_licenses() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let first = licensee("Liam", LICENSEE_KEY);
+    let second = licensee("Lena", "second-key");
+    let song = song(owner.id, "First Light");
+
+    let active = approve(request(song.id, first.id).id);
+
+    let result = create_license_request(exclusive_payload(song.id, second.id));
+    assert_eq!(conflicting_ids(err(result)), vec![active.id]);
+}
+
+#[test]
+fn exclusive_license_allows_disjoint_scopes() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let first = licensee("Liam", LICENSEE_KEY);
+    let second = licensee("Lena", "second-key");
+    let song = song(owner.id, "First Light");
+    approve(ok(create_license_request(exclusive_payload(song.id, first.id))).id);
+
+    ok(create_license_request(LicensePayload {
+        territories: vec!["DE".to_string()],
+        ..license_payload(song.id, second.id)
+    }));
+    ok(create_license_request(LicensePayload {
+        media: vec![Media::Tv],
+        ..license_payload(song.id, second.id)
+    }));
+    ok(create_license_request(LicensePayload {
+        start_date: "2025-01-01".to_string(),
+        end_date: "2025-06-30".to_string(),
+        ..license_payload(song.id, second.id)
+    }));
+}
+
+#[test]
+fn approving_an_overlapping_pending_request_conflicts() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let first = licensee("Liam", LICENSEE_KEY);
+    let second = licensee("Lena", "second-key");
+    let song = song(owner.id, "First Light");
+    let exclusive = ok(create_license_request(exclusive_payload(song.id, first.id)));
+    let other = request(song.id, second.id);
+
+    approve(exclusive.id);
+
+    let result = approve_license(Approvepayload {
+        auth_key: OWNER_KEY.to_string(),
+        license_id: other.id,
+        cost: 100,
+    });
+    assert_eq!(conflicting_ids(err(result)), vec![exclusive.id]);
+    assert!(!ok(get_license(other.id)).approved);
+}
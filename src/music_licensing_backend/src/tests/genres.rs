@@ -0,0 +1,71 @@
+use super::*;
+
+fn rock_id() -> u64 {
+    get_genres()
+        .into_iter()
+        .find(|genre| genre.name == "Rock")
+        .unwrap()
+        .id
+}
+
+fn as_controller() {
+    let controller = Principal::from_slice(&[1; 29]);
+    runtime::add_controller(controller);
+    runtime::set_caller(controller);
+}
+
+// A song that has about `spare` bytes left before it reaches Song::MAX_SIZE
+fn full_song(owner_id: u64, spare: usize) -> Song {
+    let mut payload = song_payload(owner_id, "Full");
+    payload.tags = (0..validation::MAX_TAGS)
+        .map(|i| format!("{:02}{}", i, "界".repeat(validation::MAX_TAG_LENGTH - 2)))
+        .collect();
+    let song = ok(create_song(payload.clone()));
+    let size = Encode!(&song).unwrap().len();
+    ok(delete_song(OWNER_KEY.to_string(), song.id));
+
+    payload.rights.attribution = Some("界".repeat((Song::MAX_SIZE as usize - spare - size) / 3));
+    ok(create_song(payload))
+}
+
+#[test]
+fn renaming_a_genre_renames_its_songs() {
+    setup();
+    as_controller();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+
+    ok(update_genre(
+        rock_id(),
+        GenrePayload {
+            name: "Rock & Roll".to_string(),
+            parent_id: None,
+            aliases: vec!["Rock".to_string()],
+        },
+    ));
+
+    assert_eq!(_get_song(&song.id).unwrap().genre, "Rock & Roll");
+}
+
+#[test]
+fn genre_names_must_fit_every_song() {
+    setup();
+    as_controller();
+    let owner = owner("Olivia", OWNER_KEY);
+    let small = song(owner.id, "First Light");
+    let full = full_song(owner.id, 100);
+
+    let result = update_genre(
+        rock_id(),
+        GenrePayload {
+            name: "界".repeat(validation::MAX_NAME_LENGTH),
+            parent_id: None,
+            aliases: Vec::new(),
+        },
+    );
+
+    assert!(matches!(err(result), Error::ValidationFailed { field, .. } if field == "song"));
+    assert_eq!(_get_genre(&rock_id()).unwrap().name, "Rock");
+    assert_eq!(_get_song(&small.id).unwrap().genre, "Rock");
+    assert_eq!(_get_song(&full.id).unwrap().genre, "Rock");
+}
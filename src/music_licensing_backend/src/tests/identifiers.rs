@@ -0,0 +1,106 @@
+use super::*;
+
+const ISRC: &str = "USRC17607839";
+
+fn import(owner_id: u64, data: &str) -> ImportReport {
+    ok(bulk_import_songs(BulkImportPayload {
+        auth_key: OWNER_KEY.to_string(),
+        owner_id,
+        format: ImportFormat::Csv,
+        data: data.to_string(),
+    }))
+}
+
+// Imported and ingested songs are checked before they have an id, so a stored song with
+// id 0 must not be mistaken for the song being checked
+#[test]
+fn import_conflicts_with_the_song_stored_under_id_0() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let mut first = song(owner.id, "First Light");
+    first.id = 0;
+    first.isrc = Some(ISRC.to_string());
+    index_song_isrc(None, &first);
+    SONG_STORAGE.with(|s| s.borrow_mut().insert(0, first));
+
+    let report = import(
+        owner.id,
+        &format!(
+            "title,artist,year,genre,price,isrc\nCopy,The Testers,2020,Rock,100,{}",
+            ISRC
+        ),
+    );
+
+    assert!(!report.committed);
+    assert_eq!(report.failed, 1);
+}
+
+#[test]
+fn update_keeps_the_songs_own_isrc() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let mut payload = song_payload(owner.id, "First Light");
+    payload.isrc = Some(ISRC.to_string());
+    let song = ok(create_song(payload.clone()));
+
+    let result = update_song(UpdateSongPayload {
+        auth_key: OWNER_KEY.to_string(),
+        id: song.id,
+        title: "First Light (Remastered)".to_string(),
+        artist: payload.artist,
+        year: payload.year,
+        genre: payload.genre,
+        price: payload.price,
+        rights: payload.rights,
+        isrc: payload.isrc,
+        iswc: payload.iswc,
+        contributors: payload.contributors,
+        tags: payload.tags,
+        moods: payload.moods,
+        tempo: payload.tempo,
+        instruments: payload.instruments,
+        duration_secs: payload.duration_secs,
+        bpm: payload.bpm,
+        key: payload.key,
+        instrumental: payload.instrumental,
+        explicit: payload.explicit,
+        language: payload.language,
+    });
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn isrc_cannot_be_used_twice() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let mut payload = song_payload(owner.id, "First Light");
+    payload.isrc = Some(ISRC.to_string());
+    ok(create_song(payload.clone()));
+
+    payload.title = "Second Light".to_string();
+    let result = create_song(payload);
+
+    assert!(matches!(err(result), Error::Conflict { .. }));
+}
+
+// More songs than the ids an owner record could hold
+#[test]
+fn large_imports_are_committed() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let rows: Vec<String> = (0..300)
+        .map(|i| format!("Song {},The Testers,2020,Rock,100", i))
+        .collect();
+
+    let report = import(
+        owner.id,
+        &format!("title,artist,year,genre,price\n{}", rows.join("\n")),
+    );
+
+    assert!(report.committed);
+    assert_eq!(report.imported, 300);
+    let song_ids = ok(get_owner_profile(owner.id)).song_ids;
+    assert_eq!(song_ids.len(), 300);
+    assert!(Encode!(&_get_owner(&owner.id).unwrap()).unwrap().len() < Owner::MAX_SIZE as usize);
+}
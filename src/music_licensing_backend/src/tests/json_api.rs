@@ -0,0 +1,71 @@
+use super::*;
+
+fn get(path: &str) -> (u16, serde_json::Value) {
+    let response = http_request(HttpRequest {
+        method: "GET".to_string(),
+        url: path.to_string(),
+        headers: Vec::new(),
+        body: ByteBuf::new(),
+    });
+    let body = serde_json::from_slice(&response.body).unwrap();
+    (response.status_code, body)
+}
+
+#[test]
+fn song_owner_route_serves_the_public_profile() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+
+    let (status, body) = get(&format!("/api/songs/{}/owner", song.id));
+
+    assert_eq!(status, 200);
+    assert_eq!(body["name"], "Olivia");
+    assert!(body.get("email").is_none());
+}
+
+#[test]
+fn songs_are_served_without_auto_approval_rules() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+    ok(set_auto_approval(AutoApprovalPayload {
+        auth_key: OWNER_KEY.to_string(),
+        song_id: song.id,
+        rules: Some(AutoApprovalRules {
+            licensee_ids: Vec::new(),
+            min_price: Some(500),
+            allow_exclusive: false,
+            max_duration_days: None,
+        }),
+    }));
+
+    for path in [
+        format!("/api/songs/{}", song.id),
+        "/api/songs".to_string(),
+        format!("/api/owners/{}/songs", owner.id),
+    ] {
+        let (status, body) = get(&path);
+        let song = match body.as_array() {
+            Some(songs) => songs[0].clone(),
+            None => body,
+        };
+
+        assert_eq!(status, 200);
+        assert_eq!(song["title"], "First Light");
+        assert!(song.get("auto_approval").is_none());
+    }
+}
+
+#[test]
+fn licensees_are_served_without_their_email() {
+    setup();
+    let licensee = licensee("Liam", LICENSEE_KEY);
+
+    let (status, body) = get(&format!("/api/licensees/{}", licensee.id));
+
+    assert_eq!(status, 200);
+    assert_eq!(body["name"], "Liam");
+    assert!(body.get("email").is_none());
+    assert!(body.get("auth_key").is_none());
+}
@@ -0,0 +1,101 @@
+use super::*;
+
+fn message(auth_key: &str, license_id: u64, body: &str) -> Result<LicenseMessage, Error> {
+    send_license_message(MessagePayload {
+        auth_key: auth_key.to_string(),
+        license_id,
+        body: body.to_string(),
+    })
+}
+
+#[test]
+fn only_license_parties_can_use_a_thread() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let license = request(song.id, licensee.id);
+
+    ok(message(LICENSEE_KEY, license.id, "For a short film"));
+    ok(message(OWNER_KEY, license.id, "Which festivals?"));
+
+    assert!(matches!(
+        err(message("stranger", license.id, "Hello")),
+        Error::Unauthorized { .. }
+    ));
+    assert!(matches!(
+        err(get_license_messages(
+            "stranger".to_string(),
+            license.id,
+            None,
+            None
+        )),
+        Error::Unauthorized { .. }
+    ));
+    let thread = ok(get_license_messages(
+        LICENSEE_KEY.to_string(),
+        license.id,
+        None,
+        None,
+    ));
+    assert_eq!(thread.len(), 2);
+    assert!(matches!(thread[1].sender, MessageParty::Owner));
+}
+
+#[test]
+fn senders_are_rate_limited_across_threads() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let first = request(song.id, licensee.id);
+    let second = request(song.id, licensee.id);
+
+    for i in 0..MESSAGE_RATE_LIMIT {
+        let license = if i % 2 == 0 { &first } else { &second };
+        ok(message(LICENSEE_KEY, license.id, "Any news?"));
+    }
+
+    match err(message(LICENSEE_KEY, first.id, "Any news?")) {
+        Error::RateLimited {
+            retry_after_secs, ..
+        } => assert_eq!(retry_after_secs, MESSAGE_RATE_WINDOW_SECS),
+        e => panic!("unexpected error: {}", error_message(e)),
+    }
+    // The limit is per sender; the owner can still reply
+    ok(message(OWNER_KEY, first.id, "Soon"));
+
+    runtime::advance_time(MESSAGE_RATE_WINDOW_SECS * 1_000_000_000);
+    ok(message(LICENSEE_KEY, first.id, "Any news?"));
+}
+
+#[test]
+fn threads_are_paged() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let license = request(song.id, licensee.id);
+    for i in 0..5 {
+        ok(message(LICENSEE_KEY, license.id, &format!("Message {}", i)));
+    }
+
+    let first = ok(get_license_messages(
+        OWNER_KEY.to_string(),
+        license.id,
+        None,
+        Some(3),
+    ));
+    let rest = ok(get_license_messages(
+        OWNER_KEY.to_string(),
+        license.id,
+        Some(first[2].id),
+        Some(3),
+    ));
+
+    let bodies: Vec<String> = first.iter().chain(&rest).map(|m| m.body.clone()).collect();
+    assert_eq!(
+        bodies,
+        (0..5).map(|i| format!("Message {}", i)).collect::<Vec<_>>()
+    );
+}
@@ -0,0 +1,260 @@
+// Behaviour tests for the canister endpoints. Each test runs on its own thread and so starts
+// with empty stable memory; the runtime module stands in for the system API.
+
+use super::*;
+
+mod attributes;
+mod auto_approval;
+mod certificates;
+mod contributors;
+mod covers;
+mod ddex;
+mod exclusivity;
+mod genres;
+mod identifiers;
+mod json_api;
+mod messages;
+mod notifications;
+mod offers;
+mod parties;
+mod previews;
+mod releases;
+mod renewal;
+mod rights;
+mod signing;
+mod song_transfer;
+mod songs;
+mod tokens;
+mod transfers;
+mod upgrade;
+mod uploads;
+
+pub(crate) const OWNER_KEY: &str = "owner-key";
+pub(crate) const LICENSEE_KEY: &str = "licensee-key";
+
+pub(crate) fn ok<T>(result: Result<T, Error>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => panic!("unexpected error: {}", error_message(e)),
+    }
+}
+
+pub(crate) fn err<T>(result: Result<T, Error>) -> Error {
+    match result {
+        Ok(_) => panic!("expected an error"),
+        Err(e) => e,
+    }
+}
+
+pub(crate) fn setup() {
+    seed_genres();
+}
+
+pub(crate) fn owner(name: &str, auth_key: &str) -> Owner {
+    ok(create_owner(OwnerPayload {
+        name: name.to_string(),
+        email: format!("{}@example.com", name.to_lowercase()),
+        auth_key: auth_key.to_string(),
+    }))
+}
+
+pub(crate) fn licensee(name: &str, auth_key: &str) -> Licensee {
+    ok(create_licensee(LicenseePayload {
+        name: name.to_string(),
+        email: format!("{}@example.com", name.to_lowercase()),
+        auth_key: auth_key.to_string(),
+    }))
+}
+
+pub(crate) fn song_payload(owner_id: u64, title: &str) -> SongPayload {
+    SongPayload {
+        auth_key: OWNER_KEY.to_string(),
+        title: title.to_string(),
+        artist: "The Testers".to_string(),
+        owner_id,
+        year: 2020,
+        genre: "Rock".to_string(),
+        price: 100,
+        rights: UsageRights {
+            exclusive_allowed: true,
+            ..Default::default()
+        },
+        isrc: None,
+        iswc: None,
+        contributors: Vec::new(),
+        tags: Vec::new(),
+        moods: Vec::new(),
+        tempo: None,
+        instruments: Vec::new(),
+        duration_secs: None,
+        bpm: None,
+        key: None,
+        instrumental: None,
+        explicit: None,
+        language: None,
+    }
+}
+
+pub(crate) fn song(owner_id: u64, title: &str) -> Song {
+    ok(create_song(song_payload(owner_id, title)))
+}
+
+pub(crate) fn license_payload(song_id: u64, licensee_id: u64) -> LicensePayload {
+    LicensePayload {
+        auth_key: _get_licensee(&licensee_id)
+            .map(|licensee| licensee.auth_key)
+            .unwrap_or_default(),
+        song_id,
+        licensee_id,
+        start_date: "2024-01-01".to_string(),
+        end_date: "2024-12-31".to_string(),
+        exclusive: false,
+        media: vec![Media::Film],
+        territories: vec!["US".to_string()],
+        license_type: LicenseType::Sync,
+        max_copies: None,
+        max_streams: None,
+        price: 100,
+    }
+}
+
+pub(crate) fn request(song_id: u64, licensee_id: u64) -> License {
+    ok(create_license_request(license_payload(
+        song_id,
+        licensee_id,
+    )))
+}
+
+pub(crate) fn sha256(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+pub(crate) fn start(
+    entity_id: u64,
+    kind: AssetKind,
+    content_type: &str,
+    data: &[u8],
+) -> Result<UploadSession, Error> {
+    start_upload(StartUploadPayload {
+        auth_key: OWNER_KEY.to_string(),
+        entity_id,
+        kind,
+        file_name: "file".to_string(),
+        content_type: content_type.to_string(),
+        size: data.len() as u64,
+        sha256: sha256(data),
+    })
+}
+
+pub(crate) fn send_chunk(
+    session: &UploadSession,
+    index: u32,
+    data: &[u8],
+) -> Result<UploadSession, Error> {
+    let from = index as usize * session.chunk_size as usize;
+    let to = (from + session.chunk_size as usize).min(data.len());
+    upload_chunk(UploadChunkPayload {
+        auth_key: OWNER_KEY.to_string(),
+        session_id: session.id,
+        index,
+        data: ByteBuf::from(data[from..to].to_vec()),
+        sha256: sha256(&data[from..to]),
+    })
+}
+
+// Upload a whole file in one go
+pub(crate) fn upload(
+    entity_id: u64,
+    kind: AssetKind,
+    content_type: &str,
+    data: &[u8],
+) -> Result<Asset, Error> {
+    let session = start(entity_id, kind, content_type, data)?;
+    for index in 0..session.received.len() as u32 {
+        send_chunk(&session, index, data)?;
+    }
+    finish_upload(OWNER_KEY.to_string(), session.id)
+}
+
+// License ids of an owner, listed on the record or indexed
+pub(crate) fn owner_license_ids(owner: &Owner) -> Vec<u64> {
+    let mut license_ids = owner.license_ids.clone();
+    OWNER_LICENSE_INDEX.with(|i| {
+        for ((_, license_id), _) in i.borrow().range((owner.id, 0)..=(owner.id, u64::MAX)) {
+            license_ids.push(license_id);
+        }
+    });
+    license_ids
+}
+
+pub(crate) fn approve(license_id: u64) -> License {
+    ok(approve_license(Approvepayload {
+        auth_key: OWNER_KEY.to_string(),
+        license_id,
+        cost: 100,
+    }))
+}
+
+#[test]
+fn approved_license_is_certified() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+
+    let license = approve(request(song.id, licensee.id).id);
+
+    assert!(license.approved);
+    assert!(!runtime::certified_data().is_empty());
+}
+
+#[test]
+fn approval_requires_the_owner_key() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let license = request(song.id, licensee.id);
+
+    let result = approve_license(Approvepayload {
+        auth_key: LICENSEE_KEY.to_string(),
+        license_id: license.id,
+        cost: 100,
+    });
+
+    assert!(matches!(err(result), Error::Unauthorized { .. }));
+}
+
+#[test]
+fn approving_twice_is_a_conflict() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let license = approve(request(song.id, licensee.id).id);
+
+    let result = approve_license(Approvepayload {
+        auth_key: OWNER_KEY.to_string(),
+        license_id: license.id,
+        cost: 100,
+    });
+
+    match err(result) {
+        Error::Conflict { license_ids, .. } => assert_eq!(license_ids, vec![license.id]),
+        e => panic!("unexpected error: {}", error_message(e)),
+    }
+}
+
+#[test]
+fn controller_endpoints_check_the_caller() {
+    let controller = Principal::from_slice(&[1; 29]);
+    runtime::add_controller(controller);
+
+    assert!(matches!(
+        err(set_signing_key("key_1".to_string())),
+        Error::Forbidden { .. }
+    ));
+
+    runtime::set_caller(controller);
+    ok(set_signing_key("key_1".to_string()));
+}
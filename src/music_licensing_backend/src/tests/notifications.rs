@@ -0,0 +1,185 @@
+use super::*;
+
+const DAY_NS: u64 = 86_400_000_000_000;
+
+fn kinds(party: MessageParty, id: u64, auth_key: &str) -> Vec<NotificationKind> {
+    ok(get_notifications(NotificationQuery {
+        party,
+        id,
+        auth_key: auth_key.to_string(),
+        prev: None,
+        take: None,
+        unread_only: false,
+    }))
+    .into_iter()
+    .map(|notification| notification.kind)
+    .collect()
+}
+
+fn counter(license_id: u64, auth_key: &str, price: u32) -> Result<License, Error> {
+    counter_license_request(CounterOfferPayload {
+        auth_key: auth_key.to_string(),
+        license_id,
+        price,
+    })
+}
+
+fn accept(license_id: u64, auth_key: &str) -> Result<License, Error> {
+    accept_counter_offer(ProtectedPayload {
+        auth_key: auth_key.to_string(),
+        license_id,
+    })
+}
+
+#[test]
+fn accepted_counter_offers_approve_the_license() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let license = request(song.id, licensee.id);
+
+    let countered = ok(counter(license.id, OWNER_KEY, 250));
+    assert_eq!(countered.counter_price, Some(250));
+    assert!(!countered.approved);
+    assert_eq!(
+        kinds(MessageParty::Licensee, licensee.id, LICENSEE_KEY),
+        vec![NotificationKind::CounterOffer]
+    );
+
+    assert!(matches!(
+        err(accept(license.id, OWNER_KEY)),
+        Error::Unauthorized { .. }
+    ));
+    let approved = ok(accept(license.id, LICENSEE_KEY));
+
+    assert!(approved.approved);
+    assert_eq!(approved.price, 250);
+    assert_eq!(approved.counter_price, None);
+    assert!(matches!(
+        err(counter(license.id, OWNER_KEY, 300)),
+        Error::Conflict { .. }
+    ));
+    assert!(matches!(
+        err(accept(license.id, LICENSEE_KEY)),
+        Error::Conflict { .. }
+    ));
+}
+
+#[test]
+fn only_the_owner_counters_a_request() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let license = request(song.id, licensee.id);
+
+    assert!(matches!(
+        err(counter(license.id, LICENSEE_KEY, 1)),
+        Error::Unauthorized { .. }
+    ));
+    assert_eq!(_get_license(&license.id).unwrap().counter_price, None);
+}
+
+#[test]
+fn feeds_are_read_with_their_own_key() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    request(song.id, licensee.id);
+
+    let result = get_notifications(NotificationQuery {
+        party: MessageParty::Owner,
+        id: owner.id,
+        auth_key: LICENSEE_KEY.to_string(),
+        prev: None,
+        take: None,
+        unread_only: false,
+    });
+
+    assert!(matches!(err(result), Error::Unauthorized { .. }));
+    assert_eq!(
+        kinds(MessageParty::Owner, owner.id, OWNER_KEY),
+        vec![NotificationKind::NewRequest]
+    );
+}
+
+#[test]
+fn licenses_ending_soon_are_announced_once() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let ending = approve(request(song.id, licensee.id).id);
+    let mut later = license_payload(song.id, licensee.id);
+    later.end_date = "2025-12-31".to_string();
+    approve(ok(create_license_request(later)).id);
+
+    notify_expiring_licenses();
+    let expiring = |id, key| {
+        kinds(MessageParty::Licensee, id, key)
+            .into_iter()
+            .filter(|kind| *kind == NotificationKind::ExpiringSoon)
+            .count()
+    };
+    assert_eq!(expiring(licensee.id, LICENSEE_KEY), 0);
+
+    // 2024-12-20, eleven days before the first license ends
+    runtime::advance_time(354 * DAY_NS);
+    notify_expiring_licenses();
+    notify_expiring_licenses();
+
+    assert_eq!(expiring(licensee.id, LICENSEE_KEY), 1);
+    assert!(EXPIRY_NOTICES.with(|n| n.borrow().contains_key(&ending.id)));
+    assert_eq!(EXPIRY_NOTICES.with(|n| n.borrow().len()), 1);
+}
+
+#[test]
+fn owners_are_told_of_payments() {
+    setup();
+    let controller = Principal::from_slice(&[1; 29]);
+    runtime::add_controller(controller);
+    runtime::set_caller(controller);
+    ok(set_payment_ledger(Some(Principal::from_slice(&[2; 29]))));
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    ok(set_auto_approval(AutoApprovalPayload {
+        auth_key: OWNER_KEY.to_string(),
+        song_id: song.id,
+        rules: Some(AutoApprovalRules::default()),
+    }));
+    credit_balance(MessageParty::Licensee, licensee.id, 100);
+
+    assert!(request(song.id, licensee.id).approved);
+
+    assert_eq!(
+        kinds(MessageParty::Owner, owner.id, OWNER_KEY),
+        vec![NotificationKind::PaymentReceived]
+    );
+    assert_eq!(
+        kinds(MessageParty::Licensee, licensee.id, LICENSEE_KEY),
+        vec![NotificationKind::Approved]
+    );
+}
+
+#[test]
+fn owners_are_told_of_pending_requests() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+
+    let license = request(song.id, licensee.id);
+    approve(license.id);
+
+    assert_eq!(
+        kinds(MessageParty::Owner, owner.id, OWNER_KEY),
+        vec![NotificationKind::NewRequest]
+    );
+    assert_eq!(
+        kinds(MessageParty::Licensee, licensee.id, LICENSEE_KEY),
+        vec![NotificationKind::Approved]
+    );
+}
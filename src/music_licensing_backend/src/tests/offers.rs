@@ -0,0 +1,156 @@
+use super::*;
+
+fn offer_payload(song_id: u64, instant: bool) -> OfferPayload {
+    OfferPayload {
+        auth_key: OWNER_KEY.to_string(),
+        song_id,
+        name: "Indie film".to_string(),
+        price: 250,
+        license_type: LicenseType::Sync,
+        media: vec![Media::Film],
+        territories: vec!["US".to_string()],
+        exclusive: false,
+        duration_days: 30,
+        max_copies: None,
+        max_streams: None,
+        instant,
+    }
+}
+
+fn take_offer(offer_id: u64, licensee_id: u64) -> Result<License, Error> {
+    create_license_from_offer(OfferLicensePayload {
+        auth_key: LICENSEE_KEY.to_string(),
+        offer_id,
+        licensee_id,
+        start_date: "2024-03-01".to_string(),
+    })
+}
+
+fn enable_payments() {
+    let controller = Principal::from_slice(&[1; 29]);
+    runtime::add_controller(controller);
+    runtime::set_caller(controller);
+    ok(set_payment_ledger(Some(Principal::from_slice(&[2; 29]))));
+}
+
+#[test]
+fn instant_offers_license_on_their_terms() {
+    setup();
+    enable_payments();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let offer = ok(create_offer(offer_payload(song.id, true)));
+    credit_balance(MessageParty::Licensee, licensee.id, 300);
+
+    let license = ok(take_offer(offer.id, licensee.id));
+
+    assert!(license.approved);
+    assert_eq!(license.offer_id, Some(offer.id));
+    assert_eq!(license.price, 250);
+    assert_eq!(license.start_date, "2024-03-01");
+    assert_eq!(license.end_date, "2024-03-30");
+    assert_eq!(license.media, vec![Media::Film]);
+    assert_eq!(balance(MessageParty::Licensee, licensee.id), 50);
+    assert_eq!(balance(MessageParty::Owner, owner.id), 250);
+}
+
+#[test]
+fn unpaid_instant_offers_wait_for_approval() {
+    setup();
+    enable_payments();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let offer = ok(create_offer(offer_payload(song.id, true)));
+    credit_balance(MessageParty::Licensee, licensee.id, 249);
+
+    let license = ok(take_offer(offer.id, licensee.id));
+
+    assert!(!license.approved);
+    assert_eq!(balance(MessageParty::Licensee, licensee.id), 249);
+}
+
+#[test]
+fn offers_are_taken_with_the_licensee_key() {
+    setup();
+    enable_payments();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let offer = ok(create_offer(offer_payload(song.id, true)));
+    credit_balance(MessageParty::Licensee, licensee.id, 300);
+
+    let result = create_license_from_offer(OfferLicensePayload {
+        auth_key: "stranger".to_string(),
+        offer_id: offer.id,
+        licensee_id: licensee.id,
+        start_date: "2024-03-01".to_string(),
+    });
+
+    assert!(matches!(err(result), Error::Unauthorized { .. }));
+    assert_eq!(balance(MessageParty::Licensee, licensee.id), 300);
+}
+
+#[test]
+fn offers_stay_within_the_song_rights() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let offer = ok(create_offer(offer_payload(song.id, false)));
+
+    // The owner narrows the song's rights after publishing the offer
+    let mut narrowed = _get_song(&song.id).unwrap();
+    narrowed.rights.media = vec![Media::Tv];
+    SONG_STORAGE.with(|s| s.borrow_mut().insert(song.id, narrowed));
+
+    assert!(matches!(
+        err(take_offer(offer.id, licensee.id)),
+        Error::ValidationFailed { .. }
+    ));
+}
+
+#[test]
+fn other_offers_wait_for_approval() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let offer = ok(create_offer(offer_payload(song.id, false)));
+
+    let license = ok(take_offer(offer.id, licensee.id));
+
+    assert!(!license.approved);
+    assert!(approve(license.id).approved);
+}
+
+#[test]
+fn withdrawn_offers_cannot_be_taken() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let offer = ok(create_offer(offer_payload(song.id, true)));
+
+    ok(withdraw_offer(OWNER_KEY.to_string(), offer.id));
+
+    assert!(matches!(
+        err(take_offer(offer.id, licensee.id)),
+        Error::NotFound { .. }
+    ));
+}
+
+#[test]
+fn only_the_song_owner_creates_offers() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+
+    let result = create_offer(OfferPayload {
+        auth_key: "someone-else".to_string(),
+        ..offer_payload(song.id, true)
+    });
+
+    assert!(matches!(err(result), Error::Unauthorized { .. }));
+}
@@ -0,0 +1,78 @@
+use super::*;
+
+// Every field within its character limit, but too many bytes for one stored record
+fn oversized_party() -> (String, String, String) {
+    let name = "界".repeat(validation::MAX_NAME_LENGTH);
+    let email = format!(
+        "{}@{}.{}.{}.com",
+        "a".repeat(64),
+        "b".repeat(63),
+        "c".repeat(63),
+        "d".repeat(56)
+    );
+    let auth_key = "𝄞".repeat(validation::MAX_AUTH_KEY_LENGTH);
+    (name, email, auth_key)
+}
+
+#[test]
+fn oversized_owners_are_rejected() {
+    let (name, email, auth_key) = oversized_party();
+
+    let result = create_owner(OwnerPayload {
+        name,
+        email,
+        auth_key,
+    });
+
+    assert!(matches!(err(result), Error::ValidationFailed { field, .. } if field == "owner"));
+}
+
+#[test]
+fn oversized_licensees_are_rejected() {
+    let (name, email, auth_key) = oversized_party();
+
+    let result = create_licensee(LicenseePayload {
+        name,
+        email,
+        auth_key,
+    });
+
+    assert!(matches!(err(result), Error::ValidationFailed { field, .. } if field == "licensee"));
+}
+
+#[test]
+fn new_licensee_keys_must_fit() {
+    let controller = Principal::from_slice(&[1; 29]);
+    runtime::add_controller(controller);
+    runtime::set_caller(controller);
+    let (name, email, auth_key) = oversized_party();
+    let licensee = ok(create_licensee(LicenseePayload {
+        name,
+        email,
+        auth_key: LICENSEE_KEY.to_string(),
+    }));
+
+    let result = set_licensee_auth_key(licensee.id, auth_key);
+
+    assert!(matches!(err(result), Error::ValidationFailed { field, .. } if field == "licensee"));
+    assert_eq!(_get_licensee(&licensee.id).unwrap().auth_key, LICENSEE_KEY);
+}
+
+// More license ids than an owner or licensee record could hold
+#[test]
+fn parties_hold_any_number_of_licenses() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+
+    let license_ids: Vec<u64> = (0..150)
+        .map(|_| approve(request(song.id, licensee.id).id).id)
+        .collect();
+
+    assert_eq!(ok(get_licensee(licensee.id)).licenses, license_ids);
+    assert_eq!(
+        owner_license_ids(&_get_owner(&owner.id).unwrap()),
+        license_ids
+    );
+}
@@ -0,0 +1,152 @@
+use super::*;
+
+// Two chunks, so ranges can span a chunk boundary
+fn preview() -> Vec<u8> {
+    (0..MAX_CHUNK_SIZE as usize + 100)
+        .map(|i| (i % 251) as u8)
+        .collect()
+}
+
+fn get_preview(song_id: u64, range: Option<&str>) -> HttpResponse {
+    http_request(HttpRequest {
+        method: "GET".to_string(),
+        url: format!("/songs/{}/preview", song_id),
+        headers: range
+            .map(|range| vec![("Range".to_string(), range.to_string())])
+            .unwrap_or_default(),
+        body: ByteBuf::new(),
+    })
+}
+
+fn header<'a>(response: &'a HttpResponse, name: &str) -> Option<&'a str> {
+    response
+        .headers
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+fn song_with_preview(data: &[u8]) -> Song {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+    ok(upload(song.id, AssetKind::Preview, "audio/mpeg", data));
+    song
+}
+
+#[test]
+fn previews_are_served_whole_without_a_range() {
+    let data = preview();
+    let song = song_with_preview(&data);
+
+    let response = get_preview(song.id, None);
+
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body.to_vec(), data);
+    assert_eq!(header(&response, "Accept-Ranges"), Some("bytes"));
+}
+
+#[test]
+fn ranges_are_served_partially() {
+    let data = preview();
+    let song = song_with_preview(&data);
+    let size = data.len();
+    let boundary = MAX_CHUNK_SIZE as usize;
+
+    let response = get_preview(
+        song.id,
+        Some(&format!("bytes={}-{}", boundary - 10, boundary + 9)),
+    );
+
+    assert_eq!(response.status_code, 206);
+    assert_eq!(response.body.to_vec(), data[boundary - 10..boundary + 10]);
+    assert_eq!(
+        header(&response, "Content-Range"),
+        Some(format!("bytes {}-{}/{}", boundary - 10, boundary + 9, size).as_str())
+    );
+}
+
+#[test]
+fn open_and_suffix_ranges_run_to_the_end() {
+    let data = preview();
+    let song = song_with_preview(&data);
+    let size = data.len();
+
+    let open = get_preview(song.id, Some(&format!("bytes={}-", size - 20)));
+    let suffix = get_preview(song.id, Some("bytes=-20"));
+    let long_suffix = get_preview(song.id, Some(&format!("bytes=-{}", size + 1)));
+
+    assert_eq!(open.status_code, 206);
+    assert_eq!(open.body.to_vec(), data[size - 20..]);
+    assert_eq!(suffix.status_code, 206);
+    assert_eq!(suffix.body.to_vec(), data[size - 20..]);
+    assert_eq!(
+        header(&suffix, "Content-Range"),
+        Some(format!("bytes {}-{}/{}", size - 20, size - 1, size).as_str())
+    );
+    assert_eq!(long_suffix.status_code, 206);
+    assert_eq!(long_suffix.body.to_vec(), data);
+}
+
+#[test]
+fn ranges_ending_past_the_end_are_clipped() {
+    let data = preview();
+    let song = song_with_preview(&data);
+    let size = data.len();
+
+    let response = get_preview(song.id, Some(&format!("bytes={}-{}", size - 5, size + 100)));
+
+    assert_eq!(response.status_code, 206);
+    assert_eq!(response.body.to_vec(), data[size - 5..]);
+    assert_eq!(
+        header(&response, "Content-Range"),
+        Some(format!("bytes {}-{}/{}", size - 5, size - 1, size).as_str())
+    );
+}
+
+#[test]
+fn unsatisfiable_ranges_are_refused() {
+    let data = preview();
+    let song = song_with_preview(&data);
+    let size = data.len();
+
+    for range in [format!("bytes={}-", size), "bytes=-0".to_string()] {
+        let response = get_preview(song.id, Some(&range));
+
+        assert_eq!(response.status_code, 416, "{}", range);
+        assert_eq!(
+            header(&response, "Content-Range"),
+            Some(format!("bytes */{}", size).as_str())
+        );
+    }
+}
+
+#[test]
+fn malformed_ranges_serve_the_whole_preview() {
+    let data = preview();
+    let song = song_with_preview(&data);
+
+    for range in [
+        "bytes=abc",
+        "bytes=10",
+        "bytes=20-10",
+        "bytes=0-1,5-6",
+        "items=0-9",
+        "",
+    ] {
+        let response = get_preview(song.id, Some(range));
+
+        assert_eq!(response.status_code, 200, "{}", range);
+        assert_eq!(response.body.len(), data.len());
+        assert!(header(&response, "Content-Range").is_none());
+    }
+}
+
+#[test]
+fn missing_previews_are_not_found() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+
+    assert_eq!(get_preview(song.id, Some("bytes=0-9")).status_code, 404);
+}
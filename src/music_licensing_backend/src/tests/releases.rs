@@ -0,0 +1,146 @@
+use super::*;
+
+fn release(owner_id: u64, track_ids: Vec<u64>) -> Release {
+    ok(create_release(ReleasePayload {
+        auth_key: OWNER_KEY.to_string(),
+        owner_id,
+        title: "Daybreak".to_string(),
+        release_type: ReleaseType::Ep,
+        release_date: "2023-06-01".to_string(),
+        upc: None,
+        track_ids,
+        bundle_price: 301,
+    }))
+}
+
+fn request_release(release_id: u64, licensee_id: u64) -> Vec<License> {
+    ok(create_release_license_request(ReleaseLicensePayload {
+        release_id,
+        licensee_id,
+        start_date: "2024-01-01".to_string(),
+        end_date: "2024-12-31".to_string(),
+        exclusive: false,
+        media: vec![Media::Film],
+        territories: vec!["US".to_string()],
+        license_type: LicenseType::Sync,
+        max_copies: None,
+        max_streams: None,
+    }))
+}
+
+#[test]
+fn release_licenses_are_priced_from_the_bundle_price() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let first = song(owner.id, "First Light");
+    let second = song(owner.id, "Last Light");
+    let release = release(owner.id, vec![first.id, second.id]);
+
+    let licenses = request_release(release.id, licensee.id);
+
+    let prices: Vec<u32> = licenses.iter().map(|license| license.price).collect();
+    assert_eq!(prices, vec![151, 150]);
+
+    let approved = ok(approve_release_license(ApproveBundlePayload {
+        auth_key: OWNER_KEY.to_string(),
+        bundle_id: licenses[0].bundle_id.unwrap(),
+    }));
+
+    assert!(approved.iter().all(|license| license.approved));
+    let total: u32 = approved.iter().map(|license| license.price).sum();
+    assert_eq!(total, release.bundle_price);
+}
+
+#[test]
+fn release_without_tracks_is_deleted() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let first = song(owner.id, "First Light");
+    let second = song(owner.id, "Last Light");
+    let single = release(owner.id, vec![first.id]);
+    let ep = release(owner.id, vec![first.id, second.id]);
+
+    ok(delete_song(OWNER_KEY.to_string(), first.id));
+
+    assert!(_get_release(&single.id).is_none());
+    assert_eq!(_get_release(&ep.id).unwrap().track_ids, vec![second.id]);
+}
+
+fn approve_release(bundle_id: u64) -> Result<Vec<License>, Error> {
+    approve_release_license(ApproveBundlePayload {
+        auth_key: OWNER_KEY.to_string(),
+        bundle_id,
+    })
+}
+
+fn enable_payments() {
+    let controller = Principal::from_slice(&[1; 29]);
+    runtime::add_controller(controller);
+    runtime::set_caller(controller);
+    ok(set_payment_ledger(Some(Principal::from_slice(&[2; 29]))));
+}
+
+#[test]
+fn bundle_price_is_charged_once() {
+    setup();
+    enable_payments();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let first = song(owner.id, "First Light");
+    let second = song(owner.id, "Last Light");
+    let release = release(owner.id, vec![first.id, second.id]);
+    let licenses = request_release(release.id, licensee.id);
+    credit_balance(MessageParty::Licensee, licensee.id, 400);
+
+    ok(approve_release(licenses[0].bundle_id.unwrap()));
+
+    assert_eq!(balance(MessageParty::Licensee, licensee.id), 99);
+    assert_eq!(balance(MessageParty::Owner, owner.id), 301);
+}
+
+#[test]
+fn unpaid_bundles_stay_pending() {
+    setup();
+    enable_payments();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let first = song(owner.id, "First Light");
+    let second = song(owner.id, "Last Light");
+    let release = release(owner.id, vec![first.id, second.id]);
+    let licenses = request_release(release.id, licensee.id);
+    credit_balance(MessageParty::Licensee, licensee.id, 300);
+
+    let result = approve_release(licenses[0].bundle_id.unwrap());
+
+    assert!(matches!(err(result), Error::PaymentFailed { .. }));
+    assert!(licenses
+        .iter()
+        .all(|license| !_get_license(&license.id).unwrap().approved));
+    assert_eq!(balance(MessageParty::Licensee, licensee.id), 300);
+}
+
+#[test]
+fn bundles_are_approved_whole_or_not_at_all() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let rival = licensee("Rita", "rival-key");
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let first = song(owner.id, "First Light");
+    let second = song(owner.id, "Last Light");
+    let release = release(owner.id, vec![first.id, second.id]);
+    let exclusive = ok(create_license_request(LicensePayload {
+        exclusive: true,
+        ..license_payload(second.id, rival.id)
+    }));
+    let licenses = request_release(release.id, licensee.id);
+    approve(exclusive.id);
+
+    let result = approve_release(licenses[0].bundle_id.unwrap());
+
+    assert!(matches!(err(result), Error::Conflict { .. }));
+    assert!(licenses
+        .iter()
+        .all(|license| !_get_license(&license.id).unwrap().approved));
+    assert!(ok(get_licensee(licensee.id)).licenses.is_empty());
+}
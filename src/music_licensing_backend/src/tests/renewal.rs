@@ -0,0 +1,160 @@
+use super::*;
+
+fn renew(license_id: u64, end_date: Option<&str>) -> Result<License, Error> {
+    request_renewal(RenewalPayload {
+        auth_key: LICENSEE_KEY.to_string(),
+        license_id,
+        end_date: end_date.map(str::to_string),
+        price: None,
+    })
+}
+
+fn licensed() -> License {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    approve(request(song.id, licensee.id).id)
+}
+
+#[test]
+fn renewal_links_a_pending_successor() {
+    let license = licensed();
+
+    let successor = ok(renew(license.id, None));
+
+    assert!(!successor.approved);
+    assert_eq!(successor.previous_license_id, Some(license.id));
+    assert_eq!(successor.start_date, "2025-01-01");
+    assert_eq!(successor.end_date, "2026-01-01");
+    assert_eq!(
+        ok(get_license(license.id)).next_license_id,
+        Some(successor.id)
+    );
+
+    let history: Vec<u64> = ok(get_license_history(successor.id))
+        .iter()
+        .map(|license| license.id)
+        .collect();
+    assert_eq!(history, vec![license.id, successor.id]);
+}
+
+#[test]
+fn newer_renewal_request_replaces_a_pending_one() {
+    let license = licensed();
+    let stale = ok(renew(license.id, None));
+
+    let successor = ok(renew(license.id, Some("2025-06-30")));
+
+    assert!(matches!(err(get_license(stale.id)), Error::NotFound { .. }));
+    assert_eq!(
+        ok(get_license(license.id)).next_license_id,
+        Some(successor.id)
+    );
+    assert_eq!(ok(get_license_history(license.id)).len(), 2);
+}
+
+#[test]
+fn approved_successor_blocks_another_renewal() {
+    let license = licensed();
+    let successor = approve(ok(renew(license.id, None)).id);
+
+    match err(renew(license.id, None)) {
+        Error::Conflict { license_ids, .. } => assert_eq!(license_ids, vec![successor.id]),
+        e => panic!("expected a conflict, got: {}", error_message(e)),
+    }
+
+    // The successor itself can be renewed
+    ok(renew(successor.id, None));
+}
+
+#[test]
+fn owner_extension_replaces_a_pending_renewal() {
+    let license = licensed();
+    let pending = ok(renew(license.id, None));
+
+    let extension = ok(extend_license(ExtendPayload {
+        auth_key: OWNER_KEY.to_string(),
+        license_id: license.id,
+        end_date: "2025-03-31".to_string(),
+        price: 30,
+    }));
+
+    assert!(extension.approved);
+    assert_eq!(extension.end_date, "2025-03-31");
+    assert!(matches!(
+        err(get_license(pending.id)),
+        Error::NotFound { .. }
+    ));
+}
+
+fn auto_renewed() -> License {
+    let license = licensed();
+    let controller = Principal::from_slice(&[1; 29]);
+    runtime::add_controller(controller);
+    runtime::set_caller(controller);
+    ok(set_payment_ledger(Some(Principal::from_slice(&[2; 29]))));
+    ok(set_auto_renew(AutoRenewPayload {
+        auth_key: OWNER_KEY.to_string(),
+        license_id: license.id,
+        auto_renew: true,
+    }));
+    license
+}
+
+#[test]
+fn auto_renew_approves_paid_renewals() {
+    let license = auto_renewed();
+    credit_balance(MessageParty::Licensee, license.licensee_id, 100);
+
+    let successor = ok(renew(license.id, None));
+
+    assert!(successor.approved);
+    assert_eq!(successor.price, license.price);
+    assert_eq!(successor.start_date, "2025-01-01");
+    // As many days as 2024, a leap year
+    assert_eq!(successor.end_date, "2026-01-01");
+    assert_eq!(balance(MessageParty::Licensee, license.licensee_id), 0);
+    assert_eq!(balance(MessageParty::Owner, license.owner_id), 100);
+}
+
+#[test]
+fn auto_renew_keeps_the_price_and_term() {
+    let license = auto_renewed();
+    credit_balance(MessageParty::Licensee, license.licensee_id, 100);
+
+    let cheaper = ok(request_renewal(RenewalPayload {
+        auth_key: LICENSEE_KEY.to_string(),
+        license_id: license.id,
+        end_date: None,
+        price: Some(0),
+    }));
+    assert!(!cheaper.approved);
+
+    let longer = ok(renew(license.id, Some("2034-12-31")));
+    assert!(!longer.approved);
+    assert_eq!(balance(MessageParty::Licensee, license.licensee_id), 100);
+}
+
+#[test]
+fn unpaid_auto_renewals_wait_for_the_owner() {
+    let license = auto_renewed();
+    credit_balance(MessageParty::Licensee, license.licensee_id, 99);
+
+    assert!(!ok(renew(license.id, None)).approved);
+    assert_eq!(balance(MessageParty::Licensee, license.licensee_id), 99);
+}
+
+#[test]
+fn only_the_licensee_requests_renewals() {
+    let license = licensed();
+
+    let result = request_renewal(RenewalPayload {
+        auth_key: OWNER_KEY.to_string(),
+        license_id: license.id,
+        end_date: None,
+        price: None,
+    });
+
+    assert!(matches!(err(result), Error::Unauthorized { .. }));
+}
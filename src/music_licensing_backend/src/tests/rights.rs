@@ -0,0 +1,84 @@
+use super::*;
+
+fn restricted_song(owner_id: u64) -> Song {
+    ok(create_song(SongPayload {
+        rights: UsageRights {
+            license_types: vec![LicenseType::Sync],
+            media: vec![Media::Film, Media::Tv],
+            territories: vec!["US".to_string(), "CA".to_string()],
+            max_copies: Some(1000),
+            max_streams: None,
+            attribution: Some("Courtesy of Olivia".to_string()),
+            exclusive_allowed: false,
+        },
+        ..song_payload(owner_id, "First Light")
+    }))
+}
+
+fn rejected_field(error: Error) -> String {
+    match error {
+        Error::ValidationFailed { field, .. } => field,
+        e => panic!("expected a validation error, got: {}", error_message(e)),
+    }
+}
+
+#[test]
+fn requests_within_the_offered_rights_are_accepted() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = restricted_song(owner.id);
+
+    let license = ok(create_license_request(LicensePayload {
+        max_copies: Some(500),
+        ..license_payload(song.id, licensee.id)
+    }));
+
+    assert_eq!(license.attribution.as_deref(), Some("Courtesy of Olivia"));
+}
+
+#[test]
+fn requests_beyond_the_offered_rights_are_rejected() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = restricted_song(owner.id);
+    let within = || LicensePayload {
+        max_copies: Some(500),
+        ..license_payload(song.id, licensee.id)
+    };
+
+    let requests = [
+        LicensePayload {
+            license_type: LicenseType::Sample,
+            ..within()
+        },
+        LicensePayload {
+            media: vec![Media::Games],
+            ..within()
+        },
+        LicensePayload {
+            territories: vec!["FR".to_string()],
+            ..within()
+        },
+        LicensePayload {
+            max_copies: Some(5000),
+            ..within()
+        },
+        LicensePayload {
+            max_copies: None,
+            ..within()
+        },
+        LicensePayload {
+            exclusive: true,
+            ..within()
+        },
+    ];
+
+    for payload in requests {
+        assert_eq!(
+            rejected_field(err(create_license_request(payload))),
+            "rights"
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use super::*;
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+
+// Run a future that completes without waiting on a call
+fn now<T>(future: impl Future<Output = T>) -> T {
+    match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(value) => value,
+        Poll::Pending => panic!("future waited on a call"),
+    }
+}
+
+fn queued(license_id: u64) -> bool {
+    SIGNING_QUEUE.with(|q| q.borrow().contains_key(&license_id))
+}
+
+#[test]
+fn approved_licenses_are_queued_for_signing_once() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let license = request(song.id, licensee.id);
+    assert!(!queued(license.id));
+
+    let license = approve(license.id);
+    store_license(license.clone());
+
+    assert!(queued(license.id));
+    assert_eq!(SIGNING_QUEUE.with(|q| q.borrow().len()), 1);
+}
+
+#[test]
+fn signing_runs_drop_licenses_with_nothing_to_sign() {
+    setup();
+    SIGNING_QUEUE.with(|q| q.borrow_mut().insert(42, ()));
+
+    now(sign_queued_licenses());
+
+    assert!(!queued(42));
+    assert!(!SIGNING_RUN_ACTIVE.with(|active| *active.borrow()));
+}
+
+#[test]
+fn only_license_parties_can_request_a_signature() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let license = approve(request(song.id, licensee.id).id);
+
+    let result = now(request_license_signature(
+        "stranger".to_string(),
+        license.id,
+    ));
+
+    assert!(matches!(err(result), Error::Unauthorized { .. }));
+}
@@ -0,0 +1,112 @@
+use super::*;
+
+const BUYER_KEY: &str = "buyer-key";
+
+fn offer(song_id: u64, new_owner_id: u64, pending_requests: PendingRequestPolicy) -> Song {
+    ok(offer_song_transfer(SongTransferPayload {
+        auth_key: OWNER_KEY.to_string(),
+        song_id,
+        new_owner_id,
+        pending_requests,
+    }))
+}
+
+#[test]
+fn accepted_song_moves_with_its_licenses() {
+    setup();
+    let seller = owner("Olivia", OWNER_KEY);
+    let buyer = owner("Bruno", BUYER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(seller.id, "First Light");
+    let license = approve(request(song.id, licensee.id).id);
+    offer(song.id, buyer.id, PendingRequestPolicy::Transfer);
+
+    let song = ok(accept_song_transfer(BUYER_KEY.to_string(), song.id));
+
+    assert_eq!(song.owner_id, buyer.id);
+    assert!(song.pending_transfer.is_none());
+    let seller = _get_owner(&seller.id).unwrap();
+    let buyer = _get_owner(&buyer.id).unwrap();
+    assert!(owner_song_ids(&seller).is_empty() && owner_license_ids(&seller).is_empty());
+    assert_eq!(owner_song_ids(&buyer), vec![song.id]);
+    assert_eq!(owner_license_ids(&buyer), vec![license.id]);
+    let license = _get_license(&license.id).unwrap();
+    assert!(license.approved);
+    assert_eq!(license.owner_id, buyer.id);
+}
+
+#[test]
+fn transfer_clears_auto_approval() {
+    setup();
+    let seller = owner("Olivia", OWNER_KEY);
+    let buyer = owner("Bruno", BUYER_KEY);
+    let song = song(seller.id, "First Light");
+    ok(set_auto_approval(AutoApprovalPayload {
+        auth_key: OWNER_KEY.to_string(),
+        song_id: song.id,
+        rules: Some(AutoApprovalRules {
+            licensee_ids: Vec::new(),
+            min_price: None,
+            allow_exclusive: false,
+            max_duration_days: None,
+        }),
+    }));
+    offer(song.id, buyer.id, PendingRequestPolicy::Transfer);
+
+    let song = ok(accept_song_transfer(BUYER_KEY.to_string(), song.id));
+
+    assert!(song.auto_approval.is_none());
+}
+
+#[test]
+fn pending_requests_follow_the_chosen_policy() {
+    setup();
+    let seller = owner("Olivia", OWNER_KEY);
+    let buyer = owner("Bruno", BUYER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let kept = song(seller.id, "First Light");
+    let dropped = song(seller.id, "Last Light");
+    let kept_request = request(kept.id, licensee.id);
+    let dropped_request = request(dropped.id, licensee.id);
+    offer(kept.id, buyer.id, PendingRequestPolicy::Transfer);
+    offer(dropped.id, buyer.id, PendingRequestPolicy::Cancel);
+
+    ok(accept_song_transfer(BUYER_KEY.to_string(), kept.id));
+    ok(accept_song_transfer(BUYER_KEY.to_string(), dropped.id));
+
+    assert_eq!(_get_license(&kept_request.id).unwrap().owner_id, buyer.id);
+    assert!(_get_license(&dropped_request.id).is_none());
+}
+
+#[test]
+fn only_the_new_owner_can_accept() {
+    setup();
+    let seller = owner("Olivia", OWNER_KEY);
+    let buyer = owner("Bruno", BUYER_KEY);
+    let song = song(seller.id, "First Light");
+    offer(song.id, buyer.id, PendingRequestPolicy::Transfer);
+
+    let result = accept_song_transfer(OWNER_KEY.to_string(), song.id);
+
+    assert!(matches!(err(result), Error::Unauthorized { .. }));
+    assert_eq!(_get_song(&song.id).unwrap().owner_id, seller.id);
+    assert_eq!(
+        owner_song_ids(&_get_owner(&seller.id).unwrap()),
+        vec![song.id]
+    );
+}
+
+#[test]
+fn cancelled_transfer_cannot_be_accepted() {
+    setup();
+    let seller = owner("Olivia", OWNER_KEY);
+    let buyer = owner("Bruno", BUYER_KEY);
+    let song = song(seller.id, "First Light");
+    offer(song.id, buyer.id, PendingRequestPolicy::Transfer);
+
+    ok(cancel_song_transfer(BUYER_KEY.to_string(), song.id));
+    let result = accept_song_transfer(BUYER_KEY.to_string(), song.id);
+
+    assert!(matches!(err(result), Error::NotFound { .. }));
+    assert_eq!(_get_song(&song.id).unwrap().owner_id, seller.id);
+}
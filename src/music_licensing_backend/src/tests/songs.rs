@@ -0,0 +1,40 @@
+use super::*;
+
+#[test]
+fn songs_are_created_with_the_owner_key() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+
+    let result = create_song(SongPayload {
+        auth_key: "other-key".to_string(),
+        ..song_payload(owner.id, "First Light")
+    });
+
+    assert!(matches!(err(result), Error::Unauthorized { .. }));
+    assert!(owner_song_ids(&_get_owner(&owner.id).unwrap()).is_empty());
+}
+
+#[test]
+fn songs_need_an_existing_owner() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+
+    let result = create_song(song_payload(owner.id + 1, "First Light"));
+
+    assert!(matches!(err(result), Error::NotFound { msg } if msg.contains("owner")));
+}
+
+#[test]
+fn rejected_songs_use_no_id() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+
+    let rejected = create_song(SongPayload {
+        year: 1000,
+        ..song_payload(owner.id, "First Light")
+    });
+    let song = song(owner.id, "First Light");
+
+    assert!(matches!(err(rejected), Error::ValidationFailed { field, .. } if field == "year"));
+    assert_eq!(song.id, owner.id + 1);
+}
@@ -0,0 +1,158 @@
+use super::*;
+
+const BUYER_KEY: &str = "buyer-key";
+
+fn principal(byte: u8) -> Principal {
+    Principal::from_slice(&[byte; 29])
+}
+
+struct Minted {
+    buyer: Licensee,
+    license: License,
+}
+
+// An active license of Liam (principal 1) with its token minted; Bea (principal 2) can
+// receive it
+fn minted(transferable: bool) -> Minted {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let buyer = licensee("Bea", BUYER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    runtime::set_caller(principal(1));
+    ok(set_licensee_principal(
+        LICENSEE_KEY.to_string(),
+        licensee.id,
+    ));
+    runtime::set_caller(principal(2));
+    ok(set_licensee_principal(BUYER_KEY.to_string(), buyer.id));
+    let song = song(owner.id, "First Light");
+    let license = approve(request(song.id, licensee.id).id);
+    ok(set_license_transferable(TransferablePayload {
+        auth_key: OWNER_KEY.to_string(),
+        license_id: license.id,
+        transferable,
+    }));
+    ok(mint_license_token(LICENSEE_KEY.to_string(), license.id));
+    runtime::set_caller(principal(1));
+    Minted { buyer, license }
+}
+
+fn transfer(license_id: u64, from_subaccount: Option<Vec<u8>>) -> icrc7::TransferResult {
+    let arg = icrc7::TransferArg {
+        from_subaccount: from_subaccount.map(ByteBuf::from),
+        to: icrc7::Account {
+            owner: principal(2),
+            subaccount: None,
+        },
+        token_id: Nat::from(license_id),
+        memo: None,
+        created_at_time: None,
+    };
+    icrc7_transfer(vec![arg]).remove(0).unwrap()
+}
+
+fn account(byte: u8, subaccount: Option<Vec<u8>>) -> icrc7::Account {
+    icrc7::Account {
+        owner: principal(byte),
+        subaccount: subaccount.map(ByteBuf::from),
+    }
+}
+
+#[test]
+fn minted_tokens_are_held_by_the_licensee() {
+    let Minted { license, .. } = minted(false);
+
+    assert_eq!(
+        icrc7_balance_of(vec![account(1, None)]),
+        vec![Nat::from(1u64)]
+    );
+    assert_eq!(
+        icrc7_tokens_of(account(1, None), None, None),
+        vec![Nat::from(license.id)]
+    );
+}
+
+#[test]
+fn a_license_is_minted_once() {
+    let Minted { license, .. } = minted(false);
+
+    let result = mint_license_token(LICENSEE_KEY.to_string(), license.id);
+
+    assert!(matches!(err(result), Error::Conflict { .. }));
+}
+
+#[test]
+fn transfer_moves_the_license_to_the_recipient() {
+    let Minted { buyer, license } = minted(true);
+
+    let result = transfer(license.id, None);
+
+    assert!(matches!(result, icrc7::TransferResult::Ok(_)));
+    assert_eq!(_get_license(&license.id).unwrap().licensee_id, buyer.id);
+    assert_eq!(
+        icrc7_balance_of(vec![account(2, None)]),
+        vec![Nat::from(1u64)]
+    );
+}
+
+#[test]
+fn transfer_needs_a_transferable_license() {
+    let Minted { license, .. } = minted(false);
+
+    let result = transfer(license.id, None);
+
+    assert!(matches!(
+        result,
+        icrc7::TransferResult::Err(icrc7::TransferError::GenericError { .. })
+    ));
+}
+
+#[test]
+fn malformed_from_subaccount_is_rejected() {
+    let Minted { license, .. } = minted(true);
+
+    let result = transfer(license.id, Some(vec![0; 5]));
+
+    assert!(matches!(
+        result,
+        icrc7::TransferResult::Err(icrc7::TransferError::GenericError { .. })
+    ));
+    assert_eq!(
+        icrc7_balance_of(vec![account(1, Some(vec![0; 5]))]),
+        vec![Nat::from(0u64)]
+    );
+    assert_eq!(
+        icrc7_balance_of(vec![account(1, None)]),
+        vec![Nat::from(1u64)]
+    );
+}
+
+#[test]
+fn only_the_holder_transfers() {
+    let Minted { license, .. } = minted(true);
+    runtime::set_caller(principal(2));
+
+    let result = transfer(license.id, None);
+
+    assert!(matches!(
+        result,
+        icrc7::TransferResult::Err(icrc7::TransferError::Unauthorized)
+    ));
+}
+
+#[test]
+fn burned_tokens_are_gone() {
+    let Minted { license, .. } = minted(false);
+
+    assert!(matches!(
+        err(burn_license_token(BUYER_KEY.to_string(), license.id)),
+        Error::Unauthorized { .. }
+    ));
+    ok(burn_license_token(LICENSEE_KEY.to_string(), license.id));
+
+    assert_eq!(
+        icrc7_balance_of(vec![account(1, None)]),
+        vec![Nat::from(0u64)]
+    );
+    assert!(_get_license(&license.id).unwrap().approved);
+}
@@ -0,0 +1,172 @@
+use super::*;
+
+struct Parties {
+    licensee: Licensee,
+    buyer: Licensee,
+    license: License,
+}
+
+fn licensed() -> Parties {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let buyer = licensee("Bea", "buyer-key");
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let license = approve(request(song.id, licensee.id).id);
+    Parties {
+        licensee,
+        buyer,
+        license,
+    }
+}
+
+fn request_transfer(license_id: u64, auth_key: &str, to: u64) -> Result<License, Error> {
+    request_license_transfer(TransferPayload {
+        auth_key: auth_key.to_string(),
+        license_id,
+        new_licensee_id: to,
+    })
+}
+
+fn owner_consents(license_id: u64) -> License {
+    ok(approve_license_transfer(ProtectedPayload {
+        auth_key: OWNER_KEY.to_string(),
+        license_id,
+    }))
+}
+
+#[test]
+fn approved_transfer_moves_the_license_and_records_custody() {
+    let Parties {
+        licensee,
+        buyer,
+        license,
+    } = licensed();
+
+    let pending = ok(request_transfer(license.id, LICENSEE_KEY, buyer.id));
+    assert_eq!(pending.pending_transfer_to, Some(buyer.id));
+    assert_eq!(pending.licensee_id, licensee.id);
+
+    let moved = owner_consents(license.id);
+
+    assert_eq!(moved.licensee_id, buyer.id);
+    assert_eq!(moved.pending_transfer_to, None);
+    assert!(ok(get_licensee(buyer.id)).licenses.contains(&license.id));
+    assert!(!ok(get_licensee(licensee.id)).licenses.contains(&license.id));
+
+    let custody = ok(get_license_custody(license.id, None, None));
+    assert_eq!(custody.len(), 1);
+    assert_eq!(custody[0].seq, 0);
+    assert_eq!(custody[0].from_licensee_id, licensee.id);
+    assert_eq!(custody[0].to_licensee_id, buyer.id);
+}
+
+#[test]
+fn transfers_close_the_message_thread() {
+    let Parties { buyer, license, .. } = licensed();
+    ok(send_license_message(MessagePayload {
+        auth_key: LICENSEE_KEY.to_string(),
+        license_id: license.id,
+        body: "Our budget is small".to_string(),
+    }));
+
+    ok(request_transfer(license.id, LICENSEE_KEY, buyer.id));
+    owner_consents(license.id);
+
+    let thread = ok(get_license_messages(
+        "buyer-key".to_string(),
+        license.id,
+        None,
+        None,
+    ));
+    assert!(thread.is_empty());
+    assert_eq!(
+        unread_count(MessageParty::Owner, license.owner_id, license.id),
+        0
+    );
+}
+
+#[test]
+fn rejected_transfer_leaves_the_license() {
+    let Parties {
+        licensee,
+        buyer,
+        license,
+    } = licensed();
+    ok(request_transfer(license.id, LICENSEE_KEY, buyer.id));
+
+    let kept = ok(reject_license_transfer(ProtectedPayload {
+        auth_key: OWNER_KEY.to_string(),
+        license_id: license.id,
+    }));
+
+    assert_eq!(kept.licensee_id, licensee.id);
+    assert_eq!(kept.pending_transfer_to, None);
+    assert!(ok(get_license_custody(license.id, None, None)).is_empty());
+}
+
+#[test]
+fn only_the_licensee_requests_a_transfer() {
+    let Parties { buyer, license, .. } = licensed();
+
+    assert!(matches!(
+        err(request_transfer(license.id, "buyer-key", buyer.id)),
+        Error::Unauthorized { .. }
+    ));
+}
+
+#[test]
+fn custody_history_is_paged() {
+    let Parties {
+        licensee,
+        buyer,
+        license,
+    } = licensed();
+    let keys = [(licensee.id, LICENSEE_KEY), (buyer.id, "buyer-key")];
+
+    for i in 0..5 {
+        let (_, from_key) = keys[i % 2];
+        let (to, _) = keys[(i + 1) % 2];
+        ok(request_transfer(license.id, from_key, to));
+        owner_consents(license.id);
+    }
+
+    let first = ok(get_license_custody(license.id, None, Some(2)));
+    let seqs: Vec<u64> = first.iter().map(|t| t.seq).collect();
+    assert_eq!(seqs, vec![0, 1]);
+
+    let rest = ok(get_license_custody(license.id, Some(1), None));
+    let seqs: Vec<u64> = rest.iter().map(|t| t.seq).collect();
+    assert_eq!(seqs, vec![2, 3, 4]);
+}
+
+#[test]
+fn sublicenses_stay_within_their_parent() {
+    let Parties { buyer, license, .. } = licensed();
+    let sublicense = |end_date: &str, territories: Vec<String>| {
+        create_sublicense(SublicensePayload {
+            auth_key: LICENSEE_KEY.to_string(),
+            parent_license_id: license.id,
+            sublicensee_id: buyer.id,
+            start_date: "2024-02-01".to_string(),
+            end_date: end_date.to_string(),
+            media: vec![Media::Film],
+            territories,
+            max_copies: None,
+            max_streams: None,
+            price: 10,
+        })
+    };
+
+    let child = ok(sublicense("2024-06-30", vec!["US".to_string()]));
+    assert_eq!(child.parent_license_id, Some(license.id));
+
+    assert!(matches!(
+        err(sublicense("2025-06-30", vec!["US".to_string()])),
+        Error::ValidationFailed { .. }
+    ));
+    assert!(matches!(
+        err(sublicense("2024-06-30", vec!["FR".to_string()])),
+        Error::ValidationFailed { .. }
+    ));
+}
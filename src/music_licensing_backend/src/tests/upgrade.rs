@@ -0,0 +1,289 @@
+use super::*;
+use crate::legacy::RawRecord;
+use candid::CandidType;
+
+// Records as the first release of the canister stored them
+#[derive(CandidType)]
+struct FirstSong {
+    id: u64,
+    title: String,
+    artist: String,
+    owner_id: u64,
+    year: u32,
+    genre: String,
+    price: u32,
+}
+
+#[derive(CandidType)]
+struct FirstLicense {
+    id: u64,
+    song_id: u64,
+    owner_id: u64,
+    licensee_id: u64,
+    approved: bool,
+    price: u32,
+    start_date: String,
+    end_date: String,
+}
+
+#[derive(CandidType)]
+struct FirstLicensee {
+    id: u64,
+    name: String,
+    email: String,
+    licenses: Vec<u64>,
+}
+
+// A license as stored once exclusivity, media and territories were added
+#[derive(CandidType)]
+struct ScopedLicense {
+    id: u64,
+    song_id: u64,
+    owner_id: u64,
+    licensee_id: u64,
+    approved: bool,
+    price: u32,
+    start_date: String,
+    end_date: String,
+    exclusive: bool,
+    media: Vec<Media>,
+    territories: Vec<String>,
+}
+
+// A transferred license as stored while it kept its chain of custody inline
+#[derive(CandidType)]
+struct TransferredLicense {
+    id: u64,
+    song_id: u64,
+    owner_id: u64,
+    licensee_id: u64,
+    approved: bool,
+    price: u32,
+    start_date: String,
+    end_date: String,
+    custody: Vec<InlineCustodyTransfer>,
+}
+
+#[derive(CandidType)]
+struct InlineCustodyTransfer {
+    from_licensee_id: u64,
+    to_licensee_id: u64,
+    timestamp: u64,
+}
+
+fn write_records<const N: u32>(memory_id: MemoryId, records: Vec<(u64, Vec<u8>)>) {
+    let memory = MEMORY_MANAGER.with(|m| m.borrow().get(memory_id));
+    let mut map: StableBTreeMap<u64, RawRecord<N>, Memory> = StableBTreeMap::init(memory);
+    for (id, bytes) in records {
+        map.insert(id, RawRecord(bytes));
+    }
+}
+
+fn store_first_release() {
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = FirstSong {
+        id: 10,
+        title: "Old Song".to_string(),
+        artist: "Old Band".to_string(),
+        owner_id: owner.id,
+        year: 1999,
+        genre: "rock".to_string(),
+        price: 50,
+    };
+    let license = FirstLicense {
+        id: 11,
+        song_id: 10,
+        owner_id: owner.id,
+        licensee_id: 12,
+        approved: true,
+        price: 50,
+        start_date: "2023-01-01".to_string(),
+        end_date: "2025-01-01".to_string(),
+    };
+    let licensee = FirstLicensee {
+        id: 12,
+        name: "Old Licensee".to_string(),
+        email: "old@example.com".to_string(),
+        licenses: vec![11],
+    };
+
+    write_records::<1024>(LEGACY_SONG_MEMORY, vec![(10, Encode!(&song).unwrap())]);
+    write_records::<1024>(
+        LEGACY_LICENSE_MEMORY,
+        vec![(11, Encode!(&license).unwrap())],
+    );
+    write_records::<1024>(MemoryId::new(4), vec![(12, Encode!(&licensee).unwrap())]);
+    ID_COUNTER.with(|c| c.borrow_mut().set(13)).unwrap();
+}
+
+#[test]
+fn upgrade_migrates_first_release_records() {
+    store_first_release();
+
+    post_upgrade();
+
+    let song = ok(get_song(10));
+    assert_eq!(song.title, "Old Song");
+    assert_eq!(song.genre, "Rock");
+    assert!(song.genre_id.is_some());
+    assert!(song.rights.license_types.is_empty());
+    assert!(song.contributors.is_empty());
+
+    let license = ok(get_license(11));
+    assert!(license.approved);
+    assert!(!license.exclusive);
+    assert!(license.media.is_empty());
+    assert_eq!(license.license_type, LicenseType::Sync);
+    assert!(ok(get_license_custody(11, None, None)).is_empty());
+    assert_eq!(license.counter_price, None);
+    assert_eq!(license_end_key(&license), Some((20089, 11)));
+    assert!(LICENSE_END_INDEX.with(|i| i.borrow().contains_key(&(20089, 11))));
+
+    assert_eq!(ok(get_licensee(12)).licenses, vec![11]);
+}
+
+#[test]
+fn upgrade_is_repeatable() {
+    store_first_release();
+
+    post_upgrade();
+    post_upgrade();
+
+    assert_eq!(ok(get_song(10)).title, "Old Song");
+    assert_eq!(ok(get_license(11)).song_id, 10);
+}
+
+#[test]
+fn upgrade_migrates_records_from_larger_maps() {
+    let license = ScopedLicense {
+        id: 1,
+        song_id: 0,
+        owner_id: 0,
+        licensee_id: 2,
+        approved: false,
+        price: 10,
+        start_date: "2024-01-01".to_string(),
+        end_date: "2024-06-30".to_string(),
+        exclusive: true,
+        media: vec![Media::Tv],
+        territories: vec!["DE".to_string()],
+    };
+    write_records::<2048>(LEGACY_LICENSE_MEMORY, vec![(1, Encode!(&license).unwrap())]);
+
+    post_upgrade();
+
+    let license = ok(get_license(1));
+    assert!(license.exclusive);
+    assert_eq!(license.media, vec![Media::Tv]);
+    assert_eq!(license.territories, vec!["DE".to_string()]);
+    assert!(!license.transferable);
+}
+
+#[test]
+fn licensees_without_an_auth_key_are_locked_until_one_is_set() {
+    store_first_release();
+    post_upgrade();
+
+    assert!(matches!(
+        err(get_notifications(NotificationQuery {
+            party: MessageParty::Licensee,
+            id: 12,
+            auth_key: String::new(),
+            prev: None,
+            take: None,
+            unread_only: false,
+        })),
+        Error::Unauthorized { .. }
+    ));
+    assert!(matches!(
+        err(set_licensee_auth_key(12, "new-key".to_string())),
+        Error::Forbidden { .. }
+    ));
+
+    let controller = Principal::from_slice(&[1; 29]);
+    runtime::add_controller(controller);
+    runtime::set_caller(controller);
+    ok(set_licensee_auth_key(12, "new-key".to_string()));
+
+    ok(get_notifications(NotificationQuery {
+        party: MessageParty::Licensee,
+        id: 12,
+        auth_key: "new-key".to_string(),
+        prev: None,
+        take: None,
+        unread_only: false,
+    }));
+}
+
+#[test]
+fn upgrade_moves_inline_custody_to_its_own_map() {
+    let license = TransferredLicense {
+        id: 1,
+        song_id: 0,
+        owner_id: 0,
+        licensee_id: 4,
+        approved: true,
+        price: 10,
+        start_date: "2024-01-01".to_string(),
+        end_date: "2024-06-30".to_string(),
+        custody: vec![
+            InlineCustodyTransfer {
+                from_licensee_id: 2,
+                to_licensee_id: 3,
+                timestamp: 1,
+            },
+            InlineCustodyTransfer {
+                from_licensee_id: 3,
+                to_licensee_id: 4,
+                timestamp: 2,
+            },
+        ],
+    };
+    write_records::<4096>(LEGACY_LICENSE_MEMORY, vec![(1, Encode!(&license).unwrap())]);
+
+    post_upgrade();
+
+    let custody = ok(get_license_custody(1, None, None));
+    let chain: Vec<(u64, u64, u64)> = custody
+        .iter()
+        .map(|t| (t.seq, t.from_licensee_id, t.to_licensee_id))
+        .collect();
+    assert_eq!(chain, vec![(0, 2, 3), (1, 3, 4)]);
+    assert_eq!(ok(get_license(1)).licensee_id, 4);
+}
+
+// Files as stored when they could only be uploaded for songs
+#[derive(CandidType, Deserialize)]
+struct SongAsset {
+    id: u64,
+    song_id: u64,
+    kind: AssetKind,
+    file_name: String,
+    content_type: String,
+    size: u64,
+    sha256: String,
+    chunk_size: u32,
+    chunk_count: u32,
+    uploaded_at: u64,
+}
+
+#[test]
+fn assets_stored_for_songs_stay_readable() {
+    let stored = SongAsset {
+        id: 9,
+        song_id: 4,
+        kind: AssetKind::Master,
+        file_name: "master.wav".to_string(),
+        content_type: "audio/wav".to_string(),
+        size: 10,
+        sha256: String::new(),
+        chunk_size: 10,
+        chunk_count: 1,
+        uploaded_at: 1,
+    };
+
+    let asset = Asset::from_bytes(Cow::Owned(Encode!(&stored).unwrap()));
+
+    assert_eq!(asset.entity_id, 4);
+    assert!(Decode!(&asset.to_bytes(), SongAsset).is_ok_and(|a| a.song_id == 4));
+}
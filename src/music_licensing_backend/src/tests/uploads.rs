@@ -0,0 +1,175 @@
+use super::*;
+
+// Three chunks, the last one short
+fn master() -> Vec<u8> {
+    (0..2 * MAX_CHUNK_SIZE as usize + 10)
+        .map(|i| (i % 251) as u8)
+        .collect()
+}
+
+fn download(licensee_id: u64, song_id: u64, index: u32) -> Result<ByteBuf, Error> {
+    download_chunk(DownloadChunkPayload {
+        licensee_id,
+        auth_key: LICENSEE_KEY.to_string(),
+        song_id,
+        kind: AssetKind::Master,
+        index,
+    })
+}
+
+#[test]
+fn chunks_arrive_in_any_order() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+    let data = master();
+    let session = ok(start(song.id, AssetKind::Master, "audio/wav", &data));
+
+    for index in [2, 0, 1] {
+        ok(send_chunk(&session, index, &data));
+    }
+    let asset = ok(finish_upload(OWNER_KEY.to_string(), session.id));
+
+    assert_eq!(asset.chunk_count, 3);
+    assert_eq!(asset.sha256, sha256(&data));
+    let file: Vec<u8> = (0..3)
+        .flat_map(|index| {
+            ok(download_own_chunk(
+                OWNER_KEY.to_string(),
+                song.id,
+                AssetKind::Master,
+                index,
+            ))
+            .into_vec()
+        })
+        .collect();
+    assert_eq!(file, data);
+}
+
+#[test]
+fn interrupted_uploads_resume() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+    let data = master();
+    let session = ok(start(song.id, AssetKind::Master, "audio/wav", &data));
+    ok(send_chunk(&session, 0, &data));
+    ok(send_chunk(&session, 2, &data));
+
+    let session = ok(get_upload_session(OWNER_KEY.to_string(), session.id));
+    let result = finish_upload(OWNER_KEY.to_string(), session.id);
+
+    assert_eq!(session.received, vec![true, false, true]);
+    assert!(matches!(err(result), Error::Conflict { msg, .. } if msg.ends_with("chunks:1")));
+    ok(send_chunk(&session, 1, &data));
+    ok(finish_upload(OWNER_KEY.to_string(), session.id));
+}
+
+#[test]
+fn chunks_must_have_their_size() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+    let data = master();
+    let session = ok(start(song.id, AssetKind::Master, "audio/wav", &data));
+
+    let result = upload_chunk(UploadChunkPayload {
+        auth_key: OWNER_KEY.to_string(),
+        session_id: session.id,
+        index: 2,
+        data: ByteBuf::from(data[..11].to_vec()),
+        sha256: sha256(&data[..11]),
+    });
+
+    assert!(matches!(err(result), Error::ValidationFailed { field, .. } if field == "data"));
+}
+
+#[test]
+fn uploads_must_match_their_hash() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+    let data = master();
+    let session = ok(start(song.id, AssetKind::Master, "audio/wav", &data));
+    let mut sent = data.clone();
+    sent[0] ^= 1;
+    for index in 0..3 {
+        ok(send_chunk(&session, index, &sent));
+    }
+
+    let result = finish_upload(OWNER_KEY.to_string(), session.id);
+
+    assert!(matches!(err(result), Error::ValidationFailed { field, .. } if field == "sha256"));
+    assert!(_get_asset(song.id, AssetKind::Master).is_none());
+    assert!(matches!(
+        err(get_upload_session(OWNER_KEY.to_string(), session.id)),
+        Error::NotFound { .. }
+    ));
+}
+
+#[test]
+fn unfinished_uploads_expire() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let song = song(owner.id, "First Light");
+    let data = master();
+    let stale = ok(start(song.id, AssetKind::Master, "audio/wav", &data));
+    ok(send_chunk(&stale, 0, &data));
+    runtime::advance_time(UPLOAD_SESSION_TTL_NS);
+    let fresh = ok(start(song.id, AssetKind::Master, "audio/wav", &data));
+    assert!(ok(get_upload_session(OWNER_KEY.to_string(), stale.id)).received[0]);
+
+    runtime::advance_time(1);
+    ok(start(song.id, AssetKind::Master, "audio/wav", &data));
+
+    assert!(matches!(
+        err(get_upload_session(OWNER_KEY.to_string(), stale.id)),
+        Error::NotFound { .. }
+    ));
+    assert!(CHUNK_STORAGE.with(|s| s.borrow().get(&(stale.id, 0)).is_none()));
+    ok(get_upload_session(OWNER_KEY.to_string(), fresh.id));
+}
+
+#[test]
+fn downloads_need_an_active_license() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    let data = master();
+    ok(upload(song.id, AssetKind::Master, "audio/wav", &data));
+    let license = request(song.id, licensee.id);
+
+    let pending = download(licensee.id, song.id, 0);
+    approve(license.id);
+    let approved = download(licensee.id, song.id, 0);
+    ok(revoke_license(ProtectedPayload {
+        auth_key: OWNER_KEY.to_string(),
+        license_id: license.id,
+    }));
+    let revoked = download(licensee.id, song.id, 0);
+
+    assert!(matches!(err(pending), Error::Forbidden { .. }));
+    assert_eq!(
+        ok(approved).into_vec(),
+        data[..MAX_CHUNK_SIZE as usize].to_vec()
+    );
+    assert!(matches!(err(revoked), Error::Forbidden { .. }));
+}
+
+#[test]
+fn expired_licenses_do_not_download() {
+    setup();
+    let owner = owner("Olivia", OWNER_KEY);
+    let licensee = licensee("Liam", LICENSEE_KEY);
+    let song = song(owner.id, "First Light");
+    ok(upload(song.id, AssetKind::Master, "audio/wav", &master()));
+    approve(request(song.id, licensee.id).id);
+
+    runtime::advance_time(366 * 86_400_000_000_000);
+
+    assert!(matches!(
+        err(download(licensee.id, song.id, 0)),
+        Error::Forbidden { .. }
+    ));
+}
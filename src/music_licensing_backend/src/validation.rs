@@ -0,0 +1,261 @@
+// Validation and normalization of payload fields (names, titles, emails, years, tags and
+// musical attributes)
+use unicode_normalization::UnicodeNormalization;
+
+// Lengths are counted in characters after normalization
+pub const MAX_TITLE_LENGTH: usize = 200;
+pub const MAX_NAME_LENGTH: usize = 100;
+pub const MAX_EMAIL_LENGTH: usize = 254;
+pub const MAX_AUTH_KEY_LENGTH: usize = 128;
+pub const MAX_ATTRIBUTION_LENGTH: usize = 500;
+pub const MAX_TAG_LENGTH: usize = 40;
+pub const MAX_TAGS: usize = 20;
+pub const MAX_MESSAGE_LENGTH: usize = 2000;
+
+// Earliest year a recording can be from
+pub const MIN_YEAR: u32 = 1860;
+
+// Accepted ranges of a song's length in seconds and tempo in beats per minute
+pub const MAX_DURATION_SECS: u32 = 86_400;
+pub const MIN_BPM: u32 = 20;
+pub const MAX_BPM: u32 = 400;
+
+// Top-level genres the taxonomy starts with; controllers refine it with create_genre
+pub const DEFAULT_GENRES: [&str; 26] = [
+    "Alternative",
+    "Ambient",
+    "Blues",
+    "Children's",
+    "Classical",
+    "Country",
+    "Dance",
+    "Electronic",
+    "Experimental",
+    "Folk",
+    "Funk",
+    "Gospel",
+    "Hip-Hop",
+    "Indie",
+    "Jazz",
+    "Latin",
+    "Metal",
+    "Pop",
+    "Punk",
+    "R&B",
+    "Reggae",
+    "Rock",
+    "Soul",
+    "Soundtrack",
+    "Spoken Word",
+    "World",
+];
+
+// NFC form with surrounding whitespace removed and inner whitespace runs (including line
+// breaks and tabs) collapsed to a single space, so equal-looking text is stored identically
+pub fn normalize_text(value: &str) -> String {
+    value
+        .nfc()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+// Required single-line text of at most `max_length` characters
+pub fn text(field: &str, value: &str, max_length: usize) -> Result<String, String> {
+    let text = normalize_text(value);
+    if text.is_empty() {
+        return Err(format!("{} is required", field));
+    }
+    if text.chars().any(char::is_control) {
+        return Err(format!("{} must not contain control characters", field));
+    }
+
+    match text.chars().count() {
+        length if length > max_length => Err(format!(
+            "{} has {} characters, at most {} are allowed",
+            field, length, max_length
+        )),
+        _ => Ok(text),
+    }
+}
+
+// Optional text; blank values are treated as missing
+pub fn optional_text(
+    field: &str,
+    value: Option<&str>,
+    max_length: usize,
+) -> Result<Option<String>, String> {
+    match value.map(normalize_text).filter(|value| !value.is_empty()) {
+        Some(value) => text(field, &value, max_length).map(Some),
+        None => Ok(None),
+    }
+}
+
+// Addresses of the form local@domain.tld, stored with a lower case domain
+pub fn email(value: &str) -> Result<String, String> {
+    let email = value.trim().nfc().collect::<String>();
+    let invalid = || format!("email:{} is invalid, expected name@example.com", value);
+
+    if email.chars().count() > MAX_EMAIL_LENGTH {
+        return Err(format!(
+            "email is longer than {} characters",
+            MAX_EMAIL_LENGTH
+        ));
+    }
+
+    let (local, domain) = email.rsplit_once('@').ok_or_else(invalid)?;
+    let local_valid = !local.is_empty()
+        && local.len() <= 64
+        && !local.starts_with('.')
+        && !local.ends_with('.')
+        && !local.contains("..")
+        && local
+            .chars()
+            .all(|c| c.is_alphanumeric() || "!#$%&'*+-/=?^_`{|}~.".contains(c));
+
+    let labels: Vec<&str> = domain.split('.').collect();
+    let domain_valid = labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        })
+        && labels
+            .last()
+            .is_some_and(|tld| tld.chars().all(char::is_alphabetic) && tld.len() >= 2);
+
+    match local_valid && domain_valid {
+        true => Ok(format!("{}@{}", local, domain.to_lowercase())),
+        false => Err(invalid()),
+    }
+}
+
+// Years from the first recordings up to next year, for announced releases
+pub fn year(value: u32, current_year: u32) -> Result<u32, String> {
+    match value {
+        year if (MIN_YEAR..=current_year + 1).contains(&year) => Ok(year),
+        _ => Err(format!(
+            "year:{} is invalid, expected {} to {}",
+            value,
+            MIN_YEAR,
+            current_year + 1
+        )),
+    }
+}
+
+pub fn duration_secs(value: u32) -> Result<u32, String> {
+    match value {
+        secs if (1..=MAX_DURATION_SECS).contains(&secs) => Ok(secs),
+        _ => Err(format!(
+            "duration:{}s is invalid, expected 1 to {} seconds",
+            value, MAX_DURATION_SECS
+        )),
+    }
+}
+
+pub fn bpm(value: u32) -> Result<u32, String> {
+    match value {
+        bpm if (MIN_BPM..=MAX_BPM).contains(&bpm) => Ok(bpm),
+        _ => Err(format!(
+            "bpm:{} is invalid, expected {} to {}",
+            value, MIN_BPM, MAX_BPM
+        )),
+    }
+}
+
+// Two-letter ISO 639-1 language codes, stored in lower case
+pub fn language(value: &str) -> Result<String, String> {
+    let code = value.trim().to_lowercase();
+    match code.len() == 2 && code.chars().all(|c| c.is_ascii_lowercase()) {
+        true => Ok(code),
+        false => Err(format!(
+            "language:{} is invalid, expected a two-letter ISO 639-1 code such as en",
+            value
+        )),
+    }
+}
+
+// Genre names are compared by their letters and digits only, so "Hip Hop", "hip-hop" and
+// "HipHop" name the same genre
+pub fn genre_key(value: &str) -> String {
+    normalize_text(value)
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// Free-form tags are stored in lower case
+pub fn tag(value: &str) -> Result<String, String> {
+    text("tag", value, MAX_TAG_LENGTH).map(|tag| tag.to_lowercase())
+}
+
+// Tags of a song, deduplicated in the order given
+pub fn tags(values: &[String]) -> Result<Vec<String>, String> {
+    let mut tags: Vec<String> = Vec::new();
+    for value in values {
+        let tag = tag(value)?;
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+
+    match tags.len() {
+        n if n > MAX_TAGS => Err(format!(
+            "song has {} tags, at most {} are allowed",
+            n, MAX_TAGS
+        )),
+        _ => Ok(tags),
+    }
+}
+
+// Message bodies keep their line breaks; each line is normalized like other text and runs of
+// blank lines are reduced to one
+pub fn message(value: &str) -> Result<String, String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in value.lines().map(normalize_text) {
+        if !line.is_empty() || lines.last().is_some_and(|last| !last.is_empty()) {
+            lines.push(line);
+        }
+    }
+    while lines.last().is_some_and(|last| last.is_empty()) {
+        lines.pop();
+    }
+    let body = lines.join("\n");
+
+    if body.is_empty() {
+        return Err("message is required".to_string());
+    }
+    if body.chars().any(|c| c.is_control() && c != '\n') {
+        return Err("message must not contain control characters".to_string());
+    }
+
+    match body.chars().count() {
+        length if length > MAX_MESSAGE_LENGTH => Err(format!(
+            "message has {} characters, at most {} are allowed",
+            length, MAX_MESSAGE_LENGTH
+        )),
+        _ => Ok(body),
+    }
+}
+
+// Auth keys are secrets compared byte for byte, so they are checked but never rewritten
+pub fn auth_key(value: &str) -> Result<String, String> {
+    if value.is_empty() {
+        return Err("auth key is required".to_string());
+    }
+    if value.chars().count() > MAX_AUTH_KEY_LENGTH {
+        return Err(format!(
+            "auth key is longer than {} characters",
+            MAX_AUTH_KEY_LENGTH
+        ));
+    }
+
+    match value.chars().any(|c| c.is_control() || c.is_whitespace()) {
+        true => Err("auth key must not contain whitespace or control characters".to_string()),
+        false => Ok(value.to_string()),
+    }
+}
@@ -0,0 +1,275 @@
+// Signs a license certificate with PocketIC's threshold ECDSA keys and checks the signature
+// offline. Needs the canister built for wasm32 and a PocketIC server:
+//
+//   cargo build --target wasm32-unknown-unknown --release -p music_licensing_backend
+//   POCKET_IC_BIN=/path/to/pocket-ic cargo test -p music_licensing_backend \
+//       --test threshold_signing -- --ignored
+
+use candid::types::reserved::{Empty, Reserved};
+use candid::{CandidType, Decode, Deserialize, Encode};
+use k256::ecdsa::signature::hazmat::PrehashVerifier;
+use k256::ecdsa::{Signature, VerifyingKey};
+use pocket_ic::{CanisterId, PocketIc, PocketIcBuilder};
+use std::time::Duration;
+
+const OWNER_KEY: &str = "owner-key";
+const LICENSEE_KEY: &str = "licensee-key";
+
+// The canister signs queued certificates every five minutes
+const SIGNING_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// Payloads with just the cases and types the calls below use; empty lists are `vec empty` and
+// absent options `opt empty`, which Candid accepts for any element type
+#[derive(CandidType)]
+struct PartyPayload {
+    name: String,
+    email: String,
+    auth_key: String,
+}
+
+#[derive(CandidType)]
+enum LicenseType {
+    Sync,
+}
+
+#[derive(CandidType)]
+enum Media {
+    Film,
+}
+
+#[derive(CandidType)]
+struct UsageRights {
+    license_types: Vec<Empty>,
+    media: Vec<Empty>,
+    territories: Vec<String>,
+    max_copies: Option<u64>,
+    max_streams: Option<u64>,
+    attribution: Option<String>,
+    exclusive_allowed: bool,
+}
+
+#[derive(CandidType)]
+struct SongPayload {
+    auth_key: String,
+    title: String,
+    artist: String,
+    owner_id: u64,
+    year: u32,
+    genre: String,
+    price: u32,
+    rights: UsageRights,
+    isrc: Option<String>,
+    iswc: Option<String>,
+    contributors: Vec<Empty>,
+    tags: Vec<String>,
+    moods: Vec<Empty>,
+    tempo: Option<Empty>,
+    instruments: Vec<Empty>,
+    duration_secs: Option<u32>,
+    bpm: Option<u32>,
+    key: Option<Empty>,
+    instrumental: Option<bool>,
+    explicit: Option<bool>,
+    language: Option<String>,
+}
+
+#[derive(CandidType)]
+struct LicensePayload {
+    auth_key: String,
+    song_id: u64,
+    licensee_id: u64,
+    start_date: String,
+    end_date: String,
+    exclusive: bool,
+    media: Vec<Media>,
+    territories: Vec<String>,
+    license_type: LicenseType,
+    max_copies: Option<u64>,
+    max_streams: Option<u64>,
+    price: u32,
+}
+
+#[derive(CandidType)]
+struct Approvepayload {
+    auth_key: String,
+    license_id: u64,
+    cost: u32,
+}
+
+#[derive(CandidType, Deserialize)]
+struct Created {
+    id: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+struct LicenseSignature {
+    document: String,
+    sha256: String,
+    signature: String,
+    public_key: String,
+}
+
+fn party(name: &str, auth_key: &str) -> PartyPayload {
+    PartyPayload {
+        name: name.to_string(),
+        email: format!("{}@example.com", name.to_lowercase()),
+        auth_key: auth_key.to_string(),
+    }
+}
+
+fn update<T: for<'de> Deserialize<'de> + CandidType>(
+    pic: &PocketIc,
+    canister: CanisterId,
+    sender: CanisterId,
+    method: &str,
+    arg: Vec<u8>,
+) -> Result<T, Reserved> {
+    let reply = pic
+        .update_call(canister, sender, method, arg)
+        .unwrap_or_else(|reject| panic!("{} was rejected: {:?}", method, reject));
+    Decode!(&reply, Result<T, Reserved>).unwrap()
+}
+
+fn ok<T>(method: &str, result: Result<T, Reserved>) -> T {
+    result.unwrap_or_else(|_| panic!("{} returned an error", method))
+}
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+#[test]
+#[ignore = "needs the canister wasm and a PocketIC server"]
+fn approved_license_certificates_are_signed() {
+    let wasm = std::fs::read(
+        env!("CARGO_MANIFEST_DIR").to_string()
+            + "/../../target/wasm32-unknown-unknown/release/music_licensing_backend.wasm",
+    )
+    .expect("build the canister for wasm32-unknown-unknown first");
+
+    // Threshold keys live on the II subnet
+    let pic = PocketIcBuilder::new()
+        .with_application_subnet()
+        .with_ii_subnet()
+        .build();
+    let controller = CanisterId::from_slice(&[1; 29]);
+    let anyone = CanisterId::anonymous();
+    let canister = pic.create_canister_with_settings(Some(controller), None);
+    pic.add_cycles(canister, 10_000_000_000_000);
+    pic.install_canister(canister, wasm, Encode!().unwrap(), Some(controller));
+
+    let arg = Encode!(&"dfx_test_key".to_string()).unwrap();
+    ok::<()>(
+        "set_signing_key",
+        update(&pic, canister, controller, "set_signing_key", arg),
+    );
+
+    let arg = Encode!(&party("Olivia", OWNER_KEY)).unwrap();
+    let owner: Created = ok(
+        "create_owner",
+        update(&pic, canister, anyone, "create_owner", arg),
+    );
+    let arg = Encode!(&party("Liam", LICENSEE_KEY)).unwrap();
+    let licensee: Created = ok(
+        "create_licensee",
+        update(&pic, canister, anyone, "create_licensee", arg),
+    );
+
+    let arg = Encode!(&SongPayload {
+        auth_key: OWNER_KEY.to_string(),
+        title: "First Light".to_string(),
+        artist: "The Testers".to_string(),
+        owner_id: owner.id,
+        year: 2020,
+        genre: "Rock".to_string(),
+        price: 100,
+        rights: UsageRights {
+            license_types: Vec::new(),
+            media: Vec::new(),
+            territories: Vec::new(),
+            max_copies: None,
+            max_streams: None,
+            attribution: None,
+            exclusive_allowed: false,
+        },
+        isrc: None,
+        iswc: None,
+        contributors: Vec::new(),
+        tags: Vec::new(),
+        moods: Vec::new(),
+        tempo: None,
+        instruments: Vec::new(),
+        duration_secs: None,
+        bpm: None,
+        key: None,
+        instrumental: None,
+        explicit: None,
+        language: None,
+    })
+    .unwrap();
+    let song: Created = ok(
+        "create_song",
+        update(&pic, canister, anyone, "create_song", arg),
+    );
+
+    let arg = Encode!(&LicensePayload {
+        auth_key: LICENSEE_KEY.to_string(),
+        song_id: song.id,
+        licensee_id: licensee.id,
+        start_date: "2024-01-01".to_string(),
+        end_date: "2034-12-31".to_string(),
+        exclusive: false,
+        media: vec![Media::Film],
+        territories: vec!["US".to_string()],
+        license_type: LicenseType::Sync,
+        max_copies: None,
+        max_streams: None,
+        price: 100,
+    })
+    .unwrap();
+    let license: Created = ok(
+        "create_license_request",
+        update(&pic, canister, anyone, "create_license_request", arg),
+    );
+    let arg = Encode!(&Approvepayload {
+        auth_key: OWNER_KEY.to_string(),
+        license_id: license.id,
+        cost: 100,
+    })
+    .unwrap();
+    ok::<Created>(
+        "approve_license",
+        update(&pic, canister, anyone, "approve_license", arg),
+    );
+
+    // Approval only queues the certificate; the next signing run signs it
+    let arg = Encode!(&license.id).unwrap();
+    let reply = pic
+        .query_call(canister, anyone, "get_license_signature", arg.clone())
+        .unwrap();
+    assert!(Decode!(&reply, Result<LicenseSignature, Reserved>)
+        .unwrap()
+        .is_err());
+
+    pic.advance_time(SIGNING_INTERVAL);
+    for _ in 0..20 {
+        pic.tick();
+    }
+
+    let reply = pic
+        .query_call(canister, anyone, "get_license_signature", arg)
+        .unwrap();
+    let signature = ok(
+        "get_license_signature",
+        Decode!(&reply, Result<LicenseSignature, Reserved>).unwrap(),
+    );
+
+    let key = VerifyingKey::from_sec1_bytes(&from_hex(&signature.public_key)).unwrap();
+    let sig = Signature::from_slice(&from_hex(&signature.signature)).unwrap();
+    let digest = from_hex(&signature.sha256);
+    assert!(signature.document.contains("First Light"));
+    key.verify_prehash(&digest, &sig).unwrap();
+}